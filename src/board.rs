@@ -1,10 +1,22 @@
 
-use colored::Colorize;
+use colored::{ ColoredString, Colorize };
 
 use crate::core::Square;
+use crate::core::theme::{ Padding, Theme };
 use crate::core;
 use crate::ply::{Colour, Piece, Ply};
 
+/// What a rendered board marks besides the pieces, each as a bitboard of squares
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Marks {
+    /// The from and to squares of the last move
+    pub last_move: u64,
+    /// Pieces the selected piece attacks
+    pub attacks: u64,
+    /// Empty squares the selected piece can move to
+    pub moves: u64,
+}
+
 #[derive(Debug, Default, Copy, Clone)]
 pub struct Board {
     pub w_p_bb: u64, // white pawn bitboard
@@ -47,8 +59,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_pawn_single_pushes(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_p_bb,
+            Colour::Black => self.b_p_bb,
         };
         self.pawn_single_push(sq, colour)
     }
@@ -56,16 +68,16 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_pawn_double_pushes(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_p_bb,
+            Colour::Black => self.b_p_bb,
         };
         self.pawn_double_push(sq, colour)
     }
     /// Gets bitboard of all occupied squares that are under threat from a given colour's pawns
     pub fn all_pawn_attacks(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_p_bb,
+            Colour::Black => self.b_p_bb,
         };
         self.pawn_attacks(sq, colour)
     }
@@ -92,8 +104,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_knight_moves(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_n_bb,
+            Colour::Black => self.b_n_bb,
         };
         self.knight_moves(sq)
     }
@@ -101,8 +113,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_knight_attacks(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_n_bb,
+            Colour::Black => self.b_n_bb,
         };
         self.knight_attacks(sq, colour)
     }
@@ -120,8 +132,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves
     pub fn all_king_moves(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_k_bb,
+            Colour::Black => self.b_k_bb,
         };
         self.king_moves(sq)
     }
@@ -129,8 +141,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves 
     pub fn all_king_attacks(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_k_bb,
+            Colour::Black => self.b_k_bb,
         };
         self.king_attacks(sq, colour)
     }
@@ -148,8 +160,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_bishop_moves(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_b_bb,
+            Colour::Black => self.b_b_bb,
         };
         self.bishop_moves(sq)
     }
@@ -157,8 +169,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_bishop_attacks(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_b_bb,
+            Colour::Black => self.b_b_bb,
         };
         self.bishop_attacks(sq, colour)
     }
@@ -176,8 +188,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_rook_moves(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_r_bb,
+            Colour::Black => self.b_r_bb,
         };
         self.rook_moves(sq)
 
@@ -186,8 +198,8 @@ impl Board {
     /// NOTE: Doesn't account for illegal moves such as when the piece is pinned
     pub fn all_rook_attacks(&self, colour: Colour) -> u64 {
         let sq = match colour {
-            Colour::White => self.w_q_bb,
-            Colour::Black => self.b_q_bb,
+            Colour::White => self.w_r_bb,
+            Colour::Black => self.b_r_bb,
         };
         self.rook_attacks(sq, colour)
     }
@@ -220,17 +232,17 @@ impl Board {
         self.queen_attacks(sq, colour)
     }
     pub fn queen_moves(&self, sq: u64) -> u64 {
-          core::rook_moves_bb(sq, self.unoccupied_bb())
-        | core::bish_moves_bb(sq, self.unoccupied_bb()) 
+        ( core::rook_moves_bb(sq, self.unoccupied_bb())
+        | core::bish_moves_bb(sq, self.unoccupied_bb()) )
         & self.unoccupied_bb()
     }
     pub fn queen_attacks(&self, sq: u64, colour: Colour) -> u64 {
         match colour {
-            Colour::White => core::rook_moves_bb(sq, self.unoccupied_bb())
-                           | core::bish_moves_bb(sq, self.unoccupied_bb()) 
+            Colour::White => ( core::rook_moves_bb(sq, self.unoccupied_bb())
+                             | core::bish_moves_bb(sq, self.unoccupied_bb()) )
                            & self.black_bb(),
-            Colour::Black => core::rook_moves_bb(sq, self.unoccupied_bb())
-                           | core::bish_moves_bb(sq, self.unoccupied_bb()) 
+            Colour::Black => ( core::rook_moves_bb(sq, self.unoccupied_bb())
+                             | core::bish_moves_bb(sq, self.unoccupied_bb()) )
                            & self.white_bb(),
         }
     }
@@ -284,6 +296,76 @@ impl Board {
         todo!()
     } 
 
+    /// Gets the bitboard of a given piece type and colour
+    pub fn pieces(&self, piece: Piece, colour: Colour) -> u64 {
+        match (piece, colour) {
+            (Piece::Pawn,   Colour::White) => self.w_p_bb,
+            (Piece::Rook,   Colour::White) => self.w_r_bb,
+            (Piece::Knight, Colour::White) => self.w_n_bb,
+            (Piece::Bishop, Colour::White) => self.w_b_bb,
+            (Piece::Queen,  Colour::White) => self.w_q_bb,
+            (Piece::King,   Colour::White) => self.w_k_bb,
+            (Piece::Pawn,   Colour::Black) => self.b_p_bb,
+            (Piece::Rook,   Colour::Black) => self.b_r_bb,
+            (Piece::Knight, Colour::Black) => self.b_n_bb,
+            (Piece::Bishop, Colour::Black) => self.b_b_bb,
+            (Piece::Queen,  Colour::Black) => self.b_q_bb,
+            (Piece::King,   Colour::Black) => self.b_k_bb,
+        }
+    }
+
+    fn pieces_mut(&mut self, piece: Piece, colour: Colour) -> &mut u64 {
+        match (piece, colour) {
+            (Piece::Pawn,   Colour::White) => &mut self.w_p_bb,
+            (Piece::Rook,   Colour::White) => &mut self.w_r_bb,
+            (Piece::Knight, Colour::White) => &mut self.w_n_bb,
+            (Piece::Bishop, Colour::White) => &mut self.w_b_bb,
+            (Piece::Queen,  Colour::White) => &mut self.w_q_bb,
+            (Piece::King,   Colour::White) => &mut self.w_k_bb,
+            (Piece::Pawn,   Colour::Black) => &mut self.b_p_bb,
+            (Piece::Rook,   Colour::Black) => &mut self.b_r_bb,
+            (Piece::Knight, Colour::Black) => &mut self.b_n_bb,
+            (Piece::Bishop, Colour::Black) => &mut self.b_b_bb,
+            (Piece::Queen,  Colour::Black) => &mut self.b_q_bb,
+            (Piece::King,   Colour::Black) => &mut self.b_k_bb,
+        }
+    }
+
+    /// Toggles a piece on the given square(s). Used to both place and remove pieces
+    pub fn toggle_piece(&mut self, piece: Piece, colour: Colour, sq: u64) {
+        *self.pieces_mut(piece, colour) ^= sq;
+    }
+
+    /// Gets bitboard of all squares occupied by a given colour
+    pub fn colour_bb(&self, colour: Colour) -> u64 {
+        match colour {
+            Colour::White => self.white_bb(),
+            Colour::Black => self.black_bb(),
+        }
+    }
+
+    /// Sees if a given square, occupied or not, is attacked by any piece of the given colour
+    /// NOTE: Doesn't include en-passant captures
+    pub fn square_is_attacked_by(&self, sq: u64, colour: Colour) -> bool {
+        let empties = self.unoccupied_bb();
+        let is_white = colour == Colour::White;
+        let diagonals = self.pieces(Piece::Bishop, colour) | self.pieces(Piece::Queen, colour);
+        let orthogonals = self.pieces(Piece::Rook, colour) | self.pieces(Piece::Queen, colour);
+
+        // Attacks are symmetric, so we look outwards from the square as if it were the attacking piece
+           core::pawn_attacks_bb(sq, !is_white) & self.pieces(Piece::Pawn, colour)   != 0
+        || core::knight_moves_bb(sq)            & self.pieces(Piece::Knight, colour) != 0
+        || core::king_moves_bb(sq)              & self.pieces(Piece::King, colour)   != 0
+        || core::bish_moves_bb(sq, empties)     & diagonals                          != 0
+        || core::rook_moves_bb(sq, empties)     & orthogonals                        != 0
+    }
+
+    /// Checks if the given colour's king is attacked
+    pub fn king_is_in_check(&self, colour: Colour) -> bool {
+        let king = self.pieces(Piece::King, colour);
+        king != 0 && self.square_is_attacked_by(king, colour.opposite())
+    }
+
     pub fn piece_at(&self, offset: u64) -> Option<(Piece, Colour)> {
         use Piece::*;
         use Colour::*;
@@ -361,96 +443,25 @@ impl Board {
     }
 
     pub fn print_board(&self, white_pov: bool) {
-        let mut rank = String::new();
-        let mut letters = if white_pov {vec!['8', '7', '6', '5', '4', '3', '2', '1']} else {vec!['1', '2', '3', '4', '5', '6', '7', '8']}.into_iter();
-        
-        for i in 0..64 {
-            let offset = if white_pov { 1 << (63-i) } else { 1 << i };
-            let prev = rank;
-            // NOTE: i would have liked to use ♙♖♘♗♕♔ ♟︎♞♝♜♛♚ but they don't seem to line up properly with certain fonts/terminals 🙃
-            rank = match self.piece_at(offset) {
-                Some(piece) => match piece {
-                    // White Pieces
-                    (Piece::Pawn,   Colour::White) => format!("{}{}", " P".bright_blue(), &prev),
-                    (Piece::Rook,   Colour::White) => format!("{}{}", " R".bright_blue(), &prev),
-                    (Piece::Knight, Colour::White) => format!("{}{}", " N".bright_blue(), &prev),
-                    (Piece::Bishop, Colour::White) => format!("{}{}", " B".bright_blue(), &prev),
-                    (Piece::Queen,  Colour::White) => format!("{}{}", " Q".bright_blue(), &prev),
-                    (Piece::King,   Colour::White) => format!("{}{}", " K".bright_blue(), &prev),
-                    // Black Pieces
-                    (Piece::Pawn,   Colour::Black) => format!("{}{}", " P".bright_red(),  &prev),
-                    (Piece::Rook,   Colour::Black) => format!("{}{}", " R".bright_red(),  &prev),
-                    (Piece::Knight, Colour::Black) => format!("{}{}", " N".bright_red(),  &prev),
-                    (Piece::Bishop, Colour::Black) => format!("{}{}", " B".bright_red(),  &prev),
-                    (Piece::Queen,  Colour::Black) => format!("{}{}", " Q".bright_red(),  &prev),
-                    (Piece::King,   Colour::Black) => format!("{}{}", " K".bright_red(),  &prev),
-                },
-                None => format!("{}{}", " ·", &prev),
-            };
-            if i % 8 == 7 {
-                print!("{}", letters.next().unwrap());
-                println!("{}\r", rank.as_str());
-                rank = "".to_string();
-            }
-        }
-        if white_pov {
-            println!("~ A B C D E F G H\r")
-        } else {
-            println!("~ H G F E D C B A\r")
-        }
-
-    }    
+        println!("{}", self.get_board(white_pov));
+    }
 
     pub fn get_board(&self, white_pov: bool) -> String {
-        let mut board = String::new();
-
-        let mut rank = String::new();
-        let mut letters = if white_pov {vec!['8', '7', '6', '5', '4', '3', '2', '1']} else {vec!['1', '2', '3', '4', '5', '6', '7', '8']}.into_iter();
-        
-        for i in 0..64 {
-            let offset = if white_pov { 1 << (63-i) } else { 1 << i };
-            let prev = rank;
-            // NOTE: i would have liked to use ♙♖♘♗♕♔ ♟︎♞♝♜♛♚ but they don't seem to line up properly with certain fonts/terminals 🙃
-            rank = match self.piece_at(offset) {
-                Some(piece) => match piece {
-                    // White Pieces
-                    (Piece::Pawn,   Colour::White) => format!("{}{}", " P".bright_blue(), &prev),
-                    (Piece::Rook,   Colour::White) => format!("{}{}", " R".bright_blue(), &prev),
-                    (Piece::Knight, Colour::White) => format!("{}{}", " N".bright_blue(), &prev),
-                    (Piece::Bishop, Colour::White) => format!("{}{}", " B".bright_blue(), &prev),
-                    (Piece::Queen,  Colour::White) => format!("{}{}", " Q".bright_blue(), &prev),
-                    (Piece::King,   Colour::White) => format!("{}{}", " K".bright_blue(), &prev),
-                    // Black Pieces
-                    (Piece::Pawn,   Colour::Black) => format!("{}{}", " P".bright_red(),  &prev),
-                    (Piece::Rook,   Colour::Black) => format!("{}{}", " R".bright_red(),  &prev),
-                    (Piece::Knight, Colour::Black) => format!("{}{}", " N".bright_red(),  &prev),
-                    (Piece::Bishop, Colour::Black) => format!("{}{}", " B".bright_red(),  &prev),
-                    (Piece::Queen,  Colour::Black) => format!("{}{}", " Q".bright_red(),  &prev),
-                    (Piece::King,   Colour::Black) => format!("{}{}", " K".bright_red(),  &prev),
-                },
-                None => format!("{}{}", " ·", &prev),
-            };
-            if i % 8 == 7 {
-                board.push(letters.next().unwrap());
-                board.push_str(rank.as_str());
-                board.push_str("\r\n");
-                rank = "".to_string();
-            }
-        }
-        if white_pov {
-            board.push_str("~ A B C D E F G H\r");
-        } else {
-            board.push_str("~ H G F E D C B A\r");
-        }
-
-        board
+        self.render(white_pov, &Marks::default(), &Theme::default())
     }
 
+    /// The board with the from and to squares of the move that led to it marked
     pub fn get_move_board(&self, white_pov: bool, ply: Ply) -> String {
-        todo!()
-    } 
+        let marks = Marks { last_move: ply.mov.from_sq.as_bb() | ply.mov.to_sq.as_bb(), ..Default::default() };
+        self.render(white_pov, &marks, &Theme::default())
+    }
 
     pub fn get_possible_moves_board(&self, white_pov: bool, sq: Square) -> String {
+        self.render(white_pov, &self.selection_marks(sq), &Theme::default())
+    }
+
+    /// Marks the pieces the piece on `sq` attacks and the empty squares it can move to
+    pub fn selection_marks(&self, sq: Square) -> Marks {
         // FIXME: Doesn't (currently) filter illegal moves or pinned pieces
 
         let sq = sq.as_bb();
@@ -466,54 +477,116 @@ impl Board {
             },
             None => (0, 0), // if sq isn't valid, will render no moves
         };
-        
-        let mut board = String::new();
+        Marks { attacks, moves, ..Default::default() }
+    }
+
+    /// Draws the board from White's or Black's side in the given theme. A king in check is always marked.
+    /// The board is nine lines whether or not it has coordinates, so that it can be redrawn in place
+    pub fn render(&self, white_pov: bool, marks: &Marks, theme: &Theme) -> String {
+        let in_check = [Colour::White, Colour::Black].into_iter()
+            .filter(|&colour| self.king_is_in_check(colour))
+            .fold(0, |bb, colour| bb | self.pieces(Piece::King, colour));
+        let order: [u8; 8] = match white_pov {
+            true => [0, 1, 2, 3, 4, 5, 6, 7],
+            false => [7, 6, 5, 4, 3, 2, 1, 0],
+        };
 
-        let mut rank = String::new();
-        let mut letters = if white_pov {vec!['8', '7', '6', '5', '4', '3', '2', '1']} else {vec!['1', '2', '3', '4', '5', '6', '7', '8']}.into_iter();
-        
-        for i in 0..64 {
-            let offset = if white_pov { 1 << (63-i) } else { 1 << i };
-            let prev = rank;
-            // NOTE: i would have liked to use ♙♖♘♗♕♔ ♟︎♞♝♜♛♚ but they don't seem to line up properly with certain fonts/terminals 🙃
-            rank = match self.piece_at(offset) {
-                Some(piece) => match piece {
-                    // White Pieces
-                    (Piece::Pawn,   Colour::White) => format!("{}{}", if offset & attacks != 0 {" P".black().on_bright_red()} else {" P".bright_blue()}, &prev),
-                    (Piece::Rook,   Colour::White) => format!("{}{}", if offset & attacks != 0 {" R".black().on_bright_red()} else {" R".bright_blue()}, &prev),
-                    (Piece::Knight, Colour::White) => format!("{}{}", if offset & attacks != 0 {" N".black().on_bright_red()} else {" N".bright_blue()}, &prev),
-                    (Piece::Bishop, Colour::White) => format!("{}{}", if offset & attacks != 0 {" B".black().on_bright_red()} else {" B".bright_blue()}, &prev),
-                    (Piece::Queen,  Colour::White) => format!("{}{}", if offset & attacks != 0 {" Q".black().on_bright_red()} else {" Q".bright_blue()}, &prev),
-                    (Piece::King,   Colour::White) => format!("{}{}", if offset & attacks != 0 {" K".black().on_bright_red()} else {" K".bright_blue()}, &prev),
-                    // Black Pieces
-                    (Piece::Pawn,   Colour::Black) => format!("{}{}", if offset & attacks != 0 {" P".black().on_bright_blue()} else {" P".bright_red()},  &prev),
-                    (Piece::Rook,   Colour::Black) => format!("{}{}", if offset & attacks != 0 {" R".black().on_bright_blue()} else {" R".bright_red()},  &prev),
-                    (Piece::Knight, Colour::Black) => format!("{}{}", if offset & attacks != 0 {" N".black().on_bright_blue()} else {" N".bright_red()},  &prev),
-                    (Piece::Bishop, Colour::Black) => format!("{}{}", if offset & attacks != 0 {" B".black().on_bright_blue()} else {" B".bright_red()},  &prev),
-                    (Piece::Queen,  Colour::Black) => format!("{}{}", if offset & attacks != 0 {" Q".black().on_bright_blue()} else {" Q".bright_red()},  &prev),
-                    (Piece::King,   Colour::Black) => format!("{}{}", if offset & attacks != 0 {" K".black().on_bright_blue()} else {" K".bright_red()},  &prev),
-                },
-                None => if offset & moves != 0  {
-                    format!("{}{}", " ·".on_green().black(), &prev)
-                } else {
-                    format!("{}{}", " ·", &prev)
-                },
-            };
-            if i % 8 == 7 {
-                board.push(letters.next().unwrap());
-                board.push_str(rank.as_str());
-                board.push_str("\r\n");
-                rank = "".to_string();
+        let mut board = String::new();
+        for rank in order.iter().rev() {
+            if theme.coordinates {
+                board.push((b'1' + rank) as char);
+            }
+            for file in order {
+                let sq = 1 << (rank * 8 + file);
+                board.push_str(&self.render_square(sq, in_check, marks, theme).to_string());
             }
+            board.push_str("\r\n");
         }
-        if white_pov {
-            board.push_str("~ A B C D E F G H\r");
-        } else {
-            board.push_str("~ H G F E D C B A\r");
+        if theme.coordinates {
+            board.push('~');
+            for file in order {
+                let label = (b'A' + file) as char;
+                // Lined up under the pieces
+                match theme.padding {
+                    Padding::Narrow => board.push_str(&format!(" {label}")),
+                    Padding::Wide => board.push_str(&format!(" {label} ")),
+                }
+            }
         }
-
+        board.push('\r');
         board
+    }
+
+    fn render_square(&self, sq: u64, in_check: u64, marks: &Marks, theme: &Theme) -> ColoredString {
+        let piece = self.piece_at(sq);
+        let text = theme.glyph(piece);
+        let dark = theme.shade && sq & core::DARK_SQS != 0;
+        let Some((_, colour)) = piece else {
+            return match () {
+                _ if sq & marks.moves != 0 => text.on_green().black(),
+                _ if sq & marks.last_move != 0 => text.on_yellow(),
+                _ if dark => text.on_bright_black(),
+                _ => text.normal(),
+            }
+        };
 
+        match colour {
+            Colour::White if sq & marks.attacks != 0 => text.black().on_bright_red(),
+            Colour::Black if sq & marks.attacks != 0 => text.black().on_bright_blue(),
+            _ if sq & in_check != 0 => text.bright_white().bold().on_magenta(),
+            _ if sq & marks.last_move != 0 => theme.paint(&text, colour).on_yellow(),
+            _ if dark => theme.paint(&text, colour).on_bright_black(),
+            _ => theme.paint(&text, colour),
+        }
     }
+}
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::theme::Glyphs;
+    use crate::Game;
+
+    /// A rendered board without its colours
+    fn plain(board: &str) -> String {
+        let mut text = String::new();
+        let mut chars = board.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\x1b' => { chars.by_ref().find(|&c| c == 'm'); },
+                ch => text.push(ch),
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn rendering() {
+        let mut game = Game::new();
+        for mov in ["e2e4", "f7f6", "d1h5"] {
+            let ply = game.last_position().parse_uci(mov).unwrap();
+            game.make_ply(ply);
+        }
+        let board = game.last_position().board;
+        assert!(board.king_is_in_check(Colour::Black));
+
+        let lines: Vec<String> = plain(&board.get_board(false)).lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "1 R N B K · B N R");
+        assert_eq!(lines[4], "5 Q · · · · · · ·");
+        assert_eq!(lines[8], "~ H G F E D C B A\r");
+
+        // Marks change the colours but never the text
+        let marks = board.selection_marks(Square::H5);
+        let shaded = Theme { shade: true, ..Theme::default() };
+        assert_eq!(plain(&board.render(true, &marks, &shaded)), plain(&board.get_board(true)));
+        assert_eq!(marks.attacks, Square::E8.as_bb() | Square::H7.as_bb());
+
+        let wide = Theme { coordinates: false, padding: Padding::Wide, glyphs: Glyphs::Figurines, ..Theme::default() };
+        let lines: Vec<String> = plain(&board.render(true, &Marks::default(), &wide)).lines().map(str::to_string).collect();
+        assert_eq!(lines[0], " ♜  ♞  ♝  ♛  ♚  ♝  ♞  ♜ ");
+        assert_eq!(lines[8], "\r");
+        let wide = Theme { padding: Padding::Wide, ..Theme::default() };
+        assert_eq!(plain(&board.render(true, &Marks::default(), &wide)).lines().nth(8), Some("~ A  B  C  D  E  F  G  H \r"));
+    }
 }
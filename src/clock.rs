@@ -0,0 +1,285 @@
+use std::time::{ Duration, Instant };
+
+use crate::ply::Colour;
+
+/// Time given back or held off on each move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bonus {
+    None,
+    /// Added after every move
+    Fischer(Duration),
+    /// The time used is given back after every move, up to this much
+    Bronstein(Duration),
+    /// The clock only starts running once this has passed, the simple or US delay
+    Delay(Duration),
+}
+
+/// One stage of a time control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    /// Moves to make in this stage, the rest of the game if `None`
+    pub moves: Option<u16>,
+    pub time: Duration,
+    pub bonus: Bonus,
+}
+
+/// A time control of one or more stages. A last stage with a number of moves repeats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub stages: Vec<Stage>,
+}
+
+fn parse_number(text: &str, what: &str) -> Result<f64, String> {
+    text.trim().parse::<f64>().ok().filter(|n| *n >= 0.0 && n.is_finite()).ok_or_else(|| format!("Expected {what}, got `{text}`"))
+}
+
+impl TimeControl {
+    /// Reads stages separated by commas, each `[moves/]minutes[+secs|d secs|b secs]`: i.e. `5` for sudden death,
+    /// `3+2` with a Fischer increment, `5d3` with a simple delay, `5b3` with a Bronstein delay or `40/90+30,30+30`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let stages = text.split(',').map(|stage| {
+            let (moves, rest) = match stage.split_once('/') {
+                Some((moves, rest)) => match moves.trim().parse::<u16>() {
+                    Ok(moves @ 1..) => (Some(moves), rest),
+                    _ => return Err(format!("Expected a number of moves, got `{moves}`")),
+                },
+                None => (None, stage),
+            };
+            let (minutes, bonus) = match rest.find(['+', 'd', 'b']) {
+                Some(i) => {
+                    let secs = Duration::from_secs_f64(parse_number(&rest[i + 1..], "seconds")?);
+                    let bonus = match &rest[i..=i] {
+                        "+" => Bonus::Fischer(secs),
+                        "d" => Bonus::Delay(secs),
+                        _ => Bonus::Bronstein(secs),
+                    };
+                    (&rest[..i], bonus)
+                },
+                None => (rest, Bonus::None),
+            };
+            let time = Duration::from_secs_f64(parse_number(minutes, "minutes")? * 60.0);
+            Ok(Stage { moves, time, bonus })
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        if stages[0].time.is_zero() {
+            return Err("The first stage needs some time".to_string())
+        }
+        if stages[..stages.len() - 1].iter().any(|s| s.moves.is_none()) {
+            return Err("Only the last stage can be for the rest of the game".to_string())
+        }
+        Ok(Self { stages })
+    }
+
+    /// As in the PGN `TimeControl` tag, in seconds. Delays can't be written and are left out
+    pub fn pgn_tag(&self) -> String {
+        let stages: Vec<String> = self.stages.iter().map(|stage| {
+            let mut tag = match stage.moves {
+                Some(moves) => format!("{moves}/{}", stage.time.as_secs()),
+                None => stage.time.as_secs().to_string(),
+            };
+            if let Bonus::Fischer(inc) = stage.bonus {
+                tag.push_str(&format!("+{}", inc.as_secs_f64()));
+            }
+            tag
+        }).collect();
+        stages.join(":")
+    }
+}
+
+/// Both players' clocks, of which only the side to move's runs
+#[derive(Debug, Clone)]
+pub struct Clocks {
+    pub control: TimeControl,
+    remaining: [Duration; 2],
+    /// Each side's stage of the time control and moves made in it
+    stage: [usize; 2],
+    stage_moves: [u16; 2],
+    turn: Colour,
+    /// When the side to move's clock started, `None` before the first move is due
+    started: Option<Instant>,
+    /// The mover's time left after every move, in the order played
+    pub history: Vec<Duration>,
+}
+
+impl Clocks {
+    pub fn new(control: TimeControl, turn: Colour) -> Self {
+        let time = control.stages[0].time;
+        Self { control, remaining: [time; 2], stage: [0; 2], stage_moves: [0; 2], turn, started: None, history: Vec::new() }
+    }
+
+    /// Starts the side to move's clock if it isn't already running
+    pub fn start(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Fresh clocks with the same time control, for a new game with `turn` to move
+    pub fn reset(&self, turn: Colour) -> Self {
+        Self::new(self.control.clone(), turn)
+    }
+
+    pub fn turn(&self) -> Colour {
+        self.turn
+    }
+
+    fn current_stage(&self, colour: Colour) -> &Stage {
+        &self.control.stages[self.stage[colour as usize]]
+    }
+
+    /// How much of the time spent on a move comes off the clock
+    fn charged(&self, elapsed: Duration) -> Duration {
+        match self.current_stage(self.turn).bonus {
+            Bonus::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started.map_or(Duration::ZERO, |started| started.elapsed())
+    }
+
+    fn remaining_after(&self, colour: Colour, elapsed: Duration) -> Duration {
+        match colour == self.turn {
+            true => self.remaining[colour as usize].saturating_sub(self.charged(elapsed)),
+            false => self.remaining[colour as usize],
+        }
+    }
+
+    /// Time left, counting down the side to move's clock while it runs
+    pub fn remaining(&self, colour: Colour) -> Duration {
+        self.remaining_after(colour, self.elapsed())
+    }
+
+    /// The side to move if its time has run out
+    pub fn flagged(&self) -> Option<Colour> {
+        (self.started.is_some() && self.remaining(self.turn).is_zero()).then_some(self.turn)
+    }
+
+    /// Stops the mover's clock after their move and starts the opponent's.
+    /// Returns false, leaving the clocks as they are, if the mover had already run out of time
+    pub fn press(&mut self) -> bool {
+        self.press_after(self.elapsed())
+    }
+
+    fn press_after(&mut self, elapsed: Duration) -> bool {
+        let (side, stage) = (self.turn as usize, *self.current_stage(self.turn));
+        let used = self.charged(elapsed);
+        if used >= self.remaining[side] {
+            self.remaining[side] = Duration::ZERO;
+            return false
+        }
+
+        self.remaining[side] -= used;
+        match stage.bonus {
+            Bonus::Fischer(inc) => self.remaining[side] += inc,
+            Bonus::Bronstein(delay) => self.remaining[side] += used.min(delay),
+            Bonus::None | Bonus::Delay(_) => {},
+        }
+        self.stage_moves[side] += 1;
+        if stage.moves == Some(self.stage_moves[side]) {
+            self.stage[side] = (self.stage[side] + 1).min(self.control.stages.len() - 1);
+            self.stage_moves[side] = 0;
+            self.remaining[side] += self.current_stage(self.turn).time;
+        }
+
+        self.history.push(self.remaining[side]);
+        self.turn = self.turn.opposite();
+        self.started = Some(Instant::now());
+        true
+    }
+}
+
+/// Time left as shown while playing: `m:ss`, `h:mm:ss` from an hour, with tenths under twenty seconds
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    match secs {
+        0..=19 => format!("0:{:02}.{}", secs, time.subsec_millis() / 100),
+        20..=3599 => format!("{}:{:02}", secs / 60, secs % 60),
+        _ => format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
+/// Time left as in PGN `%clk` comments, `h:mm:ss`
+pub fn format_clk(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    /// Time left without the running clock ticking while the test runs
+    fn left(clocks: &Clocks, colour: Colour) -> Duration {
+        clocks.remaining_after(colour, Duration::ZERO)
+    }
+
+    #[test]
+    fn parsing() {
+        let control = TimeControl::parse("40/90+30,30+30").unwrap();
+        assert_eq!(control.stages, [
+            Stage { moves: Some(40), time: secs(5400), bonus: Bonus::Fischer(secs(30)) },
+            Stage { moves: None, time: secs(1800), bonus: Bonus::Fischer(secs(30)) },
+        ]);
+        assert_eq!(control.pgn_tag(), "40/5400+30:1800+30");
+
+        assert_eq!(TimeControl::parse("5").unwrap().stages[0].bonus, Bonus::None);
+        assert_eq!(TimeControl::parse("5d3").unwrap().stages[0].bonus, Bonus::Delay(secs(3)));
+        assert_eq!(TimeControl::parse("0.5b2").unwrap().stages[0], Stage { moves: None, time: secs(30), bonus: Bonus::Bronstein(secs(2)) });
+        for bad in ["", "x", "0+2", "40/", "30+30,40/90", "5+x"] {
+            assert!(TimeControl::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn bonuses() {
+        let mut fischer = Clocks::new(TimeControl::parse("1+2").unwrap(), Colour::White);
+        assert!(fischer.press_after(secs(10)));
+        assert_eq!(left(&fischer, Colour::White), secs(52));
+        assert_eq!(fischer.turn(), Colour::Black);
+
+        let mut bronstein = Clocks::new(TimeControl::parse("1b2").unwrap(), Colour::White);
+        bronstein.press_after(secs(10));
+        bronstein.press_after(Duration::from_secs_f64(1.5));
+        assert_eq!((left(&bronstein, Colour::White), left(&bronstein, Colour::Black)), (secs(52), secs(60)));
+
+        let mut delay = Clocks::new(TimeControl::parse("1d3").unwrap(), Colour::White);
+        delay.press_after(secs(2));
+        delay.press_after(secs(10));
+        assert_eq!((left(&delay, Colour::White), left(&delay, Colour::Black)), (secs(60), secs(53)));
+        assert_eq!(delay.history, [secs(60), secs(53)]);
+    }
+
+    #[test]
+    fn stages_and_flags() {
+        let mut clocks = Clocks::new(TimeControl::parse("2/1,1").unwrap(), Colour::White);
+        for _ in 0..4 {
+            assert!(clocks.press_after(secs(20)));
+        }
+        // Both made their two moves in the first stage and got the second's minute
+        assert_eq!(left(&clocks, Colour::White), secs(80));
+        assert_eq!(clocks.flagged(), None);
+
+        assert!(!clocks.press_after(secs(80)));
+        assert_eq!(left(&clocks, Colour::White), Duration::ZERO);
+        assert_eq!(clocks.turn(), Colour::White);
+
+        // A new game starts from the full time, stopped until the first move is due
+        clocks.start();
+        let fresh = clocks.reset(Colour::Black);
+        assert!(clocks.is_running() && !fresh.is_running());
+        assert_eq!((left(&fresh, Colour::White), fresh.turn(), fresh.history.len()), (secs(60), Colour::Black, 0));
+
+        assert_eq!(format_time(Duration::from_millis(9_450)), "0:09.4");
+        assert_eq!(format_time(secs(754)), "12:34");
+        assert_eq!(format_time(secs(5400)), "1:30:00");
+        assert_eq!(format_clk(secs(754)), "0:12:34");
+    }
+}
@@ -57,7 +57,8 @@ pub mod dumb7fill {
         flood |= gen; gen = (gen >> 8) & empties;
         flood |= gen; gen = (gen >> 8) & empties;
         flood |= gen; gen = (gen >> 8) & empties;
-        flood |=            (gen >> 8) & empties;
+        flood |= gen; gen = (gen >> 8) & empties;
+        flood |= gen;
 
         flood >> 8
     }
@@ -71,7 +72,8 @@ pub mod dumb7fill {
         flood |= gen; gen = (gen << 8) & empties;
         flood |= gen; gen = (gen << 8) & empties;
         flood |= gen; gen = (gen << 8) & empties;
-        flood |=            (gen << 8) & empties;
+        flood |= gen; gen = (gen << 8) & empties;
+        flood |= gen;
 
         flood << 8
     }
@@ -87,7 +89,8 @@ pub mod dumb7fill {
         flood |= gen; gen = (gen << 1) & empties;
         flood |= gen; gen = (gen << 1) & empties;
         flood |= gen; gen = (gen << 1) & empties;
-        flood |=            (gen << 1) & empties;
+        flood |= gen; gen = (gen << 1) & empties;
+        flood |= gen;
 
         (flood << 1) & !FILE_A
     }
@@ -103,15 +106,79 @@ pub mod dumb7fill {
         flood |= gen; gen = (gen >> 1) & empties;
         flood |= gen; gen = (gen >> 1) & empties;
         flood |= gen; gen = (gen >> 1) & empties;
-        flood |=            (gen >> 1) & empties;
+        flood |= gen; gen = (gen >> 1) & empties;
+        flood |= gen;
 
         (flood >> 1) & !FILE_H
     }
 
-    pub fn bish_north_west_attacks(sq: u64, empties: u64) -> u64 { todo!() }
-    pub fn bish_north_east_attacks(sq: u64, empties: u64) -> u64 { todo!() }
-    pub fn bish_south_west_attacks(sq: u64, empties: u64) -> u64 { todo!() }
-    pub fn bish_south_east_attacks(sq: u64, empties: u64) -> u64 { todo!() }
+    pub fn bish_north_west_attacks(sq: u64, empties: u64) -> u64 {
+        let mut gen = sq;
+        let mut flood = gen;
+        let mut empties = empties;
+        empties &= !FILE_H;
+
+        flood |= gen; gen = (gen << 7) & empties;
+        flood |= gen; gen = (gen << 7) & empties;
+        flood |= gen; gen = (gen << 7) & empties;
+        flood |= gen; gen = (gen << 7) & empties;
+        flood |= gen; gen = (gen << 7) & empties;
+        flood |= gen; gen = (gen << 7) & empties;
+        flood |= gen;
+
+        (flood << 7) & !FILE_H
+    }
+
+    pub fn bish_north_east_attacks(sq: u64, empties: u64) -> u64 {
+        let mut gen = sq;
+        let mut flood = gen;
+        let mut empties = empties;
+        empties &= !FILE_A;
+
+        flood |= gen; gen = (gen << 9) & empties;
+        flood |= gen; gen = (gen << 9) & empties;
+        flood |= gen; gen = (gen << 9) & empties;
+        flood |= gen; gen = (gen << 9) & empties;
+        flood |= gen; gen = (gen << 9) & empties;
+        flood |= gen; gen = (gen << 9) & empties;
+        flood |= gen;
+
+        (flood << 9) & !FILE_A
+    }
+
+    pub fn bish_south_west_attacks(sq: u64, empties: u64) -> u64 {
+        let mut gen = sq;
+        let mut flood = gen;
+        let mut empties = empties;
+        empties &= !FILE_H;
+
+        flood |= gen; gen = (gen >> 9) & empties;
+        flood |= gen; gen = (gen >> 9) & empties;
+        flood |= gen; gen = (gen >> 9) & empties;
+        flood |= gen; gen = (gen >> 9) & empties;
+        flood |= gen; gen = (gen >> 9) & empties;
+        flood |= gen; gen = (gen >> 9) & empties;
+        flood |= gen;
+
+        (flood >> 9) & !FILE_H
+    }
+
+    pub fn bish_south_east_attacks(sq: u64, empties: u64) -> u64 {
+        let mut gen = sq;
+        let mut flood = gen;
+        let mut empties = empties;
+        empties &= !FILE_A;
+
+        flood |= gen; gen = (gen >> 7) & empties;
+        flood |= gen; gen = (gen >> 7) & empties;
+        flood |= gen; gen = (gen >> 7) & empties;
+        flood |= gen; gen = (gen >> 7) & empties;
+        flood |= gen; gen = (gen >> 7) & empties;
+        flood |= gen; gen = (gen >> 7) & empties;
+        flood |= gen;
+
+        (flood >> 7) & !FILE_A
+    }
 
     // pub fn north_east_dumb7fill_attacks(sq: u64, empties: u64) -> u64 {
     //     let mut gen = sq;
@@ -153,5 +220,43 @@ pub mod dumb7fill {
             let mask = FILE_B | FILE_C | FILE_D | FILE_E;
             assert_eq!(mask, x, "\n{}", pp_bb(x));
         }
+
+        #[test]
+        fn north_unblocked() {
+            let x = rook_north_attacks(1, u64::MAX);
+            let mask = FILE_A & !RANK_1;
+            assert_eq!(mask, x, "\n{}", pp_bb(x));
+        }
+
+        #[test]
+        fn north_east() {
+            // b2 blocked on f6
+            let x = bish_north_east_attacks(1 << 9, !(1 << 45));
+            let mask = 1 << 18 | 1 << 27 | 1 << 36 | 1 << 45;
+            assert_eq!(mask, x, "\n{}", pp_bb(x));
+        }
+
+        #[test]
+        fn north_west() {
+            // h1 unblocked, must not wrap onto the h-file
+            let x = bish_north_west_attacks(1 << 7, u64::MAX);
+            let mask = 1 << 14 | 1 << 21 | 1 << 28 | 1 << 35 | 1 << 42 | 1 << 49 | 1 << 56;
+            assert_eq!(mask, x, "\n{}", pp_bb(x));
+        }
+
+        #[test]
+        fn south_east() {
+            // c6 blocked on e4
+            let x = bish_south_east_attacks(1 << 42, !(1 << 28));
+            let mask = 1 << 35 | 1 << 28;
+            assert_eq!(mask, x, "\n{}", pp_bb(x));
+        }
+
+        #[test]
+        fn south_west() {
+            // a8 has nowhere to go
+            let x = bish_south_west_attacks(1 << 56, u64::MAX);
+            assert_eq!(0, x, "\n{}", pp_bb(x));
+        }
     }
 }
@@ -1,7 +1,12 @@
 pub mod repl;
+pub mod theme;
+pub mod uci;
+pub mod xboard;
 pub mod fill;
 pub mod utils;
 pub mod parsers;
+pub mod polyglot;
+pub mod zobrist;
 
 pub const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
@@ -106,9 +111,20 @@ pub fn bish_moves_bb(sq: u64, empties: u64) -> u64 {
     | bish_south_east_attacks(sq, empties)
 }
 
+/// Iterates over each set bit of a bitboard, yielding them as single bit bitboards
+pub fn bits(mut bb: u64) -> impl Iterator<Item = u64> {
+    std::iter::from_fn(move || {
+        if bb == 0 {
+            return None
+        }
+        let lsb = bb & bb.wrapping_neg();
+        bb ^= lsb;
+        Some(lsb)
+    })
+}
 
 #[rustfmt::skip]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Square {
     #[default] // A1
     A1, B1, C1, D1, E1, F1, G1, H1,
@@ -198,6 +214,14 @@ impl Square {
         }
     }
 
+    pub fn file(&self) -> u8 {
+        *self as u8 % 8
+    }
+
+    pub fn rank(&self) -> u8 {
+        *self as u8 / 8
+    }
+
     pub fn to_bb(self) -> u64 {
         1 << (self as u8)
     }
@@ -207,6 +231,12 @@ impl Square {
     }
 }
 
+impl std::fmt::Display for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Square::bb_to_str(self.as_bb()))
+    }
+}
+
 // pub const _sq_to_int: HashMap<&str, u8> = HashMap::from([
 //     ("a8", 56), ("b8", 57), ("c8", 58), ("d8", 59), ("e8", 60), ("f8", 61), ("g8", 62), ("h8", 63),
 //     ("a7", 48), ("b7", 49), ("c7", 50), ("d7", 51), ("e7", 52), ("f7", 53), ("g7", 54), ("h7", 55),
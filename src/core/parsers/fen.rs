@@ -1,7 +1,8 @@
 use crate    :: {
     core     :: Square, 
     game     :: Game, 
-    position :: Position 
+    position :: Position,
+    tree     :: MoveTree,
 };
 
 
@@ -83,7 +84,14 @@ pub fn parse_fen(fen: &str) -> Result<Game, String> {
     if position.was_blacks_move {
         game.ply -= 1;
     }
+    // Derived state
+    let side = position.side_to_move();
+    if position.board.king_is_in_check(side) {
+        position.check = Some(side);
+    }
+    position.hash = position.compute_hash();
 
+    game.tree = MoveTree::new(position, game.mov);
     game.history.push(position);
     Ok(game)
 }
\ No newline at end of file
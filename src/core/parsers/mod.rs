@@ -1,2 +1,3 @@
 pub mod fen;
-pub mod pgn;
\ No newline at end of file
+pub mod pgn;
+pub mod san;
\ No newline at end of file
@@ -0,0 +1,513 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate    :: {
+    core     :: INITIAL_FEN,
+    game     :: Game,
+    ply      :: { Colour, Ply },
+    tree     :: { MoveTree, NodeId },
+};
+
+use super::san;
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// A game as read from PGN: its tags and the SAN moves of its main line.
+/// NOTE: Comments, NAGs and variations are skipped
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The game's result, from the movetext or else the `Result` tag
+    pub fn result(&self) -> Option<&str> {
+        self.result.as_deref().or(self.tag("Result")).filter(|r| *r != "*")
+    }
+
+    /// The starting position, which is only set up from the `FEN` tag
+    pub fn start(&self) -> Result<Game, String> {
+        Game::try_from_fen(self.tag("FEN").unwrap_or(INITIAL_FEN))
+    }
+
+    /// Checks and converts the main line's moves, returning the starting position with them
+    pub fn plies(&self) -> Result<(Game, Vec<Ply>), String> {
+        let start = self.start()?;
+        let mut pos = *start.last_position();
+        let mut plies = Vec::with_capacity(self.moves.len());
+        for mov in &self.moves {
+            let ply = san::parse_san(&pos, mov).ok_or_else(|| format!("Illegal move `{mov}`"))?;
+            pos = pos.make_ply(ply);
+            plies.push(ply);
+        }
+        Ok((start, plies))
+    }
+
+    /// Writes the game as PGN, numbering the moves from the starting position
+    pub fn to_pgn(&self) -> String {
+        let (mut number, mut white) = match self.start() {
+            Ok(start) => (start.mov.max(1), start.last_position().side_to_move() == Colour::White),
+            Err(_) => (1, true),
+        };
+        let mut tokens = Vec::with_capacity(self.moves.len() * 3 / 2 + 1);
+        for (i, mov) in self.moves.iter().enumerate() {
+            match (white, i) {
+                (true, _) => tokens.push(format!("{number}.")),
+                (false, 0) => tokens.push(format!("{number}...")),
+                _ => {},
+            }
+            tokens.push(mov.clone());
+            if !white {
+                number += 1;
+            }
+            white = !white;
+        }
+        tokens.push(self.result.clone().or(self.tag("Result").map(str::to_string)).unwrap_or("*".to_string()));
+        write_tags(&self.tags) + &wrap(&tokens)
+    }
+}
+
+fn write_tags(tags: &[(String, String)]) -> String {
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+    }
+    pgn.push('\n');
+    pgn
+}
+
+/// Joins movetext tokens into lines under 80 characters, ending the game with a blank line
+fn wrap(tokens: &[String]) -> String {
+    let mut pgn = String::new();
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() >= 80 {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(token);
+    }
+    pgn.push_str(&line);
+    pgn.push_str("\n\n");
+    pgn
+}
+
+fn skip_until(chars: &mut Peekable<Chars>, end: char) {
+    for c in chars.by_ref() {
+        if c == end {
+            break
+        }
+    }
+}
+
+/// Skips a variation, which may hold comments and further variations
+fn skip_variation(chars: &mut Peekable<Chars>) {
+    let mut depth = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => skip_until(chars, '}'),
+            ';' => skip_until(chars, '\n'),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 {
+            break
+        }
+    }
+}
+
+/// Reads `Name "Value"]`, after the opening bracket
+fn read_tag(chars: &mut Peekable<Chars>) -> Option<(String, String)> {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"' && *c != ']') {
+        name.push(c);
+    }
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+    let mut value = String::new();
+    if chars.next_if_eq(&'"').is_some() {
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next()),
+                '"' => break,
+                c => value.push(c),
+            }
+        }
+    }
+    skip_until(chars, ']');
+    (!name.is_empty()).then_some((name, value))
+}
+
+/// Reads every game in a PGN file. Anything that isn't a tag, move or result is skipped
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {},
+            '[' => {
+                // Tags after movetext start the next game, even without a result to end this one
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                game.tags.extend(read_tag(&mut chars));
+            },
+            '{' => skip_until(&mut chars, '}'),
+            ';' | '%' => skip_until(&mut chars, '\n'),
+            '(' => skip_variation(&mut chars),
+            _ => {
+                let mut token = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}()[];".contains(*c)) {
+                    token.push(c);
+                }
+
+                if RESULTS.contains(&token.as_str()) {
+                    game.result = Some(token);
+                    games.push(std::mem::take(&mut game));
+                    continue
+                }
+                if token.starts_with('$') {
+                    continue
+                }
+                // Move numbers, i.e. `12.`, `12...` or `12.e4`
+                let mov = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if !mov.is_empty() {
+                    game.moves.push(mov.to_string());
+                }
+            },
+        }
+    }
+
+    if !game.tags.is_empty() || !game.moves.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+/// Move suffixes and the NAGs they stand for
+pub const SUFFIXES: [(&str, u8); 6] = [("!", 1), ("?", 2), ("!!", 3), ("??", 4), ("!?", 5), ("?!", 6)];
+
+fn read_until(chars: &mut Peekable<Chars>, end: char) -> String {
+    chars.by_ref().take_while(|&c| c != end).collect()
+}
+
+/// Builds move trees while reading PGN
+#[derive(Default)]
+struct TreeReader {
+    trees: Vec<MoveTree>,
+    tags: Vec<(String, String)>,
+    tree: Option<MoveTree>,
+    /// Where to return to at the end of each open variation
+    stack: Vec<NodeId>,
+    /// A comment read before the next move
+    before: Option<String>,
+    /// Just after `(`, where a comment belongs to the variation's first move
+    opened: bool,
+}
+
+fn append(comment: &mut Option<String>, text: String) {
+    *comment = Some(match comment.take() {
+        Some(existing) => format!("{existing} {text}"),
+        None => text,
+    });
+}
+
+impl TreeReader {
+    /// The game being read, started from the tags read so far
+    fn tree(&mut self) -> Result<&mut MoveTree, String> {
+        if self.tree.is_none() {
+            let fen = self.tags.iter().find(|(n, _)| n == "FEN").map_or(INITIAL_FEN, |(_, v)| v.as_str());
+            let game = Game::try_from_fen(fen)?;
+            let mut tree = MoveTree::new(*game.last_position(), game.mov);
+            tree.tags = std::mem::take(&mut self.tags);
+            self.tree = Some(tree);
+        }
+        Ok(self.tree.as_mut().expect("just set"))
+    }
+
+    fn comment(&mut self, text: String) -> Result<(), String> {
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Ok(())
+        }
+        if self.opened {
+            append(&mut self.before, text);
+            return Ok(())
+        }
+        let tree = self.tree()?;
+        let cursor = tree.cursor();
+        append(&mut tree.node_mut(cursor).comment, text);
+        Ok(())
+    }
+
+    fn finish(&mut self, result: Option<String>) -> Result<(), String> {
+        if self.tree.is_none() && self.tags.is_empty() && result.is_none() {
+            return Ok(())
+        }
+        if !self.stack.is_empty() {
+            return Err("Unclosed variation".to_string())
+        }
+        let tree = self.tree()?;
+        tree.result = result;
+        tree.go_to_start();
+        let tree = self.tree.take().expect("just read");
+        self.trees.push(tree);
+        self.before = None;
+        self.opened = false;
+        Ok(())
+    }
+
+    fn token(&mut self, token: &str) -> Result<(), String> {
+        if let Some(nag) = token.strip_prefix('$') {
+            let tree = self.tree()?;
+            let cursor = tree.cursor();
+            if let (Ok(nag), true) = (nag.parse(), cursor != MoveTree::ROOT) {
+                tree.node_mut(cursor).nags.push(nag);
+            }
+            return Ok(())
+        }
+        // Move numbers, i.e. `12.`, `12...` or `12.e4`
+        let mov = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if mov.is_empty() {
+            return Ok(())
+        }
+        let before = self.before.take();
+        self.opened = false;
+        let tree = self.tree()?;
+        let san = mov.trim_end_matches(['!', '?']);
+        let suffix = SUFFIXES.iter().find(|(s, _)| *s == &mov[san.len()..]).map(|(_, nag)| *nag);
+
+        let ply = san::parse_san(tree.position(), san).ok_or_else(|| format!("Illegal move `{mov}`"))?;
+        let id = tree.play(ply);
+        let node = tree.node_mut(id);
+        node.nags.extend(suffix);
+        if let Some(before) = before {
+            append(&mut node.before, before);
+        }
+        Ok(())
+    }
+}
+
+/// Reads every game in a PGN file into move trees, keeping comments, NAGs and variations
+pub fn parse_trees(text: &str) -> Result<Vec<MoveTree>, String> {
+    let mut reader = TreeReader::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {},
+            '[' => {
+                if reader.tree.is_some() {
+                    reader.finish(None)?;
+                }
+                reader.tags.extend(read_tag(&mut chars));
+            },
+            '{' => reader.comment(read_until(&mut chars, '}'))?,
+            ';' => reader.comment(read_until(&mut chars, '\n'))?,
+            '%' => skip_until(&mut chars, '\n'),
+            '(' => {
+                let tree = reader.tree()?;
+                let cursor = tree.cursor();
+                if !tree.back() {
+                    return Err("Variation before any move".to_string())
+                }
+                reader.stack.push(cursor);
+                reader.opened = true;
+            },
+            ')' => {
+                let cursor = reader.stack.pop().ok_or("Unmatched `)`")?;
+                reader.tree()?.go_to(cursor);
+                reader.opened = false;
+            },
+            _ => {
+                let mut token = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}()[];".contains(*c)) {
+                    token.push(c);
+                }
+                match RESULTS.contains(&token.as_str()) {
+                    true => reader.finish(Some(token))?,
+                    false => reader.token(&token)?,
+                }
+            },
+        }
+    }
+    reader.finish(None)?;
+    Ok(reader.trees)
+}
+
+/// Writes one move with its number when needed, comments and NAGs.
+/// Returns whether the next move needs its number, as after a comment
+fn write_move(tree: &MoveTree, id: NodeId, numbered: bool, tokens: &mut Vec<String>) -> bool {
+    let node = tree.node(id);
+    let parent = tree.node(node.parent.expect("only the root has no parent"));
+    let ply = node.ply.expect("only the root has no ply");
+
+    let mut numbered = numbered;
+    if let Some(before) = &node.before {
+        tokens.push(format!("{{{before}}}"));
+        numbered = true;
+    }
+    match parent.position.side_to_move() {
+        Colour::White => tokens.push(format!("{}.", parent.mov)),
+        Colour::Black if numbered => tokens.push(format!("{}...", parent.mov)),
+        Colour::Black => {},
+    }
+    tokens.push(san::to_san(&parent.position, ply));
+    tokens.extend(node.nags.iter().map(|nag| format!("${nag}")));
+    match &node.comment {
+        Some(comment) => {
+            tokens.push(format!("{{{comment}}}"));
+            true
+        },
+        None => false,
+    }
+}
+
+/// Writes the main line on from `id`, with the variations at each branch in parentheses
+fn write_line(tree: &MoveTree, id: NodeId, numbered: bool, tokens: &mut Vec<String>) {
+    let (mut at, mut numbered) = (id, numbered);
+    while let Some(&main) = tree.node(at).children.first() {
+        numbered = write_move(tree, main, numbered, tokens);
+        for &variation in &tree.node(at).children[1..] {
+            let start = tokens.len();
+            let after = write_move(tree, variation, true, tokens);
+            write_line(tree, variation, after, tokens);
+            tokens[start].insert(0, '(');
+            tokens.last_mut().expect("a move was written").push(')');
+            numbered = true;
+        }
+        at = main;
+    }
+}
+
+/// Writes a move tree as PGN, adding the starting position's FEN if its tags lack it
+pub fn write_tree(tree: &MoveTree) -> String {
+    let mut tags = tree.tags.clone();
+    let fen = tree.fen(MoveTree::ROOT);
+    if fen != INITIAL_FEN && !tags.iter().any(|(n, _)| n == "FEN") {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), fen));
+    }
+
+    let mut tokens = Vec::new();
+    if let Some(comment) = &tree.node(MoveTree::ROOT).comment {
+        tokens.push(format!("{{{comment}}}"));
+    }
+    write_line(tree, MoveTree::ROOT, true, &mut tokens);
+    let result = tree.result.clone().or(tree.tags.iter().find(|(n, _)| n == "Result").map(|(_, v)| v.clone()));
+    tokens.push(result.unwrap_or("*".to_string()));
+    write_tags(&tags) + &wrap(&tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Test \"quoted\""]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2.Nf3 (2. f4 exf4 {gambit} (2... d5)) 2... Nc6 $1 3. Bb5 ; Ruy Lopez
+a6 1-0
+
+[Event "Second"]
+[FEN "4k3/P7/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. a8=Q+ *
+"#;
+
+    #[test]
+    fn reading() {
+        let games = parse_pgn(PGN);
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].tag("Event"), Some(r#"Test "quoted""#));
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(games[0].result(), Some("1-0"));
+
+        assert_eq!(games[1].moves, ["a8=Q+"]);
+        assert_eq!(games[1].result(), None);
+        let (start, plies) = games[1].plies().unwrap();
+        assert_eq!(start.as_fen(), "4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(plies[0].to_uci(), "a7a8q");
+    }
+
+    #[test]
+    fn illegal_moves() {
+        let games = parse_pgn("1. e4 e5 2. Ke3 *");
+        assert_eq!(games[0].plies().unwrap_err(), "Illegal move `Ke3`");
+    }
+
+    #[test]
+    fn writing() {
+        let games = parse_pgn(PGN);
+        let written = games[0].to_pgn();
+        assert!(written.starts_with("[Event \"Test \\\"quoted\\\"\"]\n[White \"A\"]\n"));
+        assert!(written.ends_with("\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0\n\n"), "{written}");
+        assert_eq!(parse_pgn(&written), games[..1]);
+
+        // Black to move starts with an ellipsis
+        let game = PgnGame {
+            tags: vec![("FEN".to_string(), "4k3/8/8/8/8/8/8/4K2R b K - 0 30".to_string())],
+            moves: vec!["Kd7".to_string(), "O-O".to_string()],
+            result: None,
+        };
+        assert!(game.to_pgn().ends_with("30... Kd7 31. O-O *\n\n"));
+    }
+
+    #[test]
+    fn variations() {
+        let trees = parse_trees(PGN).unwrap();
+        assert_eq!(trees.len(), 2);
+        let tree = &trees[0];
+        assert_eq!(tree.mainline(MoveTree::ROOT).len(), 6);
+
+        // 1. e4 {best by test} e5 2. Nf3 (2. f4 exf4 {gambit} (2... d5))
+        let e4 = tree.node(tree.node(MoveTree::ROOT).children[0]);
+        let e5 = tree.node(e4.children[0]);
+        assert_eq!(e4.comment.as_deref(), Some("best by test"));
+        assert_eq!(e5.children.len(), 2);
+        let f4 = tree.node(e5.children[1]);
+        assert_eq!(f4.children.len(), 2);
+        assert_eq!(tree.node(f4.children[0]).comment.as_deref(), Some("gambit"));
+        // Nc6 $1 with the comment to the end of the line
+        let nc6 = tree.node(tree.node(e5.children[0]).children[0]);
+        assert_eq!(nc6.nags, [1]);
+        assert_eq!(tree.node(nc6.children[0]).comment.as_deref(), Some("Ruy Lopez"));
+
+        let written = tree.to_pgn();
+        assert!(written.ends_with("\n\n1. e4 {best by test} 1... e5 2. Nf3 (2. f4 exf4 {gambit} (2... d5)) 2... Nc6 $1\n3. Bb5 {Ruy Lopez} 3... a6 1-0\n\n"), "{written}");
+        assert_eq!(parse_trees(&written).unwrap()[0].to_pgn(), written);
+        assert_eq!(trees[1].to_pgn(), "[Event \"Second\"]\n[FEN \"4k3/P7/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. a8=Q+ *\n\n");
+    }
+
+    #[test]
+    fn annotations() {
+        let tree = &parse_trees("1. e4! ({Or} 1. d4?! d5) 1... e5?? $18 *").unwrap()[0];
+        let root = tree.node(MoveTree::ROOT);
+        let (e4, d4) = (tree.node(root.children[0]), tree.node(root.children[1]));
+        assert_eq!((e4.nags.as_slice(), d4.nags.as_slice()), (&[1][..], &[6][..]));
+        assert_eq!(d4.before.as_deref(), Some("Or"));
+        assert_eq!(tree.node(e4.children[0]).nags, [4, 18]);
+        assert_eq!(tree.to_pgn(), "\n1. e4 $1 ({Or} 1. d4 $6 d5) 1... e5 $4 $18 *\n\n");
+
+        assert_eq!(parse_trees("1. e4 (e5) *").unwrap_err(), "Illegal move `e5`");
+        assert!(parse_trees("(1. e4) *").is_err());
+        assert!(parse_trees("1. e4 (1. d4 *").is_err());
+    }
+}
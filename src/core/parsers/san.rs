@@ -0,0 +1,198 @@
+use crate    :: {
+    position :: Position,
+    ply      :: { Piece, Ply },
+};
+
+fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn   => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook   => "R",
+        Piece::Queen  => "Q",
+        Piece::King   => "K",
+    }
+}
+
+/// Writes a legal ply in Standard Algebraic Notation, i.e. `Nbd7`, `exd5`, `O-O` or `e8=Q#`
+pub fn to_san(pos: &Position, ply: Ply) -> String {
+    let mov = ply.mov;
+    let (from, to) = (mov.from_sq, mov.to_sq);
+    let mut san = String::new();
+
+    if mov.piece == Piece::King && from.file().abs_diff(to.file()) == 2 {
+        san.push_str(if to.file() == 6 { "O-O" } else { "O-O-O" });
+    } else {
+        san.push_str(piece_letter(mov.piece));
+
+        if mov.piece == Piece::Pawn {
+            if ply.is_capture {
+                san.push_str(&from.to_string()[..1]);
+            }
+        } else {
+            // Only disambiguate as much as needed
+            let rivals: Vec<Ply> = pos.legal_plies().into_iter()
+                .filter(|p| p.mov.piece == mov.piece && p.mov.to_sq == to && p.mov.from_sq != from)
+                .collect();
+            if !rivals.is_empty() {
+                let from = from.to_string();
+                if rivals.iter().all(|p| p.mov.from_sq.file() != mov.from_sq.file()) {
+                    san.push_str(&from[..1]);
+                } else if rivals.iter().all(|p| p.mov.from_sq.rank() != mov.from_sq.rank()) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+        }
+
+        if ply.is_capture {
+            san.push('x');
+        }
+        san.push_str(&to.to_string());
+
+        if let Some(promotion) = ply.promotion {
+            san.push('=');
+            san.push_str(piece_letter(Piece::from(promotion)));
+        }
+    }
+
+    let next = pos.make_ply(ply);
+    if next.check.is_some() {
+        san.push(if next.legal_plies().is_empty() { '#' } else { '+' });
+    }
+    san
+}
+
+/// Writes a line of plies starting from `pos` in SAN, numbered as in PGN, i.e. `12. Nf3 Nc6 13. O-O`
+/// or `12... Nc6 13. O-O`
+pub fn line_to_san(pos: &Position, mov: u16, plies: &[Ply]) -> String {
+    let mut pos = *pos;
+    let mut mov = mov;
+    let mut line = Vec::with_capacity(plies.len());
+
+    for (i, &ply) in plies.iter().enumerate() {
+        let white = pos.was_blacks_move;
+        let san = to_san(&pos, ply);
+        line.push(match (white, i) {
+            (true, _) => format!("{mov}. {san}"),
+            (false, 0) => format!("{mov}... {san}"),
+            (false, _) => san,
+        });
+        if !white {
+            mov += 1;
+        }
+        pos = pos.make_ply(ply);
+    }
+    line.join(" ")
+}
+
+/// Strips check and annotation marks, and the `x` capture mark which players often leave out
+fn normalise(san: &str) -> String {
+    san.trim()
+        .trim_end_matches(['+', '#', '!', '?'])
+        .replace('0', "O")
+        .replace(['x', ':'], "")
+        .replace("e.p.", "")
+        .replace('=', "")
+}
+
+/// Ways of writing a ply with its origin partly or fully given, without capture or check marks
+fn spellings(ply: Ply) -> [String; 3] {
+    let (from, to) = (ply.mov.from_sq.to_string(), ply.mov.to_sq.to_string());
+    let piece = piece_letter(ply.mov.piece);
+    let promotion = ply.promotion.map(|p| piece_letter(Piece::from(p))).unwrap_or_default();
+    [
+        format!("{piece}{}{to}{promotion}", &from[..1]),
+        format!("{piece}{}{to}{promotion}", &from[1..]),
+        format!("{piece}{from}{to}{promotion}"),
+    ]
+}
+
+/// Finds the legal ply written in SAN. Also accepts long algebraic notation as used by UCI,
+/// and tolerates lower case piece letters where that isn't ambiguous
+pub fn parse_san(pos: &Position, san: &str) -> Option<Ply> {
+    if let Some(ply) = pos.parse_uci(san.trim()) {
+        return Some(ply)
+    }
+
+    let wanted = normalise(san);
+    if wanted.is_empty() {
+        return None
+    }
+    let legal = pos.legal_plies();
+    let written: Vec<(Ply, String)> = legal.iter().map(|&p| (p, normalise(&to_san(pos, p)))).collect();
+
+    if let Some((ply, _)) = written.iter().find(|(_, s)| *s == wanted) {
+        return Some(*ply)
+    }
+    // Over-disambiguated input, i.e. `Ngf3` or `Ng1f3`
+    let over: Vec<Ply> = legal.iter().copied().filter(|&p| spellings(p).contains(&wanted)).collect();
+    if let [ply] = over.as_slice() {
+        return Some(*ply)
+    }
+
+    let lower = wanted.to_lowercase();
+    let matches: Vec<&(Ply, String)> = written.iter().filter(|(_, s)| s.to_lowercase() == lower).collect();
+    match matches.as_slice() {
+        [(ply, _)] => Some(*ply),
+        // `bc3` could be a bishop or a pawn capture, the pawn is the usual reading
+        many => many.iter().find(|(p, _)| p.mov.piece == Piece::Pawn).map(|(p, _)| *p),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+
+    fn san_of(fen: &str, uci: &str) -> String {
+        let game = Game::try_from_fen(fen).unwrap();
+        let pos = game.last_position();
+        to_san(pos, pos.parse_uci(uci).unwrap())
+    }
+
+    #[test]
+    fn writing() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san_of(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san_of(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san_of(kiwipete, "e2a6"), "Bxa6");
+        assert_eq!(san_of("4k3/8/8/8/8/2N5/8/4K1N1 w - - 0 1", "g1e2"), "Nge2");
+        assert_eq!(san_of(kiwipete, "e5g6"), "Nxg6");
+
+        assert_eq!(san_of("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), "a8=Q+");
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+        // Rooks on the same file need the rank
+        assert_eq!(san_of("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+    }
+
+    #[test]
+    fn reading() {
+        let game = Game::try_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let pos = game.last_position();
+        for (input, uci) in [
+            ("O-O", "e1g1"), ("0-0-0", "e1c1"), ("dxe6", "d5e6"), ("de6", "d5e6"), ("Bxa6", "e2a6"),
+            ("bxa6", "e2a6"), ("Nb1", "c3b1"), ("Nc3b1", "c3b1"), ("Qxf6+", "f3f6"), ("g2g3", "g2g3"),
+        ] {
+            assert_eq!(parse_san(pos, input).map(|p| p.to_uci()).as_deref(), Some(uci), "{input}");
+        }
+        assert!(parse_san(pos, "Nd1d2").is_none());
+        assert!(parse_san(pos, "Ke3").is_none());
+    }
+
+    #[test]
+    fn lines() {
+        let game = Game::new();
+        let pos = game.last_position();
+        let e4 = pos.parse_uci("e2e4").unwrap();
+        let e5 = pos.make_ply(e4).parse_uci("e7e5").unwrap();
+        assert_eq!(line_to_san(pos, 1, &[e4, e5]), "1. e4 e5");
+        assert_eq!(line_to_san(&pos.make_ply(e4), 1, &[e5]), "1... e5");
+    }
+}
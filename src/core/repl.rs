@@ -1,8 +1,12 @@
 use std::collections::HashMap;
-use std::io::Write;
+use std::fs::File;
+use std::io::{ BufRead, Write };
 use std::io;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::os::fd::{ AsFd, AsRawFd };
+use std::time::Duration;
 
-use colored::ColoredString;
+use colored::{ ColoredString, Colorize };
 use termion::clear;
 use termion::cursor;
 
@@ -11,17 +15,33 @@ use termion::event::Key;
 use termion::raw::IntoRawMode;
 use termion::input::TermRead;
 
-use crate::ply::Colour;
+use crate::clock;
+use crate::engine::search::{ mate_in, Info };
+use crate::ply::{ Colour, Piece };
 use crate::ply::Movement;
+use crate::position::Position;
 use crate::Game;
 
+use super::parsers::san;
+use super::theme::{ Glyphs, Padding, Theme, FIGURINES };
 use super::Square;
 
+/// `println!` for game output, with the line endings `terminal_lines` gives it
+macro_rules! say {
+    ($($arg:tt)*) => {
+        print!("{}", $crate::core::repl::terminal_lines(&format!($($arg)*), $crate::core::repl::is_line_mode()))
+    };
+}
+pub(crate) use say;
 
 #[derive(Default)]
-struct InputBuffer {
+pub struct InputBuffer {
     buffer: Vec<char>,
     cursor: usize,
+    /// Lines entered before, the oldest first
+    history: Vec<String>,
+    /// The entry of `history` being shown while browsing it with the arrow keys, and the line that was being typed
+    browsing: Option<(usize, String)>,
 }
 
 impl InputBuffer {
@@ -42,6 +62,67 @@ impl InputBuffer {
         res
     }
 
+    /// Takes the entered line, keeping it in the history
+    pub fn submit(&mut self) -> String {
+        let line = self.take_trimmed();
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.browsing = None;
+        line
+    }
+
+    fn set_line(&mut self, line: &str) {
+        self.buffer = line.chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    /// Shows the line entered before the one shown
+    pub fn history_prev(&mut self) {
+        let index = match &self.browsing {
+            Some((0, _)) => return,
+            Some((i, _)) => i - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                let draft = self.buffer.iter().collect();
+                self.browsing = Some((self.history.len(), draft));
+                self.history.len() - 1
+            },
+        };
+        self.set_line(&self.history[index].clone());
+        self.browsing.as_mut().expect("browsing was just started").0 = index;
+    }
+
+    /// Shows the line entered after the one shown, or what was being typed after the last
+    pub fn history_next(&mut self) {
+        let Some((i, draft)) = self.browsing.take() else { return };
+        match self.history.get(i + 1) {
+            Some(line) => {
+                self.set_line(&line.clone());
+                self.browsing = Some((i + 1, draft));
+            },
+            None => self.set_line(&draft),
+        }
+    }
+
+    /// Completes the word before the cursor from `completions`, as far as the candidates agree.
+    /// Returns the candidates to show when there is more than one
+    pub fn complete(&mut self, pos: &Position) -> Option<String> {
+        let line: String = self.buffer[..self.cursor].iter().collect();
+        let candidates = completions(pos, &line);
+        let word_start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let start = self.cursor - line[word_start..].chars().count();
+
+        let first = candidates.first()?;
+        // The longest prefix the candidates share, ignoring case as the input does
+        let shared = candidates[1..].iter().fold(first.len(), |len, c| {
+            first.chars().zip(c.chars()).take(len).take_while(|(a, b)| a.eq_ignore_ascii_case(b)).count()
+        });
+        self.buffer.splice(start..self.cursor, first.chars().take(shared));
+        self.cursor = start + shared;
+        (candidates.len() > 1).then(|| candidates.join("  "))
+    }
+
     pub fn peak_trimmed(&self) -> String {
         self.buffer.iter().collect::<String>().trim().to_string()
     }
@@ -92,9 +173,10 @@ impl InputBuffer {
         }
     }
 
-    pub fn render(&self, board: &String, prompt: &ColoredString, response: &Option<String>, sink: &mut impl Write) -> io::Result<()> {
+    /// Redraws the board with `panel` beside it, then the prompt and line being typed with any response under them
+    pub fn render(&self, board: &str, panel: &[String], prompt: &ColoredString, response: &Option<String>, sink: &mut impl Write) -> io::Result<()> {
         let buf: String = self.buffer.iter().collect();
-        write!(sink, "\r{}{}{}\r", cursor::Up(11), board, cursor::Down(3))?;
+        write!(sink, "\r{}{}{}\r", cursor::Up(11), beside(board, panel), cursor::Down(3))?;
         write!(sink, "\r{}{}{}\r", clear::AfterCursor, prompt, &buf)?;
 
         if let Some (resp) = response {
@@ -108,11 +190,457 @@ impl InputBuffer {
     }
 }
 
+/// Rows of moves in the panel beside the board, which has a line for each of the board's nine
+const PANEL_ROWS: usize = 7;
+/// Columns the panel takes at most, with the gap before it
+const PANEL_WIDTH: usize = 28;
+const PANEL_GAP: &str = "    ";
+/// For the material difference
+const PIECE_VALUES: [i32; 6] = [1, 3, 3, 5, 9, 0];
+
+/// Columns `text` takes in the terminal, leaving out colour codes
+fn visible_width(text: &str) -> usize {
+    let mut in_code = false;
+    text.chars().filter(|&ch| {
+        match (in_code, ch) {
+            (false, '\x1b') => in_code = true,
+            (true, 'm') => { in_code = false; return false },
+            _ => {},
+        }
+        !in_code
+    }).count()
+}
+
+/// Joins the panel's lines to the ends of the board's
+fn beside(board: &str, panel: &[String]) -> String {
+    if panel.is_empty() {
+        return board.to_string()
+    }
+    let lines: Vec<&str> = board.split("\r\n").map(|line| line.trim_end_matches('\r')).collect();
+    let width = lines.iter().map(|line| visible_width(line)).max().unwrap_or_default();
+    let joined: Vec<String> = lines.iter().zip(panel.iter().map(String::as_str).chain(std::iter::repeat(""))).map(|(line, side)| {
+        format!("{line}{}{PANEL_GAP}{side}", " ".repeat(width - visible_width(line)))
+    }).collect();
+    joined.join("\r\n") + "\r"
+}
+
+/// The game's moves in SAN, two to a row, then the material each side is ahead by. The move to the position shown is marked,
+/// moves taken back that `redo` would replay are dimmed, and the rows scroll to keep the marked move in view.
+/// Empty if `columns` are too few for it beside the board
+pub fn move_panel(game: &Game, columns: usize) -> Vec<String> {
+    layout_panel(game, columns, |cell, shown| match shown {
+        Shown::Played => cell,
+        Shown::Current => cell.reversed().to_string(),
+        Shown::Undone => cell.dimmed().to_string(),
+    })
+}
+
+/// How a move in the panel stands to the position shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shown {
+    Played,
+    Current,
+    Undone,
+}
+
+/// The move panel with each move's cell styled by `mark`
+fn layout_panel(game: &Game, columns: usize, mark: impl Fn(String, Shown) -> String) -> Vec<String> {
+    if game.theme.board_width() + PANEL_WIDTH > columns {
+        return Vec::new()
+    }
+
+    let first = &game.history[0];
+    let mut prev = first;
+    let mut sans = Vec::new();
+    for pos in game.history[1..].iter().chain(game.undone.iter().rev()) {
+        let ply = pos.last_ply.expect("positions after the first are reached by a ply");
+        sans.push(san::to_san(prev, ply));
+        prev = pos;
+    }
+    let played = game.history.len() - 1;
+    let black_plies = game.history[1..].iter().filter(|p| p.side_to_move() == Colour::White).count() as u16;
+    let first_move = game.mov - black_plies;
+
+    let mut cells: Vec<String> = sans.iter().enumerate().map(|(i, san)| {
+        let shown = match i + 1 {
+            n if n == played => Shown::Current,
+            n if n > played => Shown::Undone,
+            _ => Shown::Played,
+        };
+        mark(format!("{san:<8}"), shown)
+    }).collect();
+    // Black's reply has a row to itself when the game starts with it
+    let offset = (first.side_to_move() == Colour::Black) as usize;
+    if offset == 1 {
+        cells.insert(0, format!("{:<8}", "..."));
+    }
+    let rows: Vec<String> = cells.chunks(2).enumerate().map(|(i, pair)| format!("{:>3}. {}", first_move as usize + i, pair.concat())).collect();
+    let current_row = (played + offset).saturating_sub(1) / 2;
+    let top = current_row.saturating_sub(PANEL_ROWS - 1).min(rows.len().saturating_sub(PANEL_ROWS));
+
+    let mut panel: Vec<String> = rows.into_iter().skip(top).take(PANEL_ROWS).collect();
+    panel.resize(PANEL_ROWS, String::new());
+    panel.extend(material_lines(game));
+    panel
+}
+
+/// For each side, the pieces it has more of than the other and by how much it's ahead in pawns
+fn material_lines(game: &Game) -> [String; 2] {
+    let board = &game.last_position().board;
+    let theme = Theme { padding: Padding::Narrow, ..game.theme };
+    let mut extra = [String::new(), String::new()];
+    let mut score = 0;
+    for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn] {
+        let diff = board.pieces(piece, Colour::White).count_ones() as i32 - board.pieces(piece, Colour::Black).count_ones() as i32;
+        score += diff * PIECE_VALUES[piece as usize];
+        let side = if diff > 0 { Colour::White } else { Colour::Black };
+        // The opponent's pieces, which are the ones taken
+        for _ in 0..diff.abs() {
+            extra[side as usize].push_str(&theme.glyph(Some((piece, side.opposite()))));
+        }
+    }
+    [Colour::White, Colour::Black].map(|colour| {
+        let ahead = match colour { Colour::White => score, Colour::Black => -score };
+        match ahead > 0 {
+            true => format!("{colour:?} +{ahead}{}", extra[colour as usize]),
+            false => format!("{colour:?}{}", extra[colour as usize]),
+        }
+    })
+}
+
+/// A score in pawns, or moves to mate as `#3`
+pub fn format_eval(score: i32) -> String {
+    match mate_in(score) {
+        Some(moves) => format!("#{moves}"),
+        None => format!("{:+.2}", score as f64 / 100.0),
+    }
+}
+
+/// One line of engine thinking, with the score from the engine's side and the PV in SAN
+pub fn format_thinking(info: &Info, pos: &Position, mov: u16) -> String {
+    format!(
+        "depth {:>2}  score {:>6}  nodes {:>9}  time {:>5.1}s  {}",
+        info.depth, format_eval(info.score), info.nodes, info.elapsed.as_secs_f64(), san::line_to_san(pos, mov, &info.pv)
+    )
+}
+
+/// Asks a question on a plain line, outside of raw mode. Returns `None` at end of input
+pub fn ask(question: &str) -> Option<String> {
+    print!("{question}");
+    io::stdout().flush().ok()?;
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_lowercase()),
+    }
+}
+
+/// A command typed at the prompt in place of a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmd {
+    Help,
+    Moves,
+    Fen,
+    Pgn,
+    Eval,
+    Hint,
+    Analyse,
+    Mate,
+    Book,
+    Tb,
+    Flip,
+    Theme,
+    Undo,
+    Redo,
+    Takeback,
+    Variations,
+    Promote,
+    Delete,
+    Comment,
+    Annotate,
+    Draw,
+    New,
+    Load,
+    Save,
+    Surrender,
+    Quit,
+}
+
+pub struct Command {
+    pub cmd: Cmd,
+    /// The name, then any aliases
+    pub names: &'static [&'static str],
+    /// The argument as shown in help, empty if it takes none
+    pub args: &'static str,
+    /// Whether the argument can be left out
+    pub optional: bool,
+    pub summary: &'static str,
+    /// More on the command for `help <command>`, after the summary
+    pub details: &'static str,
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+
+    pub fn usage(&self) -> String {
+        match (self.args, self.optional) {
+            ("", _) => self.name().to_string(),
+            (args, true) => format!("{} [{args}]", self.name()),
+            (args, false) => format!("{} <{args}>", self.name()),
+        }
+    }
+}
+
+/// Every command, in the order `help` lists them
+pub const COMMANDS: &[Command] = &[
+    Command { cmd: Cmd::Help, names: &["help", "-h"], args: "command", optional: true,
+        summary: "list the commands, or explain one", details: "" },
+    Command { cmd: Cmd::Moves, names: &["moves"], args: "square", optional: true,
+        summary: "list the legal moves, or those from a square", details: "" },
+    Command { cmd: Cmd::Fen, names: &["fen"], args: "", optional: false,
+        summary: "show the position as FEN", details: "" },
+    Command { cmd: Cmd::Pgn, names: &["pgn"], args: "", optional: false,
+        summary: "show the game so far as PGN", details: "" },
+    Command { cmd: Cmd::Eval, names: &["eval"], args: "", optional: false,
+        summary: "show the static evaluation, in pawns from White's side", details: "" },
+    Command { cmd: Cmd::Hint, names: &["hint"], args: "", optional: false,
+        summary: "suggest a move", details: "Searches as long as the engine would in a game against it, or for a second between humans." },
+    Command { cmd: Cmd::Analyse, names: &["analyse"], args: "lines", optional: true,
+        summary: "show the engine's best lines, three unless given a number", details: "" },
+    Command { cmd: Cmd::Mate, names: &["mate"], args: "moves", optional: false,
+        summary: "look for a forced mate and check the key is unique",
+        details: "Every key move is listed, so a composed problem with more than one solution shows its cooks. Press any key to stop looking." },
+    Command { cmd: Cmd::Book, names: &["book"], args: "file", optional: true,
+        summary: "list the opening book's moves, or load a Polyglot book", details: "" },
+    Command { cmd: Cmd::Tb, names: &["tb"], args: "directory", optional: true,
+        summary: "show the tablebase result, or load Syzygy tables", details: "" },
+    Command { cmd: Cmd::Flip, names: &["flip"], args: "white|black|auto", optional: true,
+        summary: "turn the board around, or show it from one side",
+        details: "The board turns to the player on move between humans until it's flipped, and `flip auto` turns it back again. \
+            The view is kept for the rest of the session, even after `new` or `load`." },
+    Command { cmd: Cmd::Theme, names: &["theme"], args: "setting value", optional: true,
+        summary: "show or change how the board is drawn",
+        details: "Settings are glyphs (letters, figurines), colours (classic, plain, contrast), shade (on, off), coords (on, off) \
+            and padding (narrow, wide). `theme save` keeps them in theme.cfg for next time, `theme reset` restores the defaults." },
+    Command { cmd: Cmd::Undo, names: &["undo"], args: "", optional: false,
+        summary: "step back a move, or back to your last turn against the engine", details: "Not available while the clocks run." },
+    Command { cmd: Cmd::Redo, names: &["redo"], args: "", optional: false,
+        summary: "replay a move taken back with `undo`", details: "" },
+    Command { cmd: Cmd::Takeback, names: &["takeback"], args: "", optional: false,
+        summary: "take back your last move", details: "In a two player game your opponent must accept." },
+    Command { cmd: Cmd::Variations, names: &["var", "variations"], args: "number", optional: true,
+        summary: "list the moves played from here, or go into one of them",
+        details: "Playing a move that's been played here before follows it too, and a new one starts a variation. \
+            Not available while the clocks run." },
+    Command { cmd: Cmd::Promote, names: &["promote"], args: "", optional: false,
+        summary: "make the line leading here the main line", details: "" },
+    Command { cmd: Cmd::Delete, names: &["delete"], args: "", optional: false,
+        summary: "remove the last move and everything after it", details: "Not available while the clocks run." },
+    Command { cmd: Cmd::Comment, names: &["comment"], args: "text", optional: true,
+        summary: "comment on the last move, or clear its comment", details: "Comments are written to the PGN by `save` and `pgn`." },
+    Command { cmd: Cmd::Annotate, names: &["annotate"], args: "!|?|!!|??|!?|?!", optional: true,
+        summary: "mark the last move as good or bad, or clear its marks", details: "" },
+    Command { cmd: Cmd::Draw, names: &["draw"], args: "", optional: false,
+        summary: "offer a draw along with your move, i.e. `e4 draw`", details: "" },
+    Command { cmd: Cmd::New, names: &["new"], args: "", optional: false,
+        summary: "start a new game", details: "In a timed game the clocks start again from the full time." },
+    Command { cmd: Cmd::Load, names: &["load"], args: "file", optional: false,
+        summary: "continue the first game of a PGN file", details: "In a timed game the clocks start again from the full time." },
+    Command { cmd: Cmd::Save, names: &["save"], args: "file", optional: false,
+        summary: "write the game to a PGN file", details: "" },
+    Command { cmd: Cmd::Surrender, names: &["surrender", "surr", "sur"], args: "", optional: false,
+        summary: "resign the game", details: "" },
+    Command { cmd: Cmd::Quit, names: &["quit", "exit"], args: "", optional: false,
+        summary: "leave without finishing", details: "" },
+];
+
+pub fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+}
+
+/// Splits a line into a command and its argument, which keeps its case for paths.
+/// A command that takes no argument doesn't match with one, so `draw e4` is still a move
+pub fn parse_command(input: &str) -> Option<(Cmd, &str)> {
+    let input = input.trim();
+    let (name, arg) = input.split_once(char::is_whitespace).map_or((input, ""), |(n, a)| (n, a.trim()));
+    let command = find_command(name)?;
+    match command.args.is_empty() && !arg.is_empty() {
+        true => None,
+        false => Some((command.cmd, arg)),
+    }
+}
+
+/// `help` lists every command, `help <command>` explains one
+pub fn print_help(topic: &str) {
+    if topic.is_empty() {
+        say!("Enter moves in SAN (e4, Nf3, exd5, O-O, e8=Q) or as squares (e2e4, e2 e4). Tab completes, up and down recall earlier lines");
+        for command in COMMANDS {
+            say!("  {:<18} {}", command.usage(), command.summary);
+        }
+        return say!("")
+    }
+
+    let Some(command) = find_command(topic) else {
+        return say!("Unknown command `{topic}`, see `help`\n")
+    };
+    say!("{}", command.usage());
+    say!("  {}", command.summary);
+    if !command.details.is_empty() {
+        say!("  {}", command.details);
+    }
+    if command.names.len() > 1 {
+        say!("  Also: {}", command.names[1..].join(", "));
+    }
+    say!("");
+}
+
+/// Candidates to complete the last word of `line` with: command names for the first word, legal moves in SAN and squares.
+/// Matches ignore case, and are sorted with duplicates removed
+pub fn completions(pos: &Position, line: &str) -> Vec<String> {
+    let word_start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = line[word_start..].to_lowercase();
+    if word.is_empty() {
+        return Vec::new()
+    }
+
+    let commands = COMMANDS.iter().map(|c| c.name().to_string()).filter(|_| word_start == 0);
+    let moves = pos.legal_plies().into_iter().map(|ply| san::to_san(pos, ply));
+    let squares = (0..64).map(|sq| Square::bb_to_str(1 << sq).to_string());
+    let mut candidates: Vec<String> = commands.chain(moves).chain(squares)
+        .filter(|c| c.to_lowercase().starts_with(&word))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
 pub enum InputType {
     String(String),
+    /// The player's time ran out while they were entering a move
+    Flagged,
     Termination
 }
 
+/// Whether input is read as plain lines rather than key by key in raw mode
+static LINE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Switches to line mode when `forced`, or when stdin or stdout isn't a terminal so that the game can be piped or scripted.
+/// Colours are left out too, even with `--lines` at a terminal, so the output can be compared as it is
+pub fn init_line_mode(forced: bool) {
+    let line_mode = forced || !termion::is_tty(&io::stdin()) || !termion::is_tty(&io::stdout());
+    LINE_MODE.store(line_mode, Ordering::Relaxed);
+    if line_mode {
+        colored::control::set_override(false);
+    }
+}
+
+pub fn is_line_mode() -> bool {
+    LINE_MODE.load(Ordering::Relaxed)
+}
+
+/// `text` as a finished line. Raw mode needs `\r\n` to get back to the first column, line mode only `\n`
+pub fn terminal_lines(text: &str, line_mode: bool) -> String {
+    let text = text.replace('\r', "");
+    match line_mode {
+        true => format!("{text}\n"),
+        false => format!("{}\r\n", text.replace('\n', "\r\n")),
+    }
+}
+
+/// Falls back to what the terminal can show: letters if the figurines aren't drawn one column wide,
+/// and narrow padding if a wide board doesn't fit. Returns what was changed. Nothing can be measured in line mode
+pub fn fit_theme(theme: &mut Theme) -> Option<String> {
+    if is_line_mode() {
+        return None
+    }
+    let mut changes = Vec::new();
+    if theme.glyphs == Glyphs::Figurines && figurine_width().is_ok_and(|width| width != FIGURINES.len() * 6) {
+        theme.glyphs = Glyphs::Letters;
+        changes.push("figurines don't line up in this terminal, using letters");
+    }
+    let (columns, _) = termion::terminal_size().unwrap_or((u16::MAX, 0));
+    if theme.padding == Padding::Wide && theme.board_width() > columns as usize {
+        theme.padding = Padding::Narrow;
+        changes.push("the terminal is too narrow for wide padding");
+    }
+    (!changes.is_empty()).then(|| changes.join(", "))
+}
+
+/// Columns the terminal moves the cursor by to draw every figurine
+fn figurine_width() -> io::Result<usize> {
+    let mut stdout = io::stdout().into_raw_mode()?;
+    let (start, _) = stdout.cursor_pos()?;
+    write!(stdout, "{}", FIGURINES.iter().flatten().collect::<String>())?;
+    stdout.flush()?;
+    let (end, _) = stdout.cursor_pos()?;
+    write!(stdout, "\r{}", clear::CurrentLine)?;
+    stdout.flush()?;
+    Ok(end.saturating_sub(start) as usize)
+}
+
+/// How often the clocks are redrawn while waiting for a move
+const CLOCK_REFRESH: Duration = Duration::from_millis(100);
+
+/// The move and ply counters, with both clocks in a timed game
+pub fn status_line(game: &Game) -> String {
+    let mut line = format!("Move: {} Ply: {}", game.mov, game.ply);
+    if let Some(clocks) = &game.clocks {
+        for colour in [Colour::White, Colour::Black] {
+            let time = format!("{:?} {}", colour, clock::format_time(clocks.remaining(colour)));
+            match colour == clocks.turn() {
+                true => line.push_str(&format!("  {}", time.bold())),
+                false => line.push_str(&format!("  {}", time.dimmed())),
+            }
+        }
+    }
+    line
+}
+
+/// Waits up to `timeout` for a key press, or indefinitely if `None`. Returns whether one is ready
+fn wait_for_key(stdin: &File, timeout: Option<Duration>) -> io::Result<bool> {
+    let mut fds = libc::pollfd { fd: stdin.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let timeout = timeout.map_or(-1, |t| t.as_millis() as libc::c_int);
+    // SAFETY: `fds` is a single valid pollfd for the duration of the call
+    match unsafe { libc::poll(&mut fds, 1, timeout) } {
+        -1 => match io::Error::last_os_error() {
+            e if e.kind() == io::ErrorKind::Interrupted => Ok(false),
+            e => Err(e),
+        },
+        ready => Ok(ready > 0),
+    }
+}
+
+/// Runs `work` on another thread, setting the flag it's given if a key is pressed before it finishes.
+/// The key is swallowed. In line mode there's no key to wait for, so `work` runs to the end
+pub fn until_key<T: Send>(work: impl FnOnce(&AtomicBool) -> T + Send) -> io::Result<T> {
+    let stop = AtomicBool::new(false);
+    if is_line_mode() {
+        return Ok(work(&stop))
+    }
+    let _raw = io::stdout().into_raw_mode()?;
+    let stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+
+    std::thread::scope(|s| {
+        let handle = s.spawn(|| work(&stop));
+        while !handle.is_finished() {
+            match wait_for_key(&stdin, Some(CLOCK_REFRESH)) {
+                Ok(false) => (),
+                Ok(true) => {
+                    let _ = io::Read::read(&mut &stdin, &mut [0; 16]);
+                    stop.store(true, Ordering::Relaxed);
+                },
+                Err(e) => {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(e)
+                },
+            }
+        }
+        Ok(handle.join().expect("worker thread panicked"))
+    })
+}
+
 fn update_response(game: &Game, colour: Colour, input: &String) -> (Option<String>, Option<String>) {
     // NOTE: assumes input is lowercase
     
@@ -120,16 +648,31 @@ fn update_response(game: &Game, colour: Colour, input: &String) -> (Option<Strin
 
     let pos = game.last_position();
 
+    if let Some(ply) = san::parse_san(pos, &input.replace(' ', "")) {
+        return (Some(game.stringify_moves_board(colour, ply.mov.from_sq)), None)
+    }
+    // A move with a draw offer, which can be entered as it is
+    if buf.len() > 1 && buf.contains(&"draw") {
+        let rest: String = buf.iter().filter(|&&t| t != "draw").copied().collect();
+        if let Some(ply) = san::parse_san(pos, &rest) {
+            return (Some(game.stringify_moves_board(colour, ply.mov.from_sq)), None)
+        }
+    }
+    // Commands, except `draw` which needs a move with it
+    if parse_command(input).is_some_and(|(cmd, _)| cmd != Cmd::Draw) {
+        return (None, None)
+    }
+
     if buf.len() == 1 {
         match buf[0] {
             "" => unreachable!(),
-            "draw" => (None, Some("Must make a move before offering a draw".to_string())),
+            "draw" => (None, Some("Offer a draw along with your move, i.e. `e4 draw`".to_string())),
             x => {
                 let Some(sq) = Square::from_str(x) else { return (None, None) };
 
                 match pos.board.piece_at(sq.to_bb()) {
                     Some((p, c)) if c == colour => {
-                        (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, sq)), None)
+                        (Some(game.stringify_moves_board(colour, sq)), None)
                     },
                     Some((p, c)) if c != colour => {
                         (None, Some(format!("{p} at {x} is not yours")))
@@ -141,18 +684,18 @@ fn update_response(game: &Game, colour: Colour, input: &String) -> (Option<Strin
     } else if buf.len() == 2 {
         match (buf[0], buf[1]) {
             (x, "draw") | ("draw", x) => {
-                let Some(sq) = Square::from_str(x) else { return (None, Some("Must make a move before offering a draw".to_string())) };
+                let Some(sq) = Square::from_str(x) else { return (None, Some("Finish the move to offer a draw with".to_string())) };
 
                 match pos.board.piece_at(sq.to_bb()) {
                     Some((p, c)) if c == colour => {
-                        (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, sq)), Some("Must make a move before offering a draw".to_string()))
+                        (Some(game.stringify_moves_board(colour, sq)), Some("Finish the move to offer a draw with".to_string()))
                     },
-                    _ => (None, Some("Must make a move before offering a draw".to_string())), // No owned piece at position
+                    _ => (None, Some("Finish the move to offer a draw with".to_string())), // No owned piece at position
                 }
         },
             (x, y) => {
                 let Some(fr_sq) = Square::from_str(x) else { return (None, Some(format!("Unknown input `{x}`"))) };
-                let Some(to_sq) = Square::from_str(y) else { return (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, fr_sq)), Some(format!("Unknown input `{y}`"))) };
+                let Some(to_sq) = Square::from_str(y) else { return (Some(game.stringify_moves_board(colour, fr_sq)), Some(format!("Unknown input `{y}`"))) };
                 // {
                 //     let mov = Movement::new(colour, pos.board.piece_at(to_sq.to_bb()).unwrap().0, fr_sq, to_sq);
                 //     if let Some(ply) = game.validate_movement(mov) {
@@ -163,7 +706,7 @@ fn update_response(game: &Game, colour: Colour, input: &String) -> (Option<Strin
                 match (pos.board.piece_at(fr_sq.to_bb()), pos.board.piece_at(to_sq.to_bb())) {
                     (None, _) => (None, Some(format!("No piece at {x}"))),
                     (Some((fp, fc)), None) if fc == colour => 
-                    (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, fr_sq)), Some(format!("{fp} to {y}"))),
+                    (Some(game.stringify_moves_board(colour, fr_sq)), Some(format!("{fp} to {y}"))),
                     (Some((fp, fc)), None) if fc != colour => (None, Some(format!("{fp} at {x} is not yours"))),
                     (Some((fp, fc)), Some((tp, tc))) if fc == colour && tc != colour => {
                         let pos_moves = match fp {
@@ -176,14 +719,14 @@ fn update_response(game: &Game, colour: Colour, input: &String) -> (Option<Strin
                         };
 
                         if to_sq.as_bb() & pos_moves != 0 {
-                            (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, fr_sq)), Some(format!("{fp} takes {tp}")))
+                            (Some(game.stringify_moves_board(colour, fr_sq)), Some(format!("{fp} takes {tp}")))
                         } else {
-                            (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, fr_sq)), Some(format!("Not a valid attack")))
+                            (Some(game.stringify_moves_board(colour, fr_sq)), Some(format!("Not a valid attack")))
                         }
 
                     },
                     (Some((fp, fc)), Some((tp, tc))) if fc == colour && tc == colour => {
-                        (Some(game.last_position().board.get_possible_moves_board(colour == Colour::White, fr_sq)), Some(format!("TODO: Check for castling")))
+                        (Some(game.stringify_moves_board(colour, fr_sq)), Some(format!("TODO: Check for castling")))
                     },
                     _ => (None, None)
 
@@ -204,25 +747,93 @@ fn validate_input(game: &Game, colour: Colour, input: &String) -> Option<String>
     // game.validate_movement(movement)
 }
 
-pub fn get_input(game: &Game, colour: Colour, prompt: &ColoredString) -> Result<InputType, io::Error> {
+/// Reads one move or command per line, printing the board and any response on plain lines without moving the cursor.
+/// Input is echoed when `echo`, so that a transcript of piped input reads like a session at the terminal
+fn get_line_input(game: &Game, colour: Colour, prompt: &ColoredString, input: &mut impl BufRead, out: &mut impl Write, echo: bool) -> io::Result<InputType> {
+    write!(out, "{}", terminal_lines(&format!("{}\n", game.stringify_board(colour)), true))?;
+    writeln!(out, "{}", status_line(game))?;
+
+    loop {
+        write!(out, "{prompt}")?;
+        out.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            return Ok(InputType::Termination)
+        }
+        if game.clocks.as_ref().is_some_and(|c| c.flagged().is_some()) {
+            writeln!(out)?;
+            return Ok(InputType::Flagged)
+        }
+        let line = line.trim();
+        if echo {
+            writeln!(out, "{line}")?;
+        }
+        if line.is_empty() {
+            continue
+        }
+        // As in raw mode, input with a response such as `draw` on its own isn't entered
+        match update_response(game, colour, &line.to_lowercase()) {
+            (_, Some(resp)) => writeln!(out, "{resp}")?,
+            (_, None) => return Ok(InputType::String(line.to_string())),
+        }
+    }
+}
+
+/// Reads a move or command, showing the moves of the piece being entered. `inp_buf` keeps the history between calls
+pub fn get_input(game: &Game, colour: Colour, prompt: &ColoredString, inp_buf: &mut InputBuffer) -> Result<InputType, io::Error> {
+    if is_line_mode() {
+        let echo = !termion::is_tty(&io::stdin());
+        return get_line_input(game, colour, prompt, &mut io::stdin().lock(), &mut io::stdout(), echo)
+    }
     let mut stdout = io::stdout().into_raw_mode()?;
     
-    write!(stdout, "{}\n\n", format!("{}", game.stringify_board(colour)))?;
-    write!(stdout, "Move: {} Ply: {}\r\n", game.mov, game.ply)?;
+    let (columns, _) = termion::terminal_size().unwrap_or((u16::MAX, 0));
+    let panel = move_panel(game, columns as usize);
+    write!(stdout, "{}\n\n", beside(&game.stringify_board(colour), &panel))?;
+    write!(stdout, "{}\r\n", status_line(game))?;
     write!(stdout, "{}{}", prompt, cursor::BlinkingBar)?;
     stdout.flush().unwrap();
     
-    let stdin = io::stdin();
-    let mut inp_buf = InputBuffer::default();
+    // Read unbuffered so that polling sees every key that hasn't been handled
+    let stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+    let mut keys = (&stdin).keys();
+    inp_buf.clear();
     let mut board = game.stringify_board(colour);
     let mut response: Option<String> = None;
+    let refresh = game.clocks.as_ref().map(|_| CLOCK_REFRESH);
 
-    for key in stdin.keys() {
-        match key.unwrap() {
+    loop {
+        if !wait_for_key(&stdin, refresh)? {
+            if let Some(clocks) = &game.clocks {
+                if clocks.flagged().is_some() {
+                    write!(stdout, "\r\n")?;
+                    return Ok(InputType::Flagged)
+                }
+                // Redraw the status line above the prompt, leaving the cursor where it was
+                write!(stdout, "{}\r{}{}{}{}", cursor::Save, cursor::Up(1), clear::CurrentLine, status_line(game), cursor::Restore)?;
+                stdout.flush()?;
+            }
+            continue
+        }
+        let Some(key) = keys.next() else { break };
+        match key? {
             Key::Home => inp_buf.jump_start(),
             Key::End => inp_buf.jump_end(),
             Key::Left => inp_buf.left_char(),
             Key::Right => inp_buf.right_char(),
+            Key::Up => {
+                inp_buf.history_prev();
+                let (brd, resp) = update_response(game, colour, &inp_buf.peak_trimmed().to_lowercase());
+                if let Some(b) = brd { board = b } else { board = game.stringify_board(colour) }
+                response = resp;
+            },
+            Key::Down => {
+                inp_buf.history_next();
+                let (brd, resp) = update_response(game, colour, &inp_buf.peak_trimmed().to_lowercase());
+                if let Some(b) = brd { board = b } else { board = game.stringify_board(colour) }
+                response = resp;
+            },
             Key::Backspace => {
                 inp_buf.backspace();
                 let (brd, resp) = update_response(game, colour, &inp_buf.peak_trimmed().to_lowercase());
@@ -241,9 +852,15 @@ pub fn get_input(game: &Game, colour: Colour, prompt: &ColoredString) -> Result<
                 response = resp;
                 if response.is_none() {
                     write!(stdout, "\r\n")?;
-                    return Ok(InputType::String(inp_buf.take_trimmed()))
+                    return Ok(InputType::String(inp_buf.submit()))
                 }
             },
+            Key::Char('\t') => {
+                let listed = inp_buf.complete(game.last_position());
+                let (brd, resp) = update_response(game, colour, &inp_buf.peak_trimmed().to_lowercase());
+                if let Some(b) = brd { board = b } else { board = game.stringify_board(colour) }
+                response = listed.or(resp);
+            },
             Key::Char(ch) => {
                 inp_buf.insert_char(ch);
                 let (brd, resp) = update_response(game, colour, &inp_buf.peak_trimmed().to_lowercase());
@@ -257,7 +874,7 @@ pub fn get_input(game: &Game, colour: Colour, prompt: &ColoredString) -> Result<
             _ => {}
         }
 
-        inp_buf.render(&board, &prompt, &response, &mut stdout).unwrap();
+        inp_buf.render(&board, &panel, prompt, &response, &mut stdout).unwrap();
         stdout.flush().unwrap();
     }
 
@@ -276,3 +893,150 @@ impl Match {
 
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn typed(text: &str) -> InputBuffer {
+        let mut buf = InputBuffer::default();
+        text.chars().for_each(|ch| buf.insert_char(ch));
+        buf
+    }
+
+    #[test]
+    fn line_mode() {
+        // As `init_line_mode` leaves it, whether or not the tests run at a terminal
+        colored::control::set_override(false);
+        let game = Game::new();
+        let prompt = "White to play: ".normal();
+        let mut out = Vec::new();
+        let mut input = io::Cursor::new("\n  draw\ne4 draw\n");
+        let read = get_line_input(&game, Colour::White, &prompt, &mut input, &mut out, true).unwrap();
+        assert!(matches!(read, InputType::String(line) if line == "e4 draw"));
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains(['\r', '\x1b']), "{out:?}");
+        assert!(out.contains("\n~ A B C D E F G H\n\nMove: 1 Ply: 1\n"), "{out}");
+        assert!(out.ends_with("White to play: \nWhite to play: draw\nOffer a draw along with your move, i.e. `e4 draw`\nWhite to play: e4 draw\n"), "{out}");
+
+        let read = get_line_input(&game, Colour::White, &prompt, &mut io::Cursor::new(""), &mut Vec::new(), false).unwrap();
+        assert!(matches!(read, InputType::Termination));
+
+        // What the game prints between prompts, the board keeping none of the `\r`s it's drawn with
+        let mut game = Game::new();
+        game.make_ply(game.last_position().parse_uci("e2e4").unwrap());
+        let (mut lines, mut raw) = (String::new(), String::new());
+        for text in [game.stringify_board(Colour::Black), status_line(&game), game.as_pgn()] {
+            lines.push_str(&terminal_lines(&text, true));
+            raw.push_str(&terminal_lines(&text, false));
+        }
+        assert!(!lines.contains('\r'), "{lines:?}");
+        assert_eq!(raw, lines.replace('\n', "\r\n"));
+    }
+
+    /// The move panel with `[` before the marked move and `(` before those taken back, in place of colours
+    fn plain_panel(game: &Game) -> Vec<String> {
+        layout_panel(game, 80, |cell, shown| match shown {
+            Shown::Played => cell,
+            Shown::Current => format!("[{cell}"),
+            Shown::Undone => format!("({cell}"),
+        })
+    }
+
+    #[test]
+    fn move_list() {
+        let mut game = Game::new();
+        for mov in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "d2d4"] {
+            let ply = game.last_position().parse_uci(mov).unwrap();
+            game.make_ply(ply);
+        }
+        game.undo();
+        let panel = plain_panel(&game);
+        assert_eq!(panel.len(), 9);
+        assert_eq!(panel[..4], ["  1. e4      d5      ", "  2. exd5    Qxd5    ", "  3. Nc3     [Qa5     ", "  4. (d4      "]);
+        assert_eq!(panel[7..], ["White", "Black"]);
+
+        // Long games scroll to keep the current move in view
+        for mov in ["g1f3", "g8f6", "f1c4", "c8g4", "e1g1", "b8c6", "d2d3", "e8c8", "c1d2", "e7e5", "f3e5"] {
+            let ply = game.last_position().parse_uci(mov).unwrap();
+            game.make_ply(ply);
+        }
+        let panel = plain_panel(&game);
+        assert!(panel[0].starts_with("  3. Nc3"), "{panel:?}");
+        assert_eq!(panel[6], "  9. [Nxe5    ");
+        assert_eq!(panel[7..], ["White +1 P", "Black"]);
+        // Too narrow to fit beside the board
+        assert!(move_panel(&game, game.theme.board_width() + PANEL_WIDTH - 1).is_empty());
+
+        let lines: Vec<String> = beside("ab\r\nabcd\r", &["x".to_string(), "y".to_string()]).split("\r\n").map(str::to_string).collect();
+        assert_eq!(lines, [format!("ab  {PANEL_GAP}x"), format!("abcd{PANEL_GAP}y\r")]);
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("help"), Some((Cmd::Help, "")));
+        assert_eq!(parse_command("  Book  Books/Main.bin "), Some((Cmd::Book, "Books/Main.bin")));
+        assert_eq!(parse_command("surr"), Some((Cmd::Surrender, "")));
+        // Commands without arguments don't swallow a move
+        assert_eq!(parse_command("draw e4"), None);
+        assert_eq!(parse_command("e4"), None);
+        assert!(COMMANDS.iter().all(|c| find_command(c.name()).is_some_and(|f| f.cmd == c.cmd)));
+    }
+
+    #[test]
+    fn history() {
+        let mut buf = InputBuffer::default();
+        for line in ["e4", "hint", "hint", "Nf3"] {
+            buf.set_line(line);
+            buf.submit();
+        }
+        assert_eq!(buf.history, ["e4", "hint", "Nf3"]);
+
+        buf.set_line("d");
+        buf.history_prev();
+        buf.history_prev();
+        assert_eq!(buf.peak_trimmed(), "hint");
+        buf.history_prev();
+        buf.history_prev();
+        assert_eq!(buf.peak_trimmed(), "e4");
+        buf.history_next();
+        buf.history_next();
+        buf.history_next();
+        // Back to what was being typed
+        assert_eq!((buf.peak_trimmed(), buf.cursor), ("d".to_string(), 1));
+    }
+
+    #[test]
+    fn completion() {
+        let pos = *Game::new().last_position();
+        assert_eq!(completions(&pos, "n"), ["Na3", "Nc3", "Nf3", "Nh3", "new"]);
+        assert_eq!(completions(&pos, "moves e"), ["e1", "e2", "e3", "e4", "e5", "e6", "e7", "e8"]);
+        assert!(completions(&pos, "e4 ").is_empty());
+
+        let mut buf = typed("he");
+        assert_eq!(buf.complete(&pos), None);
+        assert_eq!(buf.peak_trimmed(), "help");
+
+        let mut buf = typed("nf");
+        buf.complete(&pos);
+        assert_eq!(buf.peak_trimmed(), "Nf3");
+
+        // Only the word before the cursor is completed
+        let mut buf = typed("un e4");
+        (0..3).for_each(|_| buf.left_char());
+        assert_eq!(buf.complete(&pos), None);
+        assert_eq!(buf.peak_trimmed(), "undo e4");
+
+        let mut buf = typed("un");
+        assert_eq!(buf.complete(&pos), None);
+        assert_eq!(buf.peak_trimmed(), "undo");
+
+        let mut buf = typed("na");
+        assert_eq!(buf.complete(&pos), None);
+        assert_eq!(buf.peak_trimmed(), "Na3");
+        let mut buf = typed("N");
+        assert_eq!(buf.complete(&pos), Some("Na3  Nc3  Nf3  Nh3  new".to_string()));
+        assert_eq!(buf.peak_trimmed(), "N");
+    }
+}
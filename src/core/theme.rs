@@ -0,0 +1,213 @@
+use colored::{ ColoredString, Colorize };
+
+use crate::ply::{ Colour, Piece };
+
+/// Where the theme is read from at start up and written to by `theme save`, in the working directory
+pub const THEME_FILE: &str = "theme.cfg";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    #[default]
+    Letters,
+    /// Unicode chess figurines, outlined for White and filled for Black
+    Figurines,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// Blue for White, red for Black
+    #[default]
+    Classic,
+    /// No colours on the pieces, with Black's letters in lower case as in FEN
+    Plain,
+    /// Bold white for White, bold yellow for Black
+    Contrast,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Two columns a square, the piece after a space
+    #[default]
+    Narrow,
+    /// Three columns a square, the piece in the middle
+    Wide,
+}
+
+/// How boards are drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub glyphs: Glyphs,
+    pub scheme: Scheme,
+    /// Shade the dark squares
+    pub shade: bool,
+    /// Label the ranks and files
+    pub coordinates: bool,
+    pub padding: Padding,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            glyphs: Glyphs::Letters,
+            scheme: Scheme::Classic,
+            shade: false,
+            coordinates: true,
+            padding: Padding::Narrow,
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true"  | "on"  | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(format!("Expected on or off, got `{value}`")),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+impl Theme {
+    /// Settings as `name value` pairs, as `set` takes them
+    pub fn settings(&self) -> [(&'static str, &'static str); 5] {
+        [
+            ("glyphs", match self.glyphs { Glyphs::Letters => "letters", Glyphs::Figurines => "figurines" }),
+            ("colours", match self.scheme { Scheme::Classic => "classic", Scheme::Plain => "plain", Scheme::Contrast => "contrast" }),
+            ("shade", on_off(self.shade)),
+            ("coords", on_off(self.coordinates)),
+            ("padding", match self.padding { Padding::Narrow => "narrow", Padding::Wide => "wide" }),
+        ]
+    }
+
+    /// Sets a setting by name, names and values are case insensitive
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let value = value.to_lowercase();
+        match (name.to_lowercase().as_str(), value.as_str()) {
+            ("glyphs", "letters")    => self.glyphs = Glyphs::Letters,
+            ("glyphs", "figurines")  => self.glyphs = Glyphs::Figurines,
+            ("glyphs", _)            => return Err(format!("Expected letters or figurines, got `{value}`")),
+            ("colours" | "colors", "classic")  => self.scheme = Scheme::Classic,
+            ("colours" | "colors", "plain")    => self.scheme = Scheme::Plain,
+            ("colours" | "colors", "contrast") => self.scheme = Scheme::Contrast,
+            ("colours" | "colors", _)          => return Err(format!("Expected classic, plain or contrast, got `{value}`")),
+            ("shade", _)             => self.shade = parse_bool(&value)?,
+            ("coords", _)            => self.coordinates = parse_bool(&value)?,
+            ("padding", "narrow")    => self.padding = Padding::Narrow,
+            ("padding", "wide")      => self.padding = Padding::Wide,
+            ("padding", _)           => return Err(format!("Expected narrow or wide, got `{value}`")),
+            _ => return Err(format!("Unknown setting `{name}`")),
+        }
+        Ok(())
+    }
+
+    /// Reads `name = value` lines, skipping blank lines and `#` comments
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut theme = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue
+            }
+            let (name, value) = line.split_once('=').ok_or_else(|| format!("Line {}: expected `name = value`", i + 1))?;
+            theme.set(name.trim(), value.trim()).map_err(|e| format!("Line {}: {e}", i + 1))?;
+        }
+        Ok(theme)
+    }
+
+    /// The theme in `path`, or the default one if there is no such file
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{path}: {e}")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{path}: {e}")),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text: String = self.settings().iter().map(|(name, value)| format!("{name} = {value}\n")).collect();
+        std::fs::write(path, text).map_err(|e| format!("{path}: {e}"))
+    }
+
+    /// Terminal columns a square takes
+    pub fn square_width(&self) -> usize {
+        match self.padding {
+            Padding::Narrow => 2,
+            Padding::Wide => 3,
+        }
+    }
+
+    /// Terminal columns a rendered board takes
+    pub fn board_width(&self) -> usize {
+        8 * self.square_width() + self.coordinates as usize
+    }
+
+    /// A square's text, padded to `square_width`
+    pub fn glyph(&self, piece: Option<(Piece, Colour)>) -> String {
+        let glyph = match (self.glyphs, piece) {
+            (_, None) => '·',
+            (Glyphs::Letters, Some((piece, colour))) => {
+                let letter = match piece {
+                    Piece::Pawn   => 'P',
+                    Piece::Knight => 'N',
+                    Piece::Bishop => 'B',
+                    Piece::Rook   => 'R',
+                    Piece::Queen  => 'Q',
+                    Piece::King   => 'K',
+                };
+                match (self.scheme, colour) {
+                    (Scheme::Plain, Colour::Black) => letter.to_ascii_lowercase(),
+                    _ => letter,
+                }
+            },
+            (Glyphs::Figurines, Some((piece, colour))) => FIGURINES[colour as usize][piece as usize],
+        };
+        match self.padding {
+            Padding::Narrow => format!(" {glyph}"),
+            Padding::Wide => format!(" {glyph} "),
+        }
+    }
+
+    /// Colours a piece's text as the scheme has it
+    pub fn paint(&self, text: &str, colour: Colour) -> ColoredString {
+        match (self.scheme, colour) {
+            (Scheme::Classic, Colour::White) => text.bright_blue(),
+            (Scheme::Classic, Colour::Black) => text.bright_red(),
+            (Scheme::Plain, _) => text.normal(),
+            (Scheme::Contrast, Colour::White) => text.bright_white().bold(),
+            (Scheme::Contrast, Colour::Black) => text.bright_yellow().bold(),
+        }
+    }
+}
+
+/// Indexed by colour then piece, in the order of `Piece`
+pub const FIGURINES: [[char; 6]; 2] = [
+    ['♙', '♘', '♗', '♖', '♕', '♔'],
+    ['♟', '♞', '♝', '♜', '♛', '♚'],
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        let mut theme = Theme::default();
+        theme.set("Glyphs", "Figurines").unwrap();
+        theme.set("shade", "on").unwrap();
+        assert!(theme.set("padding", "huge").is_err());
+        assert!(theme.set("size", "8").is_err());
+        assert_eq!(theme.glyph(Some((Piece::Knight, Colour::Black))), " ♞");
+
+        // Round trips through the file format
+        let text: String = theme.settings().iter().map(|(name, value)| format!("{name} = {value}\n")).collect();
+        assert_eq!(Theme::parse(&text), Ok(theme));
+        assert_eq!(Theme::parse("# comment\n\ncolours = plain  # lower case for black\n").unwrap().scheme, Scheme::Plain);
+        assert_eq!(Theme::parse("coords on\n"), Err("Line 1: expected `name = value`".to_string()));
+
+        let plain = Theme { scheme: Scheme::Plain, padding: Padding::Wide, ..Theme::default() };
+        assert_eq!(plain.glyph(Some((Piece::Queen, Colour::Black))), " q ");
+        assert_eq!((plain.board_width(), Theme::default().board_width()), (25, 17));
+    }
+}
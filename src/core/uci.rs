@@ -0,0 +1,229 @@
+use std::io::{ self, BufRead };
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate  :: {
+    engine :: {
+        options :: { OptionKind, Options },
+        search  :: { mate_in, Info, Limits },
+        time    :: Clock,
+        worker  :: Worker,
+    },
+    game   :: Game,
+    ply    :: Colour,
+};
+
+use super::INITIAL_FEN;
+
+const ENGINE_NAME: &str = "ACP";
+const ENGINE_AUTHOR: &str = "Girv98";
+
+/// Keywords that can end a `searchmoves` list
+const GO_KEYWORDS: [&str; 12] = [
+    "searchmoves", "ponder", "wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "infinite"
+];
+
+/// Runs the UCI protocol on stdin/stdout until `quit` or end of input
+pub fn run() {
+    let mut uci = Uci::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if !uci.handle(&line) {
+            break
+        }
+    }
+    uci.worker.stop();
+}
+
+pub fn format_score(score: i32) -> String {
+    match mate_in(score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {score}"),
+    }
+}
+
+pub fn format_info(info: &Info) -> String {
+    let pv: Vec<String> = info.pv.iter().map(|ply| ply.to_uci()).collect();
+    format!(
+        "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {} tbhits {} pv {}",
+        info.depth, info.seldepth, info.multipv, format_score(info.score), info.nodes, info.nps(),
+        info.elapsed.as_millis(), info.hashfull, info.tbhits, pv.join(" ")
+    )
+}
+
+struct Uci {
+    game: Game,
+    worker: Worker,
+}
+
+impl Uci {
+    fn new() -> Self {
+        Self { game: Game::new(), worker: Worker::new(Options::default()) }
+    }
+
+    /// Handles a single line of input. Returns false when the engine should exit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = tokens.split_first() else { return true };
+
+        match cmd {
+            "uci" => self.identify(),
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.worker.searcher().clear();
+                self.game = Game::new();
+            },
+            "setoption" => self.set_option(args),
+            "position" => self.position(args),
+            "go" => self.go(args),
+            "stop" => { self.worker.stop(); },
+            "ponderhit" => self.worker.ponderhit(),
+            "quit" => return false,
+            "debug" | "register" => {},
+            _ => println!("info string Unknown command `{cmd}`"),
+        }
+        true
+    }
+
+    fn identify(&mut self) {
+        println!("id name {ENGINE_NAME} {}", env!("CARGO_PKG_VERSION"));
+        println!("id author {ENGINE_AUTHOR}");
+        for opt in self.worker.searcher().options.list() {
+            match opt.kind {
+                OptionKind::Check { default } => println!("option name {} type check default {default}", opt.name),
+                OptionKind::Spin { default, min, max } => {
+                    println!("option name {} type spin default {default} min {min} max {max}", opt.name)
+                },
+                OptionKind::String { default } => {
+                    let default = if default.is_empty() { "<empty>" } else { &default };
+                    println!("option name {} type string default {default}", opt.name)
+                },
+            }
+        }
+        println!("option name Clear Hash type button");
+        println!("uciok");
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        // setoption name <id> [value <x>], where both may contain spaces
+        let Some(name_at) = args.iter().position(|&t| t == "name") else { return };
+        let value_at = args.iter().position(|&t| t == "value").unwrap_or(args.len());
+        let name = args[name_at + 1..value_at.max(name_at + 1)].join(" ");
+        let value = match args.get(value_at + 1..).map(|v| v.join(" ")).unwrap_or_default() {
+            value if value == "<empty>" => String::new(),
+            value => value,
+        };
+
+        if name.eq_ignore_ascii_case("clear hash") {
+            self.worker.searcher().clear();
+        } else if let Err(e) = self.worker.searcher().set_option(&name, &value) {
+            println!("info string {e}");
+        }
+    }
+
+    fn position(&mut self, args: &[&str]) {
+        let moves_at = args.iter().position(|&t| t == "moves").unwrap_or(args.len());
+        let game = match args.first() {
+            Some(&"startpos") => Game::try_from_fen(INITIAL_FEN),
+            Some(&"fen") => {
+                // Tolerate FENs that leave out the clocks
+                let mut fen: Vec<&str> = args[1..moves_at].to_vec();
+                if fen.len() == 4 { fen.extend(["0", "1"]) }
+                Game::try_from_fen(&fen.join(" "))
+            },
+            _ => Err("Expected `startpos` or `fen`".to_string()),
+        };
+
+        let mut game = match game {
+            Ok(game) => game,
+            Err(e) => return println!("info string {e}"),
+        };
+        for mov in args.iter().skip(moves_at + 1) {
+            let Some(ply) = game.last_position().parse_uci(mov) else {
+                println!("info string Illegal move `{mov}`");
+                break
+            };
+            game.make_ply(ply);
+        }
+        self.game = game;
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        let mut limits = Limits::default();
+        let (mut infinite, mut ponder) = (false, false);
+        let (mut time, mut inc, mut movestogo) = (None, 0, None);
+        let side = self.game.last_position().side_to_move();
+
+        let mut tokens = args.iter().peekable();
+        while let Some(&token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|v| v.parse::<u64>().ok());
+            match (token, side) {
+                ("wtime", Colour::White) | ("btime", Colour::Black) => time = value(),
+                ("winc",  Colour::White) | ("binc",  Colour::Black) => inc = value().unwrap_or(0),
+                ("wtime" | "btime" | "winc" | "binc", _) => { value(); },
+                ("movestogo", _) => movestogo = value(),
+                ("depth", _) => limits.depth = value().map(|d| d.min(u8::MAX as u64) as u8),
+                ("nodes", _) => limits.nodes = value(),
+                ("mate", _) => limits.mate = value().map(|m| m.min(u8::MAX as u64) as u8),
+                ("movetime", _) => limits.movetime = value().map(Duration::from_millis),
+                ("infinite", _) => infinite = true,
+                ("ponder", _) => ponder = true,
+                ("searchmoves", _) => {
+                    while let Some(mov) = tokens.next_if(|t| !GO_KEYWORDS.contains(t)) {
+                        if let Some(ply) = self.game.last_position().parse_uci(mov) {
+                            limits.searchmoves.push(ply);
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if let Some(n) = limits.mate {
+            limits.depth.get_or_insert((2 * n).saturating_add(1));
+        }
+        if let (Some(time), None) = (time, limits.movetime) {
+            let (time, inc) = (Duration::from_millis(time), Duration::from_millis(inc));
+            limits.clock = Some(Clock { time, inc, movestogo: movestogo.map(|m| m.min(u32::MAX as u64) as u32) });
+        }
+
+        let (stop, pondering) = (self.worker.stop_handle(), self.worker.ponder_handle());
+        let report = |info: &Info| println!("{}", format_info(info));
+        self.worker.start(&self.game, limits, ponder, report, move |result| {
+            // The best move mustn't be sent before `stop` or `ponderhit` in these modes
+            while (infinite || pondering.load(Ordering::Relaxed)) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            match (result.best, result.ponder) {
+                (Some(best), Some(ponder)) => println!("bestmove {} ponder {}", best.to_uci(), ponder.to_uci()),
+                (Some(best), None) => println!("bestmove {}", best.to_uci()),
+                (None, _) => println!("bestmove 0000"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::search::MATE;
+
+    #[test]
+    fn position_with_moves() {
+        let mut uci = Uci::new();
+        uci.handle("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(uci.game.as_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        uci.handle("position fen 4k3/P7/8/8/8/8/8/4K3 w - - 0 1 moves a7a8q e8d7");
+        assert_eq!(uci.game.as_fen(), "Q7/3k4/8/8/8/8/8/4K3 w - - 1 2");
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(MATE - 3), "mate 2");
+        assert_eq!(format_score(-MATE + 2), "mate -1");
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt::Display;
+use std::io::{ self, BufRead, Write };
+use std::sync::{ atomic::{ AtomicBool, Ordering }, Arc, Mutex };
+use std::time::Duration;
+
+use crate  :: {
+    engine :: {
+        options :: Options,
+        search  :: { mate_in, Info, Limits },
+        time    :: Clock,
+        worker  :: Worker,
+    },
+    game   :: { Game, State },
+    ply    :: Colour,
+};
+
+/// Runs the Chess Engine Communication Protocol (xboard/winboard) on stdin/stdout until `quit` or end of input
+pub fn run() {
+    let mut xboard = Xboard::new(Output(Arc::new(Mutex::new(io::stdout()))));
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if !xboard.handle(&line) {
+            break
+        }
+    }
+    xboard.abort();
+}
+
+/// CECP reports mate scores as 100000 + moves
+fn format_score(score: i32) -> i32 {
+    match mate_in(score) {
+        Some(moves) if moves > 0 => 100_000 + moves,
+        Some(moves) => -100_000 + moves,
+        None => score,
+    }
+}
+
+/// Thinking output: ply score time(cs) nodes pv
+fn format_info(info: &Info) -> String {
+    let pv: Vec<String> = info.pv.iter().map(|ply| ply.to_uci()).collect();
+    format!("{} {} {} {} {}", info.depth, format_score(info.score), info.elapsed.as_millis() / 10, info.nodes, pv.join(" "))
+}
+
+fn result_comment(state: &State) -> String {
+    let reason = match state {
+        State::White(_) => "White mates".to_string(),
+        State::Black(_) => "Black mates".to_string(),
+        State::Draw(kind) => format!("Draw by {kind:?}"),
+        State::Terminated => "Terminated".to_string(),
+    };
+    format!("{} {{{reason}}}", state.result())
+}
+
+/// Parses `level` time: minutes, or minutes:seconds
+fn parse_base(base: &str) -> Option<u64> {
+    match base.split_once(':') {
+        Some((m, s)) => Some(m.parse::<u64>().ok()? * 60_000 + s.parse::<u64>().ok()? * 1000),
+        None => Some(base.parse::<u64>().ok()? * 60_000),
+    }
+}
+
+/// Where replies go, shared with the search thread so the engine's move can be sent as soon as it's found
+#[derive(Clone)]
+struct Output(Arc<Mutex<dyn Write + Send>>);
+
+impl Output {
+    fn line(&self, text: impl Display) {
+        let mut out = self.0.lock().expect("output isn't poisoned");
+        writeln!(out, "{text}").and_then(|()| out.flush()).expect("failed writing to the GUI");
+    }
+}
+
+/// `level` time control
+#[derive(Debug, Default, Clone, Copy)]
+struct Level {
+    moves_per_session: u32,
+    inc: Duration,
+}
+
+struct Xboard {
+    out: Output,
+    game: Game,
+    worker: Worker,
+    /// In force mode the engine only checks moves and never thinks
+    force: bool,
+    engine_colour: Colour,
+    post: bool,
+    level: Level,
+    /// Fixed time per move from `st`
+    movetime: Option<Duration>,
+    depth: Option<u8>,
+    /// Engine's remaining time, from `time`
+    time: Option<Duration>,
+    /// Set when a running search should be abandoned without playing its move
+    discard: Arc<AtomicBool>,
+}
+
+impl Xboard {
+    fn new(out: Output) -> Self {
+        Self {
+            out,
+            game: Game::new(),
+            worker: Worker::new(Options::default()),
+            force: false,
+            engine_colour: Colour::Black,
+            post: false,
+            level: Level::default(),
+            movetime: None,
+            depth: None,
+            time: None,
+            discard: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Handles a single line of input. Returns false when the engine should exit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = tokens.split_first() else { return true };
+
+        // Commands that may arrive while thinking and mustn't disturb the search
+        match cmd {
+            "time" => self.time = args.first().and_then(|t| t.parse::<u64>().ok()).map(|cs| Duration::from_millis(cs * 10)),
+            "ping" => self.out.line(format!("pong {}", args.first().unwrap_or(&""))),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "otim" | "accepted" | "rejected" | "computer" | "random" | "hard" | "easy" | "." => {},
+            _ => return self.handle_game(cmd, args),
+        }
+        true
+    }
+
+    /// Handles commands that change or depend on the game
+    fn handle_game(&mut self, cmd: &str, args: &[&str]) -> bool {
+        match cmd {
+            // Move now: the move is still played below
+            "?" if self.worker.is_searching() => self.worker.stop_handle().store(true, Ordering::Relaxed),
+            // These throw away the move being thought about, so mustn't wait for it
+            "new" | "force" | "result" | "undo" | "remove" | "setboard" | "quit" => self.abort(),
+            _ => {},
+        }
+        // Everything else needs the engine's move, if any, to be on the board first
+        self.collect();
+
+        match cmd {
+            "xboard" | "?" => {},
+            "protover" => {
+                self.out.line(format!("feature myname=\"ACP {}\" setboard=1 usermove=1 time=1 draw=0 sigint=0 sigterm=0", env!("CARGO_PKG_VERSION")));
+                self.out.line("feature reuse=1 analyze=0 colors=0 ping=1 playother=1 san=0 done=1");
+            },
+            "new" => {
+                self.game = Game::new();
+                self.worker.searcher().clear();
+                self.force = false;
+                self.engine_colour = Colour::Black;
+                self.depth = None;
+                self.movetime = None;
+            },
+            "force" | "result" => self.force = true,
+            "go" => {
+                self.force = false;
+                self.engine_colour = self.game.last_position().side_to_move();
+                self.think();
+            },
+            "playother" => {
+                self.force = false;
+                self.engine_colour = self.game.last_position().side_to_move().opposite();
+            },
+            "usermove" => self.user_move(args.first().copied().unwrap_or_default()),
+            "undo" => {
+                self.game.undo();
+            },
+            "remove" => {
+                self.game.undo();
+                self.game.undo();
+            },
+            "setboard" => match Game::try_from_fen(&args.join(" ")) {
+                Ok(game) => self.game = game,
+                Err(e) => self.out.line(format!("tellusererror Illegal position: {e}")),
+            },
+            "level" => self.set_level(args),
+            "st" => {
+                self.movetime = args.first().and_then(|s| s.parse::<f64>().ok()).map(Duration::from_secs_f64);
+            },
+            "sd" => self.depth = args.first().and_then(|d| d.parse().ok()),
+            "quit" => return false,
+            _ => self.out.line(format!("Error (unknown command): {cmd}")),
+        }
+        true
+    }
+
+    /// Stops any running search without playing its move
+    fn abort(&mut self) {
+        if !self.worker.is_searching() {
+            return
+        }
+        self.discard.store(true, Ordering::Relaxed);
+        self.worker.stop();
+    }
+
+    /// Waits for the engine to finish thinking and plays its move
+    fn collect(&mut self) {
+        if let Some(result) = self.worker.wait() {
+            if !self.discard.load(Ordering::Relaxed) {
+                if let Some(best) = result.best {
+                    self.game.make_ply(best);
+                    if let Some(state) = &self.game.state {
+                        self.out.line(result_comment(state));
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_level(&mut self, args: &[&str]) {
+        // level MPS BASE INC
+        let [mps, base, inc] = args else { return self.out.line("Error (bad arguments): level") };
+        let (Ok(mps), Some(_), Ok(inc)) = (mps.parse(), parse_base(base), inc.parse::<f64>()) else {
+            return self.out.line("Error (bad arguments): level")
+        };
+        self.level = Level { moves_per_session: mps, inc: Duration::from_secs_f64(inc) };
+        self.movetime = None;
+    }
+
+    fn user_move(&mut self, mov: &str) {
+        let Some(ply) = self.game.last_position().parse_uci(mov) else {
+            return self.out.line(format!("Illegal move: {mov}"))
+        };
+        self.game.make_ply(ply);
+
+        if !self.force && self.game.last_position().side_to_move() == self.engine_colour {
+            self.think();
+        }
+    }
+
+    fn think(&mut self) {
+        if self.game.state.is_some() {
+            return
+        }
+
+        let mut limits = Limits { depth: self.depth, movetime: self.movetime, ..Default::default() };
+        if let (None, Some(time)) = (limits.movetime, self.time) {
+            let movestogo = match self.level.moves_per_session {
+                0 => None,
+                mps => Some(mps - (self.game.mov as u32 - 1) % mps),
+            };
+            limits.clock = Some(Clock { time, inc: self.level.inc, movestogo });
+        }
+
+        self.discard.store(false, Ordering::Relaxed);
+        let discard = self.discard.clone();
+        let (post, out) = (self.post, self.out.clone());
+        let report = move |info: &Info| if post { out.line(format_info(info)) };
+        let out = self.out.clone();
+        self.worker.start(&self.game, limits, false, report, move |result| {
+            if let (false, Some(best)) = (discard.load(Ordering::Relaxed), result.best) {
+                out.line(format!("move {}", best.to_uci()));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An engine whose replies are kept in the returned buffer
+    fn captured() -> (Xboard, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        (Xboard::new(Output(buffer.clone())), buffer)
+    }
+
+    fn replies(buffer: &Mutex<Vec<u8>>) -> String {
+        String::from_utf8(buffer.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn engine_replies_to_user_move() {
+        let (mut xboard, buffer) = captured();
+        for line in ["xboard", "protover 2", "new", "sd 2", "usermove e2e4"] {
+            xboard.handle(line);
+        }
+        // Engine plays black and answers straight away
+        xboard.handle("ping 1");
+        xboard.collect();
+        assert_eq!(xboard.game.ply, 3);
+        assert_eq!(xboard.game.last_position().side_to_move(), Colour::White);
+        assert!(replies(&buffer).contains("\nmove "), "{}", replies(&buffer));
+    }
+
+    #[test]
+    fn force_stops_thinking() {
+        let (mut xboard, buffer) = captured();
+        // Without a time control or depth the search would run to its maximum depth
+        for line in ["new", "force", "go", "force"] {
+            xboard.handle(line);
+        }
+        assert!(!xboard.worker.is_searching());
+        assert_eq!(xboard.game.ply, Game::new().ply);
+        xboard.handle("ping 2");
+        assert!(!replies(&buffer).contains("move"), "{}", replies(&buffer));
+    }
+
+    #[test]
+    fn force_mode_and_undo() {
+        let (mut xboard, _) = captured();
+        for line in ["new", "force", "usermove e2e4", "usermove e7e5", "usermove g1f3", "remove"] {
+            xboard.handle(line);
+        }
+        assert_eq!(xboard.game.as_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert!(!xboard.worker.is_searching());
+    }
+}
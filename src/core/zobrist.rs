@@ -0,0 +1,76 @@
+use crate::ply::{Colour, Piece};
+
+/// Zobrist keys, generated at compile time from a fixed seed so that hashes are stable between runs
+pub struct Keys {
+    pub pieces: [[u64; 64]; 12],
+    pub castling: [u64; 16],
+    pub en_passant: [u64; 8],
+    pub side: u64,
+}
+
+pub static KEYS: Keys = Keys::generate(0x1F2E3D4C5B6A7988);
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    (state, z ^ (z >> 31))
+}
+
+impl Keys {
+    const fn generate(seed: u64) -> Self {
+        let mut keys = Keys { pieces: [[0; 64]; 12], castling: [0; 16], en_passant: [0; 8], side: 0 };
+        let mut state = seed;
+        let mut key;
+
+        let mut p = 0;
+        while p < 12 {
+            let mut sq = 0;
+            while sq < 64 {
+                (state, key) = splitmix64(state);
+                keys.pieces[p][sq] = key;
+                sq += 1;
+            }
+            p += 1;
+        }
+        let mut i = 0;
+        while i < 16 {
+            (state, key) = splitmix64(state);
+            keys.castling[i] = key;
+            i += 1;
+        }
+        let mut f = 0;
+        while f < 8 {
+            (state, key) = splitmix64(state);
+            keys.en_passant[f] = key;
+            f += 1;
+        }
+        (_, key) = splitmix64(state);
+        keys.side = key;
+
+        keys
+    }
+}
+
+/// Key for a given piece on a given square index (A1 = 0)
+pub fn piece_key(piece: Piece, colour: Colour, sq: usize) -> u64 {
+    let offset = match colour {
+        Colour::White => 0,
+        Colour::Black => 6,
+    };
+    KEYS.pieces[piece as usize + offset][sq]
+}
+
+/// Key for the castling rights stored in the upper nibble of `Position::castling`
+pub fn castling_key(castling: u8) -> u64 {
+    KEYS.castling[(castling >> 4) as usize]
+}
+
+pub fn en_passant_key(file: usize) -> u64 {
+    KEYS.en_passant[file]
+}
+
+pub fn side_key() -> u64 {
+    KEYS.side
+}
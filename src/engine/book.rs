@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use crate    :: {
+    core     :: { parsers::pgn::{ self, PgnGame }, polyglot, Square },
+    ply      :: { Colour, Piece, Ply, PromotablePiece },
+    position :: Position,
+};
+
+const ENTRY_SIZE: usize = 16;
+
+/// A raw Polyglot book entry. Entries are stored big endian and sorted by key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    pub mov: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl Entry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().expect("entry is 16 bytes")),
+            mov: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().expect("entry is 16 bytes")),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mov.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+/// How to choose between the book moves for a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Selection {
+    /// At random, in proportion to the weights
+    #[default]
+    Weighted,
+    /// Always the move with the highest weight
+    Best,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BookMove {
+    pub ply: Ply,
+    pub weight: u16,
+}
+
+/// An opening book in Polyglot's `.bin` format
+#[derive(Debug, Clone)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+/// Decodes a Polyglot move, in which castling is written as the king taking its own rook
+fn decode_move(pos: &Position, mov: u16) -> Option<Ply> {
+    let from = Square::from((mov >> 6 & 63) as usize);
+    let mut to = Square::from((mov & 63) as usize);
+    let promotion = match mov >> 12 & 7 {
+        0 => None,
+        1 => Some(PromotablePiece::Knight),
+        2 => Some(PromotablePiece::Bishop),
+        3 => Some(PromotablePiece::Rook),
+        4 => Some(PromotablePiece::Queen),
+        _ => return None,
+    };
+
+    if matches!(pos.board.piece_at(from.to_bb()), Some((Piece::King, _))) {
+        to = match (from, to) {
+            (Square::E1, Square::H1) => Square::G1,
+            (Square::E1, Square::A1) => Square::C1,
+            (Square::E8, Square::H8) => Square::G8,
+            (Square::E8, Square::A8) => Square::C8,
+            _ => to,
+        };
+    }
+
+    pos.legal_plies().into_iter()
+        .find(|p| p.mov.from_sq == from && p.mov.to_sq == to && p.promotion == promotion)
+}
+
+/// The inverse of `decode_move`
+fn encode_move(ply: Ply) -> u16 {
+    let (from, mut to) = (ply.mov.from_sq, ply.mov.to_sq);
+    if ply.mov.piece == Piece::King && from.file().abs_diff(to.file()) == 2 {
+        to = match to {
+            Square::G1 => Square::H1,
+            Square::C1 => Square::A1,
+            Square::G8 => Square::H8,
+            _ => Square::A8,
+        };
+    }
+    let promotion = match ply.promotion {
+        None => 0,
+        Some(PromotablePiece::Knight) => 1,
+        Some(PromotablePiece::Bishop) => 2,
+        Some(PromotablePiece::Rook) => 3,
+        Some(PromotablePiece::Queen) => 4,
+    };
+    promotion << 12 | (from as u16) << 6 | to as u16
+}
+
+/// A random number below `n`, good enough for picking book moves
+fn random_below(n: u64) -> u64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    let mut z = nanos.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    (z ^ (z >> 31)) % n.max(1)
+}
+
+impl Book {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read book `{path}`: {e}"))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("{e} in `{path}`"))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err("Truncated Polyglot book entry".to_string())
+        }
+        let mut entries: Vec<Entry> = bytes.chunks_exact(ENTRY_SIZE).map(Entry::from_bytes).collect();
+        // Lookups binary search by key, tolerate books written out of order
+        if !entries.is_sorted_by_key(|e| e.key) {
+            entries.sort_by_key(|e| e.key);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(|e| e.to_bytes()).collect()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes()).map_err(|e| format!("Could not write book `{path}`: {e}"))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every legal book move for the position, highest weight first
+    pub fn moves(&self, pos: &Position) -> Vec<BookMove> {
+        let key = polyglot::key(pos);
+        let first = self.entries.partition_point(|e| e.key < key);
+
+        let mut moves: Vec<BookMove> = self.entries[first..].iter()
+            .take_while(|e| e.key == key)
+            .filter_map(|e| Some(BookMove { ply: decode_move(pos, e.mov)?, weight: e.weight }))
+            .collect();
+        moves.sort_by_key(|m| std::cmp::Reverse(m.weight));
+        moves
+    }
+
+    /// Picks a move to play, ignoring moves with no weight
+    pub fn pick(&self, pos: &Position, selection: Selection) -> Option<Ply> {
+        let moves: Vec<BookMove> = self.moves(pos).into_iter().filter(|m| m.weight > 0).collect();
+        match selection {
+            Selection::Best => moves.first().map(|m| m.ply),
+            Selection::Weighted => {
+                let total: u64 = moves.iter().map(|m| m.weight as u64).sum();
+                let mut roll = random_below(total);
+                moves.iter().find(|m| {
+                    let hit = roll < m.weight as u64;
+                    roll = roll.saturating_sub(m.weight as u64);
+                    hit
+                }).map(|m| m.ply)
+            },
+        }
+    }
+}
+
+/// Which games and moves make it into a built book
+#[derive(Debug, Clone, Copy)]
+pub struct BuildOptions {
+    /// Plies of each game to read
+    pub depth: usize,
+    /// Games a move must have been played in
+    pub min_games: u32,
+    /// Share of the points, between 0 and 1, a move must have scored for the side playing it
+    pub min_score: f64,
+    /// Elo rating the player making the move must have, by their `WhiteElo` or `BlackElo` tag
+    pub min_elo: Option<u32>,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self { depth: 20, min_games: 1, min_score: 0.0, min_elo: None }
+    }
+}
+
+/// Results of a move, from the side playing it
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl MoveStats {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// Polyglot's usual weighting: two per win and one per draw
+    fn weight(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+}
+
+/// Builds a book out of game collections, counting how often each move was played and how it scored
+#[derive(Debug, Clone, Default)]
+pub struct BookBuilder {
+    options: BuildOptions,
+    stats: HashMap<(u64, u16), MoveStats>,
+    pub games: usize,
+    pub skipped: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BuildOptions) -> Self {
+        Self { options, ..Default::default() }
+    }
+
+    /// Adds a game's opening. Games without a result or with illegal moves are skipped
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<(), String> {
+        let result = match game.result() {
+            Some("1-0") => Some(Colour::White),
+            Some("0-1") => Some(Colour::Black),
+            Some("1/2-1/2") => None,
+            _ => {
+                self.skipped += 1;
+                return Err("Game has no result".to_string())
+            },
+        };
+        let (start, plies) = game.plies().inspect_err(|_| self.skipped += 1)?;
+        let elo = |tag| game.tag(tag).and_then(|e| e.parse::<u32>().ok()).unwrap_or(0);
+        let (white_elo, black_elo) = (elo("WhiteElo"), elo("BlackElo"));
+
+        let mut pos = *start.last_position();
+        for &ply in plies.iter().take(self.options.depth) {
+            let side = pos.side_to_move();
+            let rated = match side {
+                Colour::White => white_elo,
+                Colour::Black => black_elo,
+            };
+            if self.options.min_elo.is_none_or(|min| rated >= min) {
+                let stats = self.stats.entry((polyglot::key(&pos), encode_move(ply))).or_default();
+                match result {
+                    Some(winner) if winner == side => stats.wins += 1,
+                    Some(_) => stats.losses += 1,
+                    None => stats.draws += 1,
+                }
+            }
+            pos = pos.make_ply(ply);
+        }
+        self.games += 1;
+        Ok(())
+    }
+
+    /// Adds every game in a PGN file's text
+    pub fn add_pgn(&mut self, text: &str) {
+        for game in pgn::parse_pgn(text) {
+            let _ = self.add_game(&game);
+        }
+    }
+
+    /// The book of moves passing the filters. Weights are scaled down to fit if need be
+    pub fn build(&self) -> Book {
+        let kept: Vec<(u64, u16, u64)> = self.stats.iter()
+            .filter(|(_, s)| s.games() >= self.options.min_games && s.score() >= self.options.min_score)
+            .map(|(&(key, mov), s)| (key, mov, s.weight()))
+            .collect();
+
+        let max = kept.iter().map(|&(_, _, w)| w).max().unwrap_or(0);
+        let scale = |w: u64| if max > u16::MAX as u64 { w * u16::MAX as u64 / max } else { w } as u16;
+
+        let mut entries: Vec<Entry> = kept.into_iter()
+            .map(|(key, mov, weight)| Entry { key, mov, weight: scale(weight), learn: 0 })
+            .collect();
+        entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight), e.mov));
+        Book { entries }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+
+    fn entry(key: u64, mov: &str, weight: u16) -> Vec<u8> {
+        let sq = |s: &str| Square::from_str(s).unwrap() as u16;
+        let mov = sq(&mov[0..2]) << 6 | sq(&mov[2..4]);
+        [key.to_be_bytes().as_slice(), &mov.to_be_bytes(), &weight.to_be_bytes(), &0u32.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn lookup_and_castling() {
+        let start = Game::new();
+        let castle = Game::try_from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+        let castle_key = polyglot::key(castle.last_position());
+
+        // Deliberately out of order
+        let bytes = [
+            entry(castle_key, "e1h1", 3),
+            entry(0x463b96181691fc9c, "d2d4", 10),
+            entry(0x463b96181691fc9c, "e2e4", 30),
+            entry(0x463b96181691fc9c, "g1f3", 0),
+            entry(castle_key, "e1a1", 1),
+        ].concat();
+        let book = Book::from_bytes(&bytes).unwrap();
+
+        let moves: Vec<(String, u16)> = book.moves(start.last_position()).iter().map(|m| (m.ply.to_uci(), m.weight)).collect();
+        assert_eq!(moves, [("e2e4".to_string(), 30), ("d2d4".to_string(), 10), ("g1f3".to_string(), 0)]);
+        assert_eq!(book.pick(start.last_position(), Selection::Best).unwrap().to_uci(), "e2e4");
+        for _ in 0..20 {
+            assert_ne!(book.pick(start.last_position(), Selection::Weighted).unwrap().to_uci(), "g1f3");
+        }
+
+        let moves: Vec<String> = book.moves(castle.last_position()).iter().map(|m| m.ply.to_uci()).collect();
+        assert_eq!(moves, ["e1g1", "e1c1"]);
+
+        assert!(Book::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn building() {
+        let pgn = r#"
+[WhiteElo "2400"] [BlackElo "2000"] [Result "1-0"] 1. e4 e5 2. Nf3 1-0
+[WhiteElo "2400"] [BlackElo "2000"] [Result "1/2-1/2"] 1. e4 c5 1/2-1/2
+[WhiteElo "1500"] [BlackElo "2500"] [Result "0-1"] 1. d4 d5 0-1
+[Result "*"] 1. c4 *
+1. e4 Ke7 1-0
+"#;
+        let start = Game::new();
+        let pos = start.last_position();
+        let moves = |book: &Book, pos: &Position| -> Vec<(String, u16)> {
+            book.moves(pos).iter().map(|m| (m.ply.to_uci(), m.weight)).collect()
+        };
+
+        let mut builder = BookBuilder::new(BuildOptions { depth: 2, ..Default::default() });
+        builder.add_pgn(pgn);
+        assert_eq!((builder.games, builder.skipped), (3, 2));
+        let book = Book::from_bytes(&builder.build().to_bytes()).unwrap();
+        assert_eq!(moves(&book, pos), [("e2e4".to_string(), 3), ("d2d4".to_string(), 0)]);
+        // Past the depth
+        let after = pos.make_ply(pos.parse_uci("e2e4").unwrap());
+        let after = after.make_ply(after.parse_uci("e7e5").unwrap());
+        assert!(book.moves(&after).is_empty());
+
+        let mut builder = BookBuilder::new(BuildOptions { min_games: 2, min_elo: Some(2000), ..Default::default() });
+        builder.add_pgn(pgn);
+        let book = builder.build();
+        assert_eq!(moves(&book, pos), [("e2e4".to_string(), 3)]);
+        assert_eq!(book.len(), 1);
+
+        let mut builder = BookBuilder::new(BuildOptions { min_score: 0.5, ..Default::default() });
+        builder.add_pgn(pgn);
+        let book = builder.build();
+        assert_eq!(moves(&book, &pos.make_ply(pos.parse_uci("d2d4").unwrap())), [("d7d5".to_string(), 2)]);
+        assert!(moves(&book, pos).iter().all(|(m, _)| m != "d2d4"));
+    }
+}
@@ -0,0 +1,174 @@
+use crate :: {
+    core     :: { DARK_SQS, LIGHT_SQS },
+    ply      :: { Colour, Piece },
+    position :: Position,
+};
+
+/// Added to won endgames so the search heads for them, still well below mate scores
+pub const KNOWN_WIN: i32 = 10_000;
+/// Scale factors are out of this
+const SCALE_NORMAL: i32 = 64;
+/// Lone rooks without pawns
+const SCALE_ROOKS: i32 = 8;
+/// Rook endings with at most a pawn's difference
+const SCALE_ROOKS_PAWNS: i32 = 48;
+/// Opposite coloured bishops with only pawns
+const SCALE_OPPOSITE_BISHOPS: i32 = 22;
+
+/// Pieces other than the king, indexed by `Piece`
+type Counts = [u32; 5];
+
+fn counts(pos: &Position, colour: Colour) -> Counts {
+    [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .map(|piece| pos.board.pieces(piece, colour).count_ones())
+}
+
+fn only(counts: &Counts, piece: Piece, n: u32) -> bool {
+    counts.iter().enumerate().all(|(p, &c)| if p == piece as usize { c == n } else { c == 0 })
+}
+
+fn square(bb: u64) -> (i32, i32) {
+    let sq = bb.trailing_zeros() as i32;
+    (sq % 8, sq / 8)
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs())
+}
+
+/// 0 in the centre up to 6 in a corner
+fn edge_bonus(sq: (i32, i32)) -> i32 {
+    let from_centre = |x: i32| (x - 4).max(3 - x);
+    from_centre(sq.0) + from_centre(sq.1)
+}
+
+/// Score of a won endgame against a lone king: drive the king to the edge and bring the winning king
+/// closer. With bishop and knight only the corners of the bishop's colour can be mated in
+fn mating(pos: &Position, strong: Colour, material: i32) -> i32 {
+    let (own, lone) = (counts(pos, strong), strong.opposite());
+    let winner = square(pos.board.pieces(Piece::King, strong));
+    let loser = square(pos.board.pieces(Piece::King, lone));
+
+    let corner = match own[Piece::Bishop as usize] == 1 && own[Piece::Knight as usize] == 1 && own[Piece::Queen as usize] + own[Piece::Rook as usize] == 0 {
+        true => {
+            let corners = match pos.board.pieces(Piece::Bishop, strong) & DARK_SQS != 0 {
+                true  => [(0, 0), (7, 7)],
+                false => [(0, 7), (7, 0)],
+            };
+            let nearest = corners.iter().map(|&c| (c.0 - loser.0).abs() + (c.1 - loser.1).abs()).min().unwrap_or(0);
+            (14 - nearest) * 20
+        },
+        false => edge_bonus(loser) * 20,
+    };
+
+    KNOWN_WIN + material + corner + (7 - distance(winner, loser)) * 10
+}
+
+/// Whether the side's material can force mate against a lone king without pawns
+fn can_mate(counts: &Counts, bishops: u64) -> bool {
+    let [pawns, knights, bishops_count, rooks, queens] = *counts;
+    pawns == 0 && (
+        queens + rooks > 0
+        || (bishops_count > 0 && knights > 0)
+        || (bishops & DARK_SQS != 0 && bishops & LIGHT_SQS != 0)
+    )
+}
+
+/// Corrects the evaluation, from white's point of view, for endgames that are known wins, dead draws
+/// or drawish in ways the piece-square tables can't see
+pub fn adjust(pos: &Position, score: i32, material: [i32; 2]) -> i32 {
+    let b = &pos.board;
+    let (white, black) = (counts(pos, Colour::White), counts(pos, Colour::Black));
+    let lone = |c: &Counts| c.iter().all(|&n| n == 0);
+
+    // Basic mates
+    if lone(&black) && can_mate(&white, b.pieces(Piece::Bishop, Colour::White)) {
+        return mating(pos, Colour::White, material[0])
+    }
+    if lone(&white) && can_mate(&black, b.pieces(Piece::Bishop, Colour::Black)) {
+        return -mating(pos, Colour::Black, material[1])
+    }
+
+    // Dead draws: KvK, KNvK, KBvK and bishops of the same colour
+    let minor = |c: &Counts| lone(c) || only(c, Piece::Knight, 1) || only(c, Piece::Bishop, 1);
+    if (lone(&white) && minor(&black)) || (lone(&black) && minor(&white)) {
+        return 0
+    }
+    let bishops = b.w_b_bb | b.b_b_bb;
+    let single_bishops = only(&white, Piece::Bishop, 1) && only(&black, Piece::Bishop, 1);
+    if single_bishops && (bishops & DARK_SQS == bishops || bishops & LIGHT_SQS == bishops) {
+        return 0
+    }
+
+    let scale = drawish_scale(&white, &black, bishops);
+    score * scale / SCALE_NORMAL
+}
+
+/// Scaling for rook endings and opposite coloured bishops
+fn drawish_scale(white: &Counts, black: &Counts, bishops: u64) -> i32 {
+    let without_pawns = |c: &Counts| -> Counts { [0, c[1], c[2], c[3], c[4]] };
+    let (w, b) = (without_pawns(white), without_pawns(black));
+    let pawns = (white[Piece::Pawn as usize], black[Piece::Pawn as usize]);
+
+    if only(&w, Piece::Rook, 1) && only(&b, Piece::Rook, 1) {
+        return match pawns {
+            (0, 0) => SCALE_ROOKS,
+            (w, b) if w.abs_diff(b) <= 1 => SCALE_ROOKS_PAWNS,
+            _ => SCALE_NORMAL,
+        }
+    }
+    let opposite = bishops & DARK_SQS != 0 && bishops & LIGHT_SQS != 0;
+    if only(&w, Piece::Bishop, 1) && only(&b, Piece::Bishop, 1) && opposite {
+        return SCALE_OPPOSITE_BISHOPS
+    }
+    SCALE_NORMAL
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ engine::eval, game::Game };
+
+    fn eval(fen: &str) -> i32 {
+        eval::evaluate_white(Game::try_from_fen(fen).unwrap().last_position(), &eval::DEFAULT_PARAMS)
+    }
+
+    #[test]
+    fn dead_draws() {
+        for fen in [
+            "8/8/4k3/8/8/8/8/4K3 w - - 0 1",
+            "8/8/4k3/8/8/2N5/8/4K3 w - - 0 1",
+            "8/8/4k3/3b4/8/8/8/4K3 w - - 0 1",
+            // Both bishops on light squares
+            "8/8/4k3/3b4/8/8/8/1B2K3 w - - 0 1",
+        ] {
+            assert_eq!(eval(fen), 0, "{fen}");
+        }
+        // Opposite colours can't be written off entirely
+        assert_ne!(eval("8/8/4k3/3b4/8/8/8/2B1K3 w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn drawish_endings() {
+        // A pawn up with opposite coloured bishops is worth far less than with a knight each
+        let bishops = eval("8/5k2/4b3/8/3P4/2P1B3/5K2/8 w - - 0 1");
+        let knights = eval("8/5k2/4n3/8/3P4/2P1N3/5K2/8 w - - 0 1");
+        assert!(bishops > 0 && bishops * 2 < knights, "{bishops} {knights}");
+
+        assert!(eval("8/5k2/4r3/8/8/2R5/5K2/8 w - - 0 1").abs() < 10);
+    }
+
+    #[test]
+    fn basic_mates() {
+        // The lone king is better off in the centre
+        let centre = eval("8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        let edge = eval("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert!(centre > KNOWN_WIN && edge > centre);
+        assert!(eval("8/8/8/4K3/8/8/8/q3k3 b - - 0 1") < -KNOWN_WIN);
+
+        // Bishop and knight mate in the corner of the bishop's colour, a1 is dark
+        let right = eval("8/8/8/8/8/2N5/1K6/k1B5 w - - 0 1");
+        let wrong = eval("8/8/8/8/8/2N5/1K6/k2B4 w - - 0 1");
+        assert!(right > wrong, "{right} {wrong}");
+    }
+}
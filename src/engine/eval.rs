@@ -0,0 +1,300 @@
+use crate :: {
+    core     :: bits,
+    engine   :: endgame,
+    ply      :: { Colour, Piece },
+    position :: Position,
+};
+
+/// All hand-written evaluation weights. Piece-square tables are written from white's point of view
+/// with A8 in the top left, i.e. as the board is normally printed
+#[derive(Debug, Clone)]
+pub struct EvalParams {
+    pub mg_values: [i32; 6],
+    pub eg_values: [i32; 6],
+    pub mg_pst: [[i32; 64]; 6],
+    pub eg_pst: [[i32; 64]; 6],
+}
+
+/// Game phase contribution of each piece type. A full set of pieces is `MAX_PHASE`
+pub const PHASE: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+const PIECE_NAMES: [&str; 6] = ["Pawn", "Knight", "Bishop", "Rook", "Queen", "King"];
+
+#[rustfmt::skip]
+pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+    mg_values: [100, 320, 330, 500, 900, 0],
+    eg_values: [120, 300, 320, 520, 950, 0],
+    mg_pst: [
+        // Pawn
+        [
+             0,  0,  0,  0,  0,  0,  0,  0,
+            50, 50, 50, 50, 50, 50, 50, 50,
+            10, 10, 20, 30, 30, 20, 10, 10,
+             5,  5, 10, 25, 25, 10,  5,  5,
+             0,  0,  0, 20, 20,  0,  0,  0,
+             5, -5,-10,  0,  0,-10, -5,  5,
+             5, 10, 10,-20,-20, 10, 10,  5,
+             0,  0,  0,  0,  0,  0,  0,  0,
+        ],
+        // Knight
+        [
+           -50,-40,-30,-30,-30,-30,-40,-50,
+           -40,-20,  0,  0,  0,  0,-20,-40,
+           -30,  0, 10, 15, 15, 10,  0,-30,
+           -30,  5, 15, 20, 20, 15,  5,-30,
+           -30,  0, 15, 20, 20, 15,  0,-30,
+           -30,  5, 10, 15, 15, 10,  5,-30,
+           -40,-20,  0,  5,  5,  0,-20,-40,
+           -50,-40,-30,-30,-30,-30,-40,-50,
+        ],
+        // Bishop
+        [
+           -20,-10,-10,-10,-10,-10,-10,-20,
+           -10,  0,  0,  0,  0,  0,  0,-10,
+           -10,  0,  5, 10, 10,  5,  0,-10,
+           -10,  5,  5, 10, 10,  5,  5,-10,
+           -10,  0, 10, 10, 10, 10,  0,-10,
+           -10, 10, 10, 10, 10, 10, 10,-10,
+           -10,  5,  0,  0,  0,  0,  5,-10,
+           -20,-10,-10,-10,-10,-10,-10,-20,
+        ],
+        // Rook
+        [
+             0,  0,  0,  0,  0,  0,  0,  0,
+             5, 10, 10, 10, 10, 10, 10,  5,
+            -5,  0,  0,  0,  0,  0,  0, -5,
+            -5,  0,  0,  0,  0,  0,  0, -5,
+            -5,  0,  0,  0,  0,  0,  0, -5,
+            -5,  0,  0,  0,  0,  0,  0, -5,
+            -5,  0,  0,  0,  0,  0,  0, -5,
+             0,  0,  0,  5,  5,  0,  0,  0,
+        ],
+        // Queen
+        [
+           -20,-10,-10, -5, -5,-10,-10,-20,
+           -10,  0,  0,  0,  0,  0,  0,-10,
+           -10,  0,  5,  5,  5,  5,  0,-10,
+            -5,  0,  5,  5,  5,  5,  0, -5,
+             0,  0,  5,  5,  5,  5,  0, -5,
+           -10,  5,  5,  5,  5,  5,  0,-10,
+           -10,  0,  5,  0,  0,  0,  0,-10,
+           -20,-10,-10, -5, -5,-10,-10,-20,
+        ],
+        // King
+        [
+           -30,-40,-40,-50,-50,-40,-40,-30,
+           -30,-40,-40,-50,-50,-40,-40,-30,
+           -30,-40,-40,-50,-50,-40,-40,-30,
+           -30,-40,-40,-50,-50,-40,-40,-30,
+           -20,-30,-30,-40,-40,-30,-30,-20,
+           -10,-20,-20,-20,-20,-20,-20,-10,
+            20, 20,  0,  0,  0,  0, 20, 20,
+            20, 30, 10,  0,  0, 10, 30, 20,
+        ],
+    ],
+    eg_pst: [
+        // Pawn
+        [
+             0,  0,  0,  0,  0,  0,  0,  0,
+            80, 80, 80, 80, 80, 80, 80, 80,
+            50, 50, 50, 50, 50, 50, 50, 50,
+            30, 30, 30, 30, 30, 30, 30, 30,
+            15, 15, 15, 15, 15, 15, 15, 15,
+             5,  5,  5,  5,  5,  5,  5,  5,
+             0,  0,  0,  0,  0,  0,  0,  0,
+             0,  0,  0,  0,  0,  0,  0,  0,
+        ],
+        // Knight
+        [
+           -50,-40,-30,-30,-30,-30,-40,-50,
+           -40,-20,  0,  0,  0,  0,-20,-40,
+           -30,  0, 10, 15, 15, 10,  0,-30,
+           -30,  5, 15, 20, 20, 15,  5,-30,
+           -30,  0, 15, 20, 20, 15,  0,-30,
+           -30,  5, 10, 15, 15, 10,  5,-30,
+           -40,-20,  0,  5,  5,  0,-20,-40,
+           -50,-40,-30,-30,-30,-30,-40,-50,
+        ],
+        // Bishop
+        [
+           -20,-10,-10,-10,-10,-10,-10,-20,
+           -10,  0,  0,  0,  0,  0,  0,-10,
+           -10,  0,  5, 10, 10,  5,  0,-10,
+           -10,  5,  5, 10, 10,  5,  5,-10,
+           -10,  0, 10, 10, 10, 10,  0,-10,
+           -10, 10, 10, 10, 10, 10, 10,-10,
+           -10,  5,  0,  0,  0,  0,  5,-10,
+           -20,-10,-10,-10,-10,-10,-10,-20,
+        ],
+        // Rook
+        [
+             0,  0,  0,  0,  0,  0,  0,  0,
+             5, 10, 10, 10, 10, 10, 10,  5,
+             0,  0,  0,  0,  0,  0,  0,  0,
+             0,  0,  0,  0,  0,  0,  0,  0,
+             0,  0,  0,  0,  0,  0,  0,  0,
+             0,  0,  0,  0,  0,  0,  0,  0,
+             0,  0,  0,  0,  0,  0,  0,  0,
+             0,  0,  0,  0,  0,  0,  0,  0,
+        ],
+        // Queen
+        [
+           -20,-10,-10, -5, -5,-10,-10,-20,
+           -10,  0,  0,  0,  0,  0,  0,-10,
+           -10,  0,  5,  5,  5,  5,  0,-10,
+            -5,  0,  5,  5,  5,  5,  0, -5,
+            -5,  0,  5,  5,  5,  5,  0, -5,
+           -10,  0,  5,  5,  5,  5,  0,-10,
+           -10,  0,  0,  0,  0,  0,  0,-10,
+           -20,-10,-10, -5, -5,-10,-10,-20,
+        ],
+        // King
+        [
+           -50,-40,-30,-20,-20,-30,-40,-50,
+           -30,-20,-10,  0,  0,-10,-20,-30,
+           -30,-10, 20, 30, 30, 20,-10,-30,
+           -30,-10, 30, 40, 40, 30,-10,-30,
+           -30,-10, 30, 40, 40, 30,-10,-30,
+           -30,-10, 20, 30, 30, 20,-10,-30,
+           -30,-30,  0,  0,  0,  0,-30,-30,
+           -50,-30,-30,-30,-30,-30,-30,-50,
+        ],
+    ],
+};
+
+impl EvalParams {
+    /// Number of weights when flattened
+    pub const LEN: usize = 2 * 6 + 2 * 6 * 64;
+
+    /// Every weight in a single list: middlegame then endgame values, then the middlegame and endgame tables
+    pub fn to_vec(&self) -> Vec<i32> {
+        let mut weights = Vec::with_capacity(Self::LEN);
+        weights.extend(self.mg_values);
+        weights.extend(self.eg_values);
+        weights.extend(self.mg_pst.iter().flatten());
+        weights.extend(self.eg_pst.iter().flatten());
+        weights
+    }
+
+    /// Inverse of [`EvalParams::to_vec`]
+    pub fn from_slice(weights: &[i32]) -> Self {
+        assert_eq!(weights.len(), Self::LEN, "wrong number of evaluation weights");
+        let table = |start: usize| -> [[i32; 64]; 6] {
+            std::array::from_fn(|p| weights[start + p * 64..start + (p + 1) * 64].try_into().expect("64 weights"))
+        };
+        Self {
+            mg_values: weights[0..6].try_into().expect("6 weights"),
+            eg_values: weights[6..12].try_into().expect("6 weights"),
+            mg_pst: table(12),
+            eg_pst: table(12 + 6 * 64),
+        }
+    }
+
+    /// The weights as Rust source, laid out like `DEFAULT_PARAMS`
+    pub fn to_rust(&self) -> String {
+        let values = |v: &[i32; 6]| v.map(|n| n.to_string()).join(", ");
+        let tables = |pst: &[[i32; 64]; 6]| {
+            let mut out = String::new();
+            for (name, table) in PIECE_NAMES.iter().zip(pst) {
+                out += &format!("        // {name}\n        [\n");
+                for row in table.chunks(8) {
+                    let row: Vec<String> = row.iter().map(|n| format!("{n:>3}")).collect();
+                    out += &format!("           {},\n", row.join(","));
+                }
+                out += "        ],\n";
+            }
+            out
+        };
+        format!(
+            "#[rustfmt::skip]\npub const DEFAULT_PARAMS: EvalParams = EvalParams {{\n    mg_values: [{}],\n    eg_values: [{}],\n    mg_pst: [\n{}    ],\n    eg_pst: [\n{}    ],\n}};\n",
+            values(&self.mg_values), values(&self.eg_values), tables(&self.mg_pst), tables(&self.eg_pst)
+        )
+    }
+}
+
+pub const PIECES: [Piece; 6] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King];
+
+/// Middlegame value of a piece, used for move ordering and pruning margins
+pub fn piece_value(piece: Piece) -> i32 {
+    DEFAULT_PARAMS.mg_values[piece as usize]
+}
+
+/// Index into a piece-square table for a given square index (A1 = 0) and colour
+pub fn pst_index(sq: usize, colour: Colour) -> usize {
+    match colour {
+        Colour::White => sq ^ 56,
+        Colour::Black => sq,
+    }
+}
+
+/// Current game phase, from `MAX_PHASE` (all pieces on the board) down to 0 (pawns and kings only)
+pub fn phase(pos: &Position) -> i32 {
+    let mut phase = 0;
+    for piece in PIECES {
+        let count = pos.board.pieces(piece, Colour::White) | pos.board.pieces(piece, Colour::Black);
+        phase += PHASE[piece as usize] * count.count_ones() as i32;
+    }
+    phase.min(MAX_PHASE)
+}
+
+/// Static evaluation in centipawns from white's point of view
+pub fn evaluate_white(pos: &Position, params: &EvalParams) -> i32 {
+    let (mut mg, mut eg) = (0, 0);
+    let mut material = [0; 2];
+
+    for colour in [Colour::White, Colour::Black] {
+        let sign = if colour == Colour::White { 1 } else { -1 };
+        for piece in PIECES {
+            let p = piece as usize;
+            for sq in bits(pos.board.pieces(piece, colour)) {
+                let i = pst_index(sq.trailing_zeros() as usize, colour);
+                mg += sign * (params.mg_values[p] + params.mg_pst[p][i]);
+                eg += sign * (params.eg_values[p] + params.eg_pst[p][i]);
+                material[colour as usize] += params.eg_values[p];
+            }
+        }
+    }
+
+    let phase = phase(pos);
+    let score = (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE;
+    endgame::adjust(pos, score, material)
+}
+
+/// Static evaluation in centipawns from the side to move's point of view
+pub fn evaluate(pos: &Position) -> i32 {
+    let score = evaluate_white(pos, &DEFAULT_PARAMS);
+    match pos.side_to_move() {
+        Colour::White => score,
+        Colour::Black => -score,
+    }
+}
+
+/// Evaluates the positions of a search. Implementations can keep state along the search path,
+/// as they are told about every move made and unmade
+pub trait Evaluator: Send {
+    /// Starts again from a new root position
+    fn reset(&mut self, pos: &Position);
+    /// Moves from `pos` to `child`, which is the position after a move or a null move
+    fn push(&mut self, pos: &Position, child: &Position);
+    /// Goes back to the position before the last `push`
+    fn pop(&mut self);
+    /// Score of the current position in centipawns from the side to move's point of view
+    fn evaluate(&mut self, pos: &Position) -> i32;
+}
+
+/// The hand-written evaluation, which needs no state
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hce;
+
+impl Evaluator for Hce {
+    fn reset(&mut self, _: &Position) {}
+
+    fn push(&mut self, _: &Position, _: &Position) {}
+
+    fn pop(&mut self) {}
+
+    fn evaluate(&mut self, pos: &Position) -> i32 {
+        evaluate(pos)
+    }
+}
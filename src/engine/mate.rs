@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+use crate    :: {
+    ply      :: Ply,
+    position :: Position,
+};
+
+/// Problems longer than this take too long to solve
+pub const MAX_MATE: u8 = 8;
+
+/// A forced mate found by [`solve`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    /// Moves to mate, at most the number asked for
+    pub moves: u8,
+    /// The mate, against the defences that hold out longest
+    pub line: Vec<Ply>,
+    /// Every first move that mates within the moves asked for. A sound problem has just the one key
+    pub keys: Vec<Ply>,
+    pub nodes: u64,
+}
+
+/// Depth-limited AND/OR search: the attacker needs one move that mates, against every defence
+struct Solver<'a> {
+    /// Whether the side to move mates within so many moves, by position
+    proven: HashMap<(u64, u8), bool>,
+    nodes: u64,
+    stop: &'a AtomicBool,
+    aborted: bool,
+}
+
+impl Solver<'_> {
+    fn should_stop(&mut self) -> bool {
+        if self.nodes.is_multiple_of(1024) && self.stop.load(Ordering::Relaxed) {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+
+    /// Whether the side to move can force mate within `moves` of its moves
+    fn mates(&mut self, pos: &Position, moves: u8) -> bool {
+        if moves == 0 || self.should_stop() {
+            return false
+        }
+        if let Some(&known) = self.proven.get(&(pos.hash, moves)) {
+            return known
+        }
+        let found = self.key(pos, moves).is_some();
+        if !self.aborted {
+            self.proven.insert((pos.hash, moves), found);
+        }
+        found
+    }
+
+    /// A move that forces mate within `moves`
+    fn key(&mut self, pos: &Position, moves: u8) -> Option<Ply> {
+        let mut children: Vec<(Ply, Position)> = pos.legal_plies().into_iter().map(|ply| (ply, pos.make_ply(ply))).collect();
+        self.nodes += children.len() as u64;
+        // The last move has to give mate, otherwise try checks then captures first
+        match moves {
+            1 => children.retain(|(_, child)| child.check.is_some()),
+            _ => children.sort_by_key(|(ply, child)| (child.check.is_none(), !ply.is_capture)),
+        }
+        children.into_iter().find(|(_, child)| !self.holds(child, moves - 1)).map(|(ply, _)| ply)
+    }
+
+    /// Whether the side to move escapes being mated within another `moves` of the attacker's moves
+    fn holds(&mut self, pos: &Position, moves: u8) -> bool {
+        let replies = pos.legal_plies();
+        if replies.is_empty() {
+            return pos.check.is_none()
+        }
+        if moves == 0 {
+            return true
+        }
+        self.nodes += replies.len() as u64;
+        replies.into_iter().any(|reply| !self.mates(&pos.make_ply(reply), moves))
+    }
+
+    /// The fewest moves, up to `moves`, the side to move needs to mate
+    fn shortest(&mut self, pos: &Position, moves: u8) -> Option<u8> {
+        (1..=moves).find(|&n| self.mates(pos, n))
+    }
+
+    /// Appends the quickest mate within `moves`, against the longest defence
+    fn line(&mut self, pos: &Position, moves: u8, line: &mut Vec<Ply>) {
+        let Some(moves) = self.shortest(pos, moves) else { return };
+        let Some(ply) = self.key(pos, moves) else { return };
+        line.push(ply);
+
+        let child = pos.make_ply(ply);
+        let longest = child
+            .legal_plies()
+            .into_iter()
+            .map(|reply| {
+                let next = child.make_ply(reply);
+                (reply, self.shortest(&next, moves - 1).unwrap_or(moves))
+            })
+            .max_by_key(|(_, moves)| *moves);
+        if let Some((reply, _)) = longest {
+            line.push(reply);
+            self.line(&child.make_ply(reply), moves - 1, line);
+        }
+    }
+}
+
+/// Looks for a forced mate in at most `moves` moves for the side to move, along with every key that
+/// achieves it. Returns `None` if there is none, or if `stop` is set before the search finishes.
+/// NOTE: Repetitions and the fifty-move rule are ignored
+pub fn solve(pos: &Position, moves: u8, stop: &AtomicBool) -> Option<Solution> {
+    let mut solver = Solver { proven: HashMap::new(), nodes: 0, stop, aborted: false };
+    let shortest = solver.shortest(pos, moves)?;
+
+    let mut line = Vec::with_capacity(2 * shortest as usize);
+    solver.line(pos, shortest, &mut line);
+    let keys = pos.legal_plies().into_iter().filter(|&ply| !solver.holds(&pos.make_ply(ply), moves - 1)).collect();
+
+    match solver.aborted {
+        true => None,
+        false => Some(Solution { moves: shortest, line, keys, nodes: solver.nodes }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ core::parsers::san, game::Game };
+
+    fn solve_fen(fen: &str, moves: u8) -> Option<(Solution, String, Vec<String>)> {
+        let game = Game::try_from_fen(fen).unwrap();
+        let pos = game.last_position();
+        let solution = solve(pos, moves, &AtomicBool::new(false))?;
+        let line = san::line_to_san(pos, game.mov, &solution.line);
+        let keys = solution.keys.iter().map(|&ply| san::to_san(pos, ply)).collect();
+        Some((solution, line, keys))
+    }
+
+    #[test]
+    fn mates() {
+        let (solution, line, keys) = solve_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1).unwrap();
+        assert_eq!((solution.moves, line.as_str()), (1, "1. Ra8#"));
+        assert_eq!(keys, ["Ra8#"]);
+
+        // Kb6 forces Kb8, then Rh8#
+        let (solution, line, keys) = solve_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2).unwrap();
+        assert_eq!(solution.moves, 2);
+        assert_eq!(line, "1. Kb6 Kb8 2. Rh8#");
+        assert!(keys.contains(&"Kb6".to_string()));
+
+        // Black to move
+        let (_, line, _) = solve_fen("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", 1).unwrap();
+        assert_eq!(line, "1... Ra1#");
+    }
+
+    #[test]
+    fn no_mate() {
+        assert!(solve_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", 1).is_none());
+        // Stalemate isn't mate
+        assert!(solve_fen("k7/8/1Q6/8/8/8/8/K7 b - - 0 1", 2).is_none());
+    }
+
+    #[test]
+    fn cooks() {
+        // Kc7 Ka7 Ra1# works as well as Kb6
+        let (_, _, keys) = solve_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2).unwrap();
+        assert_eq!(keys, ["Kb6", "Kc7"]);
+
+        // Either rook mates on the back rank
+        let (solution, _, mut keys) = solve_fen("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1", 1).unwrap();
+        assert_eq!(solution.moves, 1);
+        keys.sort();
+        assert_eq!(keys, ["Ra8#", "Re8#"]);
+    }
+
+    #[test]
+    fn stopping() {
+        let game = Game::try_from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        assert!(solve(game.last_position(), 2, &AtomicBool::new(true)).is_none());
+    }
+}
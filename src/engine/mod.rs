@@ -0,0 +1,14 @@
+pub mod book;
+pub mod endgame;
+pub mod eval;
+pub mod mate;
+pub mod nnue;
+pub mod options;
+pub mod search;
+pub mod sprt;
+pub mod syzygy;
+pub mod time;
+pub mod tournament;
+pub mod tune;
+pub mod tt;
+pub mod worker;
@@ -0,0 +1,308 @@
+use std::fs;
+use std::sync::Arc;
+
+use crate    :: {
+    core     :: bits,
+    ply      :: { Colour, Piece },
+    position :: Position,
+};
+
+use super::eval::{ Evaluator, PIECES };
+
+const MAGIC: &[u8; 8] = b"ACPNNUE\0";
+/// One input for every piece of either colour on every square
+pub const INPUTS: usize = 768;
+/// Largest hidden layer accepted, so a corrupt header can't ask for gigabytes
+const MAX_HIDDEN: usize = 4096;
+/// Hidden layer quantisation, activations are clipped to 0..=QA
+const QA: i32 = 255;
+/// Output weight quantisation
+const QB: i32 = 64;
+/// Converts the network's output to centipawns
+const SCALE: i64 = 400;
+
+/// Input index of a piece as seen by `perspective`: its own pieces first, and the board flipped for black
+fn feature(perspective: Colour, piece: Piece, colour: Colour, sq: usize) -> usize {
+    let (side, sq) = match perspective {
+        Colour::White => (colour as usize, sq),
+        Colour::Black => (colour.opposite() as usize, sq ^ 56),
+    };
+    (side * 6 + piece as usize) * 64 + sq
+}
+
+fn dot_crelu_scalar(values: &[i16], weights: &[i16]) -> i32 {
+    values.iter().zip(weights).map(|(&v, &w)| (v as i32).clamp(0, QA) * w as i32).sum()
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_crelu_avx2(values: &[i16], weights: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+
+    let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi16(QA as i16));
+    let mut sum = _mm256_setzero_si256();
+    let chunks = values.len() / 16;
+    for i in 0..chunks {
+        let v = _mm256_loadu_si256(values.as_ptr().add(i * 16) as *const __m256i);
+        let w = _mm256_loadu_si256(weights.as_ptr().add(i * 16) as *const __m256i);
+        let v = _mm256_min_epi16(_mm256_max_epi16(v, zero), max);
+        // Pairs of 16 bit products summed into 32 bit lanes
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(v, w));
+    }
+
+    let mut lanes = [0i32; 8];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+    lanes.iter().sum::<i32>() + dot_crelu_scalar(&values[chunks * 16..], &weights[chunks * 16..])
+}
+
+/// Sum of the clipped activations times their weights
+fn dot_crelu(values: &[i16], weights: &[i16]) -> i32 {
+    debug_assert_eq!(values.len(), weights.len());
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is available, and the loads stay within the slices as only whole chunks are read
+        return unsafe { dot_crelu_avx2(values, weights) }
+    }
+    dot_crelu_scalar(values, weights)
+}
+
+/// A network with one hidden layer, fed by an accumulator for each side.
+///
+/// File layout, little endian: the magic `ACPNNUE\0`, the hidden layer size as a u32, then as i16s
+/// the input weights (one hidden layer's worth per input), the hidden biases and the output weights
+/// (side to move's half first), and finally the output bias as an i32
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read network `{path}`: {e}"))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("{e} in `{path}`"))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let body = bytes.strip_prefix(MAGIC).ok_or("Missing network header")?;
+        let (header, weights) = body.split_at_checked(4).ok_or("Truncated network header")?;
+        let hidden = u32::from_le_bytes(header.try_into().expect("header is 4 bytes")) as usize;
+        if !(1..=MAX_HIDDEN).contains(&hidden) {
+            return Err(format!("Unsupported hidden layer size {hidden}"))
+        }
+
+        let words = INPUTS * hidden + hidden + 2 * hidden;
+        if weights.len() != words * 2 + 4 {
+            return Err(format!("Expected {} bytes of weights but found {}", words * 2 + 4, weights.len()))
+        }
+        let (weights, bias) = weights.split_at(words * 2);
+        let mut words = weights.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+
+        Ok(Self {
+            hidden,
+            feature_weights: words.by_ref().take(INPUTS * hidden).collect(),
+            feature_bias: words.by_ref().take(hidden).collect(),
+            output_weights: words.collect(),
+            output_bias: i32::from_le_bytes(bias.try_into().expect("bias is 4 bytes")),
+        })
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// Both sides' accumulators for a position, white's then black's
+    fn refresh(&self, pos: &Position, acc: &mut [i16]) {
+        for half in acc.chunks_exact_mut(self.hidden) {
+            half.copy_from_slice(&self.feature_bias);
+        }
+        for colour in [Colour::White, Colour::Black] {
+            for piece in PIECES {
+                for bb in bits(pos.board.pieces(piece, colour)) {
+                    self.toggle(acc, piece, colour, bb.trailing_zeros() as usize, true);
+                }
+            }
+        }
+    }
+
+    /// Adds or removes a piece from both accumulators
+    fn toggle(&self, acc: &mut [i16], piece: Piece, colour: Colour, sq: usize, add: bool) {
+        let (white, black) = acc.split_at_mut(self.hidden);
+        for (half, perspective) in [(white, Colour::White), (black, Colour::Black)] {
+            let weights = self.weights(feature(perspective, piece, colour, sq));
+            match add {
+                true  => half.iter_mut().zip(weights).for_each(|(a, w)| *a = a.wrapping_add(*w)),
+                false => half.iter_mut().zip(weights).for_each(|(a, w)| *a = a.wrapping_sub(*w)),
+            }
+        }
+    }
+
+    /// Centipawns from the point of view of `side`, the side to move
+    fn output(&self, acc: &[i16], side: Colour) -> i32 {
+        let (white, black) = acc.split_at(self.hidden);
+        let (us, them) = match side {
+            Colour::White => (white, black),
+            Colour::Black => (black, white),
+        };
+        let (w_us, w_them) = self.output_weights.split_at(self.hidden);
+        let sum = dot_crelu(us, w_us) as i64 + dot_crelu(them, w_them) as i64 + self.output_bias as i64;
+        (sum * SCALE / (QA * QB) as i64) as i32
+    }
+}
+
+/// Evaluates with a network, updating the accumulators with only the pieces each move changes
+pub struct Nnue {
+    network: Arc<Network>,
+    /// Accumulators along the search path, each being both sides' back to back
+    stack: Vec<i16>,
+    depth: usize,
+}
+
+impl Nnue {
+    pub fn new(network: Arc<Network>) -> Self {
+        let stack = vec![0; 2 * network.hidden];
+        Self { network, stack, depth: 0 }
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        let size = 2 * self.network.hidden;
+        self.depth * size..(self.depth + 1) * size
+    }
+}
+
+impl Evaluator for Nnue {
+    fn reset(&mut self, pos: &Position) {
+        self.depth = 0;
+        let range = self.range();
+        self.network.refresh(pos, &mut self.stack[range]);
+    }
+
+    fn push(&mut self, pos: &Position, child: &Position) {
+        let size = 2 * self.network.hidden;
+        let start = self.depth * size;
+        if self.stack.len() < start + 2 * size {
+            self.stack.resize(start + 2 * size, 0);
+        }
+        self.stack.copy_within(start..start + size, start + size);
+        self.depth += 1;
+
+        // Comparing the boards covers captures, promotions, castling and en passant alike
+        let range = self.range();
+        let acc = &mut self.stack[range];
+        for colour in [Colour::White, Colour::Black] {
+            for piece in PIECES {
+                let (before, after) = (pos.board.pieces(piece, colour), child.board.pieces(piece, colour));
+                for bb in bits(before & !after) {
+                    self.network.toggle(acc, piece, colour, bb.trailing_zeros() as usize, false);
+                }
+                for bb in bits(after & !before) {
+                    self.network.toggle(acc, piece, colour, bb.trailing_zeros() as usize, true);
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn evaluate(&mut self, pos: &Position) -> i32 {
+        self.network.output(&self.stack[self.range()], pos.side_to_move())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ engine::{ options::Options, search::{ Limits, Searcher } }, game::Game };
+
+    /// A network of small pseudo-random weights, as a file would hold it
+    fn random_network(hidden: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |range: i16| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (2 * range as u64 + 1)) as i16 - range
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((hidden as u32).to_le_bytes());
+        for _ in 0..INPUTS * hidden {
+            bytes.extend(next(40).to_le_bytes());
+        }
+        for _ in 0..hidden {
+            bytes.extend(next(100).to_le_bytes());
+        }
+        for _ in 0..2 * hidden {
+            bytes.extend(next(64).to_le_bytes());
+        }
+        bytes.extend(1234_i32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn loading() {
+        let bytes = random_network(8);
+        let network = Network::from_bytes(&bytes).unwrap();
+        assert_eq!(network.hidden, 8);
+        assert_eq!(network.output_bias, 1234);
+
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(b"ACPNNUE\0\0\0\0\0").is_err());
+        assert!(Network::from_bytes(b"not a network").is_err());
+    }
+
+    #[test]
+    fn incremental_updates_match_a_refresh() {
+        let network = Arc::new(Network::from_bytes(&random_network(32)).unwrap());
+        let mut nnue = Nnue::new(network.clone());
+        // Castling, en passant and promotion with capture, then their null move
+        let game = Game::try_from_fen("r3k2r/1P4p1/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let root = *game.last_position();
+        nnue.reset(&root);
+        let before = nnue.evaluate(&root);
+
+        let mut fresh = vec![0; 2 * network.hidden];
+        for ply in root.legal_plies() {
+            let child = root.make_ply(ply);
+            nnue.push(&root, &child);
+            network.refresh(&child, &mut fresh);
+            assert_eq!(&nnue.stack[nnue.range()], &fresh[..], "{}", ply.to_uci());
+
+            let null = child.make_null();
+            nnue.push(&child, &null);
+            assert_eq!(nnue.evaluate(&null), network.output(&fresh, null.side_to_move()));
+            nnue.pop();
+            nnue.pop();
+        }
+        assert_eq!(nnue.evaluate(&root), before);
+    }
+
+    #[test]
+    fn searching_with_a_network() {
+        let path = std::env::temp_dir().join(format!("nnue-test-{}.bin", std::process::id()));
+        fs::write(&path, random_network(16)).unwrap();
+
+        let mut searcher = Searcher::new(Options { threads: 2, ..Default::default() });
+        assert!(searcher.set_option("EvalFile", "no/such/network.bin").is_err());
+        assert_eq!(searcher.options.eval_file, "");
+        searcher.set_option("EvalFile", path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let game = Game::try_from_fen(crate::core::INITIAL_FEN).unwrap();
+        let limits = Limits { depth: Some(4), ..Default::default() };
+        let res = searcher.search(game.last_position(), &[], limits, |_| {});
+        assert!(res.best.is_some_and(|best| game.last_position().legal_plies().contains(&best)));
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        let values: Vec<i16> = (0..100).map(|i| (i * 37 % 600) as i16 - 200).collect();
+        let weights: Vec<i16> = (0..100).map(|i| (i * 53 % 256) as i16 - 128).collect();
+        assert_eq!(dot_crelu(&values, &weights), dot_crelu_scalar(&values, &weights));
+    }
+}
@@ -0,0 +1,135 @@
+pub const HASH_RANGE: (usize, usize) = (1, 4096);
+pub const THREADS_RANGE: (usize, usize) = (1, 256);
+pub const MULTI_PV_RANGE: (usize, usize) = (1, 256);
+/// In milliseconds
+pub const MOVE_OVERHEAD_RANGE: (usize, usize) = (0, 5000);
+
+#[derive(Debug, Clone)]
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: usize, min: usize, max: usize },
+    String { default: String },
+}
+
+/// Describes an option to front-ends, such as for UCI's `option` command
+#[derive(Debug, Clone)]
+pub struct OptionInfo {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+/// Engine settings that front-ends can change by name.
+/// Each selectivity technique can be switched off individually to measure its effect
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub hash_mb: usize,
+    /// Search threads, all sharing the transposition table
+    pub threads: usize,
+    /// Number of best lines to search and report
+    pub multi_pv: usize,
+    /// Time kept back from every move for communication delays, in milliseconds
+    pub move_overhead_ms: usize,
+    /// Play from the opening book while it has moves
+    pub own_book: bool,
+    /// Path to a Polyglot book, none if empty
+    pub book_file: String,
+    /// Always play the book's most played move rather than choosing at random by weight
+    pub book_best: bool,
+    /// Directories holding Syzygy tablebases, separated as in `PATH`, none if empty
+    pub syzygy_path: String,
+    /// Path to an NNUE network to evaluate with, the hand-written evaluation if empty
+    pub eval_file: String,
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub futility_pruning: bool,
+    pub reverse_futility_pruning: bool,
+    pub check_extensions: bool,
+    pub aspiration_windows: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            hash_mb: 16,
+            threads: 1,
+            multi_pv: 1,
+            move_overhead_ms: 30,
+            own_book: false,
+            book_file: String::new(),
+            book_best: false,
+            syzygy_path: String::new(),
+            eval_file: String::new(),
+            null_move: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            check_extensions: true,
+            aspiration_windows: true,
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true"  | "on"  | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(format!("Expected true or false, got `{value}`")),
+    }
+}
+
+fn parse_spin(value: &str, min: usize, max: usize) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(format!("Expected a number between {min} and {max}, got `{value}`")),
+    }
+}
+
+impl Options {
+    /// All options along with their current values
+    pub fn list(&self) -> Vec<OptionInfo> {
+        let check = |name, default| OptionInfo { name, kind: OptionKind::Check { default } };
+        vec![
+            OptionInfo { name: "Hash", kind: OptionKind::Spin { default: self.hash_mb, min: HASH_RANGE.0, max: HASH_RANGE.1 } },
+            OptionInfo { name: "Threads", kind: OptionKind::Spin { default: self.threads, min: THREADS_RANGE.0, max: THREADS_RANGE.1 } },
+            OptionInfo { name: "MultiPV", kind: OptionKind::Spin { default: self.multi_pv, min: MULTI_PV_RANGE.0, max: MULTI_PV_RANGE.1 } },
+            OptionInfo {
+                name: "Move Overhead",
+                kind: OptionKind::Spin { default: self.move_overhead_ms, min: MOVE_OVERHEAD_RANGE.0, max: MOVE_OVERHEAD_RANGE.1 },
+            },
+            check("OwnBook", self.own_book),
+            OptionInfo { name: "BookFile", kind: OptionKind::String { default: self.book_file.clone() } },
+            check("BookBestMove", self.book_best),
+            OptionInfo { name: "SyzygyPath", kind: OptionKind::String { default: self.syzygy_path.clone() } },
+            OptionInfo { name: "EvalFile", kind: OptionKind::String { default: self.eval_file.clone() } },
+            check("NullMove", self.null_move),
+            check("LMR", self.late_move_reductions),
+            check("Futility", self.futility_pruning),
+            check("ReverseFutility", self.reverse_futility_pruning),
+            check("CheckExtensions", self.check_extensions),
+            check("AspirationWindows", self.aspiration_windows),
+        ]
+    }
+
+    /// Sets an option by name, names are case insensitive
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "hash"              => self.hash_mb = parse_spin(value, HASH_RANGE.0, HASH_RANGE.1)?,
+            "threads"           => self.threads = parse_spin(value, THREADS_RANGE.0, THREADS_RANGE.1)?,
+            "multipv"           => self.multi_pv = parse_spin(value, MULTI_PV_RANGE.0, MULTI_PV_RANGE.1)?,
+            "move overhead"     => self.move_overhead_ms = parse_spin(value, MOVE_OVERHEAD_RANGE.0, MOVE_OVERHEAD_RANGE.1)?,
+            "ownbook"           => self.own_book = parse_bool(value)?,
+            "bookfile"          => self.book_file = value.to_string(),
+            "bookbestmove"      => self.book_best = parse_bool(value)?,
+            "syzygypath"        => self.syzygy_path = value.to_string(),
+            "evalfile"          => self.eval_file = value.to_string(),
+            "nullmove"          => self.null_move = parse_bool(value)?,
+            "lmr"               => self.late_move_reductions = parse_bool(value)?,
+            "futility"          => self.futility_pruning = parse_bool(value)?,
+            "reversefutility"   => self.reverse_futility_pruning = parse_bool(value)?,
+            "checkextensions"   => self.check_extensions = parse_bool(value)?,
+            "aspirationwindows" => self.aspiration_windows = parse_bool(value)?,
+            _ => return Err(format!("Unknown option `{name}`")),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,967 @@
+use std::sync::{ atomic::{ AtomicBool, AtomicU64, Ordering }, Arc };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use crate    :: {
+    ply      :: { Piece, Ply, PromotablePiece },
+    position :: Position,
+};
+
+use super :: {
+    book    :: { Book, Selection },
+    eval    :: { piece_value, Evaluator, Hce },
+    mate    :: { self, MAX_MATE },
+    nnue    :: { Network, Nnue },
+    options :: Options,
+    syzygy  :: { Tablebases, Wdl },
+    time    :: { Clock, TimeManager },
+    tt      :: { self, Bound, Entry, TranspositionTable },
+};
+
+pub const INFINITY: i32 = 32_000;
+pub const MATE: i32 = 31_000;
+pub const MAX_PLY: usize = 128;
+pub const MAX_DEPTH: u8 = 64;
+/// Any score beyond this is a forced mate
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+/// Tablebase wins score below any mate but above any evaluation
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32;
+
+/// Futility margins indexed by remaining depth
+const FUTILITY_MARGIN: [i32; 4] = [0, 100, 250, 400];
+const REVERSE_FUTILITY_MARGIN: i32 = 90;
+const ASPIRATION_WINDOW: i32 = 25;
+/// Helper threads skip depths in these patterns so they spread over different depths, indexed by thread
+const SKIP_SIZE: [u8; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u8; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+fn skips_depth(id: usize, depth: u8) -> bool {
+    let i = (id - 1) % SKIP_SIZE.len();
+    (depth + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 == 1
+}
+
+/// Converts a score to the number of moves until mate, positive if the side to move is mating
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score > MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score < -MATE_BOUND {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    /// Remaining time for clocked games, managed by a [`TimeManager`]
+    pub clock: Option<Clock>,
+    /// Solve for a mate in this many moves or fewer, then search normally if there is none, stopping at any mate found
+    pub mate: Option<u8>,
+    /// Only consider these moves at the root, all moves if empty
+    pub searchmoves: Vec<Ply>,
+}
+
+/// Reported after every completed iteration
+#[derive(Debug, Clone)]
+pub struct Info {
+    pub depth: u8,
+    pub seldepth: u8,
+    /// Rank of this line when searching several, from 1
+    pub multipv: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub hashfull: usize,
+    pub tbhits: u64,
+    pub pv: Vec<Ply>,
+}
+
+impl Info {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as u128 * 1000 / self.elapsed.as_millis().max(1)) as u64
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best: Option<Ply>,
+    pub ponder: Option<Ply>,
+    pub score: i32,
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+/// Counts summed over every search thread, each adding its own every 1024 nodes
+#[derive(Default)]
+struct Counters {
+    nodes: AtomicU64,
+    tbhits: AtomicU64,
+}
+
+pub struct Searcher {
+    pub options: Options,
+    tt: Arc<TranspositionTable>,
+    book: Option<Book>,
+    tablebases: Option<Arc<Tablebases>>,
+    network: Option<Arc<Network>>,
+    evaluator: Box<dyn Evaluator>,
+    /// 0 for the main thread
+    id: usize,
+    /// Searchers for the other threads, which run alongside the main thread during a search
+    helpers: Vec<Searcher>,
+    helper_stop: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+    /// Node and tablebase hit counts already added to `counters`
+    flushed: (u64, u64),
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    limits: Limits,
+    time: Option<TimeManager>,
+    start: Instant,
+    stopped: bool,
+    pondering: bool,
+    nodes: u64,
+    tbhits: u64,
+    seldepth: usize,
+    /// Hashes of every position before the current one, game history first then the search path
+    path: Vec<u64>,
+    /// Root moves already given a line in this iteration of a MultiPV search
+    excluded: Vec<Ply>,
+    killers: [[Option<Ply>; 2]; MAX_PLY],
+    /// Indexed by [colour][from][to]
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    lmr: [[i32; 64]; 64],
+}
+
+impl Searcher {
+    pub fn new(options: Options) -> Self {
+        let tt = Arc::new(TranspositionTable::new(options.hash_mb));
+        let mut searcher = Self::thread(options, tt, 0, Arc::new(AtomicBool::new(false)), Arc::default());
+        searcher.make_helpers();
+        searcher
+    }
+
+    fn thread(options: Options, tt: Arc<TranspositionTable>, id: usize, stop: Arc<AtomicBool>, counters: Arc<Counters>) -> Self {
+        let mut lmr = [[0; 64]; 64];
+        for (depth, row) in lmr.iter_mut().enumerate().skip(1) {
+            for (moves, r) in row.iter_mut().enumerate().skip(1) {
+                *r = (0.75 + (depth as f64).ln() * (moves as f64).ln() / 2.25) as i32;
+            }
+        }
+
+        Self {
+            tt,
+            book: None,
+            tablebases: None,
+            network: None,
+            evaluator: Box::new(Hce),
+            options,
+            id,
+            helpers: Vec::new(),
+            helper_stop: Arc::new(AtomicBool::new(false)),
+            counters,
+            flushed: (0, 0),
+            stop,
+            ponder: Arc::new(AtomicBool::new(false)),
+            limits: Limits::default(),
+            time: None,
+            start: Instant::now(),
+            stopped: false,
+            pondering: false,
+            nodes: 0,
+            tbhits: 0,
+            seldepth: 0,
+            path: Vec::new(),
+            excluded: Vec::new(),
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            lmr,
+        }
+    }
+
+    /// Recreates the helper threads' searchers to match the options
+    fn make_helpers(&mut self) {
+        let options = Options { threads: 1, multi_pv: 1, ..self.options.clone() };
+        self.helpers = (1..self.options.threads)
+            .map(|id| {
+                let mut helper = Self::thread(options.clone(), self.tt.clone(), id, self.helper_stop.clone(), self.counters.clone());
+                helper.tablebases = self.tablebases.clone();
+                helper.network = self.network.clone();
+                helper.evaluator = helper.make_evaluator();
+                helper
+            })
+            .collect();
+    }
+
+    fn make_evaluator(&self) -> Box<dyn Evaluator> {
+        match &self.network {
+            Some(network) => Box::new(Nnue::new(network.clone())),
+            None => Box::new(Hce),
+        }
+    }
+
+    /// Shared flag that aborts a running search when set.
+    /// NOTE: The search never clears it, the owner must reset it before starting a new search
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Shared flag that suspends the time limits while set. Clearing it mid-search (a ponder hit)
+    /// starts the clock from that moment
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        self.ponder.clone()
+    }
+
+    /// Sets an option by name, resizing the transposition table or loading the book, tablebases or network if needed
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let (hash_mb, book_file, syzygy_path) = (self.options.hash_mb, self.options.book_file.clone(), self.options.syzygy_path.clone());
+        let eval_file = self.options.eval_file.clone();
+        self.options.set(name, value)?;
+        if self.options.hash_mb != hash_mb {
+            self.tt = Arc::new(TranspositionTable::new(self.options.hash_mb));
+        }
+        if self.options.book_file != book_file {
+            match self.options.book_file.as_str() {
+                "" => self.book = None,
+                path => match Book::open(path) {
+                    Ok(book) => self.book = Some(book),
+                    Err(e) => {
+                        self.options.book_file = book_file;
+                        return Err(e)
+                    },
+                },
+            }
+        }
+        if self.options.syzygy_path != syzygy_path {
+            match self.options.syzygy_path.as_str() {
+                "" => self.tablebases = None,
+                path => match Tablebases::open(path) {
+                    Ok(tablebases) => self.tablebases = Some(Arc::new(tablebases)),
+                    Err(e) => {
+                        self.options.syzygy_path = syzygy_path;
+                        return Err(e)
+                    },
+                },
+            }
+        }
+        if self.options.eval_file != eval_file {
+            match self.options.eval_file.as_str() {
+                "" => self.network = None,
+                path => match Network::open(path) {
+                    Ok(network) => self.network = Some(Arc::new(network)),
+                    Err(e) => {
+                        self.options.eval_file = eval_file;
+                        return Err(e)
+                    },
+                },
+            }
+            self.evaluator = self.make_evaluator();
+        }
+        self.make_helpers();
+        Ok(())
+    }
+
+    pub fn book(&self) -> Option<&Book> {
+        self.book.as_ref()
+    }
+
+    pub fn tablebases(&self) -> Option<&Tablebases> {
+        self.tablebases.as_deref()
+    }
+
+    /// Forgets everything learned from previous searches, e.g. for a new game
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.killers = [[None; 2]; MAX_PLY];
+        *self.history = [[[0; 64]; 64]; 2];
+        for helper in &mut self.helpers {
+            helper.killers = [[None; 2]; MAX_PLY];
+            *helper.history = [[[0; 64]; 64]; 2];
+        }
+    }
+
+    /// Adds this thread's counts since the last flush to the shared totals
+    fn flush(&mut self) {
+        self.counters.nodes.fetch_add(self.nodes - self.flushed.0, Ordering::Relaxed);
+        self.counters.tbhits.fetch_add(self.tbhits - self.flushed.1, Ordering::Relaxed);
+        self.flushed = (self.nodes, self.tbhits);
+    }
+
+    /// Nodes searched by every thread
+    fn total_nodes(&self) -> u64 {
+        self.counters.nodes.load(Ordering::Relaxed) + self.nodes - self.flushed.0
+    }
+
+    fn total_tbhits(&self) -> u64 {
+        self.counters.tbhits.load(Ordering::Relaxed) + self.tbhits - self.flushed.1
+    }
+
+    /// Resets the per search state
+    fn prepare(&mut self, previous: &[u64], limits: Limits) {
+        self.limits = limits;
+        self.start = Instant::now();
+        self.stopped = false;
+        self.pondering = self.ponder.load(Ordering::Relaxed);
+        self.nodes = 0;
+        self.tbhits = 0;
+        self.flushed = (0, 0);
+        self.path = previous.to_vec();
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history.iter_mut().flatten().flatten().for_each(|h| *h /= 8);
+    }
+
+    /// Iterative deepening search of `root`. `previous` holds the hashes of the game's positions before `root`
+    /// and is used for repetition detection. `report` is called after every completed iteration
+    pub fn search(&mut self, root: &Position, previous: &[u64], limits: Limits, mut report: impl FnMut(&Info)) -> SearchResult {
+        let overhead = Duration::from_millis(self.options.move_overhead_ms as u64);
+        self.time = limits.clock.map(|clock| TimeManager::new(&clock, overhead));
+        self.prepare(previous, limits);
+        self.counters.nodes.store(0, Ordering::Relaxed);
+        self.counters.tbhits.store(0, Ordering::Relaxed);
+
+        let mut legal = root.legal_plies();
+        if !self.limits.searchmoves.is_empty() {
+            legal.retain(|p| self.limits.searchmoves.contains(p));
+        }
+        // Only search the moves that keep the tablebase result, unless that rules out every move asked for
+        if let Some(moves) = self.tablebases.as_ref().and_then(|tb| tb.root_moves(root)) {
+            let kept: Vec<Ply> = legal.iter().copied().filter(|p| moves.contains(p)).collect();
+            if !kept.is_empty() {
+                self.tbhits += 1;
+                legal = kept;
+                self.limits.searchmoves = legal.clone();
+            }
+        }
+        let mut result = SearchResult { best: legal.first().copied(), ponder: None, score: 0, depth: 0, nodes: 0 };
+        if legal.is_empty() {
+            return result
+        }
+
+        // Mate problems go to the dedicated solver first, falling back to a normal search if it finds nothing
+        if let Some(n) = self.limits.mate.filter(|n| (1..=MAX_MATE).contains(n) && self.limits.searchmoves.is_empty()) {
+            if let Some(solution) = mate::solve(root, n, &self.stop) {
+                let depth = 2 * solution.moves - 1;
+                let score = MATE - depth as i32;
+                report(&Info {
+                    depth,
+                    seldepth: depth,
+                    multipv: 1,
+                    score,
+                    nodes: solution.nodes,
+                    elapsed: self.start.elapsed(),
+                    hashfull: self.tt.hashfull(),
+                    tbhits: 0,
+                    pv: solution.line.clone(),
+                });
+                return SearchResult { best: solution.line.first().copied(), ponder: solution.line.get(1).copied(), score, depth, nodes: solution.nodes }
+            }
+        }
+
+        if self.options.own_book {
+            let selection = if self.options.book_best { Selection::Best } else { Selection::Weighted };
+            if let Some(ply) = self.book.as_ref().and_then(|b| b.pick(root, selection)).filter(|p| legal.contains(p)) {
+                result.best = Some(ply);
+                return result
+            }
+        }
+
+        if self.helpers.is_empty() {
+            return self.iterate(root, &legal, &mut report)
+        }
+
+        // Helpers search the same moves, leaving the clock and other limits to the main thread which stops them
+        let helper_limits = Limits { depth: self.limits.depth, searchmoves: self.limits.searchmoves.clone(), ..Default::default() };
+        self.helper_stop.store(false, Ordering::Relaxed);
+        let mut helpers = std::mem::take(&mut self.helpers);
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .map(|helper| {
+                    helper.prepare(previous, helper_limits.clone());
+                    let legal = &legal;
+                    s.spawn(move || helper.iterate(root, legal, &mut |_: &Info| {}))
+                })
+                .collect();
+            let mut results = vec![self.iterate(root, &legal, &mut report)];
+            self.helper_stop.store(true, Ordering::Relaxed);
+            results.extend(handles.into_iter().map(|h| h.join().expect("search threads don't panic")));
+            results
+        });
+        self.helpers = helpers;
+
+        // Prefer a helper's move only if it got deeper and found something better, though not over reported lines
+        let mut result = results[0].clone();
+        for other in results[1..].iter().filter(|_| self.options.multi_pv == 1) {
+            if other.best.is_some() && other.depth > result.depth && other.score > result.score {
+                result = other.clone();
+            }
+        }
+        result.nodes = self.counters.nodes.load(Ordering::Relaxed);
+        result
+    }
+
+    /// Iterative deepening over the root's legal moves, as run by every thread
+    fn iterate(&mut self, root: &Position, legal: &[Ply], report: &mut impl FnMut(&Info)) -> SearchResult {
+        self.evaluator.reset(root);
+        let mut result = SearchResult { best: legal.first().copied(), ponder: None, score: 0, depth: 0, nodes: 0 };
+        let max_depth = self.limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        // Each line's score and PV from the last iteration, best first
+        let mut lines: Vec<(i32, Vec<Ply>)> = vec![(0, Vec::new()); self.options.multi_pv.clamp(1, legal.len().max(1))];
+
+        for depth in 1..=max_depth {
+            if self.id > 0 && skips_depth(self.id, depth) {
+                continue
+            }
+            self.seldepth = 0;
+            // Every line after the first searches the root without the moves of the lines before it
+            self.excluded.clear();
+            let mut found = Vec::with_capacity(lines.len());
+            for (previous, _) in &lines {
+                let mut pv = Vec::new();
+                let score = self.aspiration(root, depth, *previous, &mut pv);
+                let Some(&first) = pv.first() else { break };
+                self.excluded.push(first);
+                found.push((score, pv));
+            }
+            self.excluded.clear();
+            // An aborted iteration can't be trusted
+            if self.stopped || found.is_empty() {
+                break
+            }
+            found.sort_by_key(|(score, _)| -score);
+            lines = found;
+
+            let (score, pv) = (lines[0].0, &lines[0].1);
+            result = SearchResult {
+                best: pv.first().copied().or(result.best),
+                ponder: pv.get(1).copied(),
+                score,
+                depth,
+                nodes: self.total_nodes(),
+            };
+            for (i, (score, pv)) in lines.iter().enumerate() {
+                report(&Info {
+                    depth,
+                    seldepth: self.seldepth as u8,
+                    multipv: i + 1,
+                    score: *score,
+                    nodes: self.total_nodes(),
+                    elapsed: self.start.elapsed(),
+                    hashfull: self.tt.hashfull(),
+                    tbhits: self.total_tbhits(),
+                    pv: pv.clone(),
+                });
+            }
+
+            if let (Some(tm), Some(best)) = (&mut self.time, result.best) {
+                tm.update(best, score);
+                // With only one legal move there's nothing to think about, though keep going while pondering
+                if !self.pondering && (legal.len() == 1 || !tm.can_continue(self.start.elapsed())) {
+                    break
+                }
+            }
+            if self.limits.mate.is_some_and(|n| mate_in(score).is_some_and(|m| m > 0 && m <= n as i32)) {
+                break
+            }
+            // Searching much deeper than a forced mate won't find anything new
+            if lines.len() == 1 && score.abs() > MATE_BOUND && depth as i32 > 2 * (MATE - score.abs()) {
+                break
+            }
+        }
+
+        self.flush();
+        result.nodes = self.total_nodes();
+        result
+    }
+
+    fn aspiration(&mut self, root: &Position, depth: u8, previous: i32, pv: &mut Vec<Ply>) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = match self.options.aspiration_windows && depth >= 4 && previous.abs() < MATE_BOUND {
+            true  => (previous - delta, previous + delta),
+            false => (-INFINITY, INFINITY),
+        };
+
+        loop {
+            let score = self.negamax(root, depth as i32, alpha, beta, 0, pv, false);
+            if self.stopped {
+                return score
+            }
+            if score <= alpha {
+                if let Some(tm) = self.time.as_mut().filter(|_| self.excluded.is_empty()) {
+                    tm.root_failed_low();
+                }
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score
+            }
+            delta *= 2;
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.nodes.is_multiple_of(1024) {
+            self.flush();
+            if self.pondering && !self.ponder.load(Ordering::Relaxed) {
+                self.pondering = false;
+                self.start = Instant::now();
+            }
+            let elapsed = self.start.elapsed();
+            let out_of_time = !self.pondering && (
+                self.limits.movetime.is_some_and(|t| elapsed >= t) || self.time.as_ref().is_some_and(|tm| tm.out_of_time(elapsed))
+            );
+            self.stopped = out_of_time || self.stop.load(Ordering::Relaxed);
+        }
+        if self.limits.nodes.is_some_and(|n| self.total_nodes() >= n) {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    fn is_draw(&self, pos: &Position) -> bool {
+        if pos.ply_clock >= 100 || pos.is_insufficient_material() {
+            return true
+        }
+        // Only positions with the same side to move since the last irreversible move can repeat
+        let len = self.path.len();
+        (2..=(pos.ply_clock as usize).min(len))
+            .step_by(2)
+            .any(|back| self.path[len - back] == pos.hash)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(&mut self, pos: &Position, mut depth: i32, mut alpha: i32, mut beta: i32, ply: usize, pv: &mut Vec<Ply>, allow_null: bool) -> i32 {
+        pv.clear();
+        let in_check = pos.check.is_some();
+        if in_check && self.options.check_extensions && ply < MAX_PLY / 2 {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(pos, alpha, beta, ply)
+        }
+
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0
+        }
+
+        let is_root = ply == 0;
+        let is_pv = beta - alpha > 1;
+        if !is_root {
+            if self.is_draw(pos) {
+                return 0
+            }
+            if ply >= MAX_PLY - 1 {
+                return self.evaluator.evaluate(pos)
+            }
+            // Mate distance pruning, no point looking for mates longer than one already found
+            alpha = alpha.max(-MATE + ply as i32);
+            beta = beta.min(MATE - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha
+            }
+        }
+
+        // Tablebases are only probed right after captures and pawn moves, as the fifty move rule isn't stored
+        if !is_root && pos.ply_clock == 0 {
+            if let Some(wdl) = self.tablebases.as_ref().and_then(|tb| tb.probe_wdl(pos)) {
+                self.tbhits += 1;
+                let score = match wdl {
+                    Wdl::Win => TB_WIN - ply as i32,
+                    Wdl::Loss => -TB_WIN + ply as i32,
+                    _ => 0,
+                };
+                // A win may still be a mate, so keep searching unless it's good enough
+                let cutoff = match wdl {
+                    Wdl::Win => score >= beta,
+                    Wdl::Loss => score <= alpha,
+                    _ => true,
+                };
+                if cutoff {
+                    return score
+                }
+            }
+        }
+
+        let entry = self.tt.probe(pos.hash);
+        let tt_move = entry.and_then(|e| e.best);
+        if let Some(e) = entry.filter(|e| !is_pv && e.depth as i32 >= depth) {
+            let score = tt::score_from_tt(e.score, ply);
+            match e.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {},
+            }
+        }
+
+        let player = pos.side_to_move();
+        let static_eval = if in_check { -INFINITY } else { self.evaluator.evaluate(pos) };
+
+        if !is_pv && !in_check {
+            // Reverse futility pruning, we are so far ahead that a shallow search won't change anything
+            if self.options.reverse_futility_pruning
+                && depth <= 6
+                && beta.abs() < MATE_BOUND
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return static_eval
+            }
+
+            // Null move pruning. Skipped with only king and pawns, where zugzwang is common
+            if self.options.null_move
+                && allow_null
+                && depth >= 3
+                && static_eval >= beta
+                && pos.has_non_pawn_material(player)
+            {
+                let r = 2 + depth / 4;
+                let null = pos.make_null();
+                self.path.push(pos.hash);
+                self.evaluator.push(pos, &null);
+                let score = -self.negamax(&null, depth - 1 - r, -beta, -beta + 1, ply + 1, &mut Vec::new(), false);
+                self.evaluator.pop();
+                self.path.pop();
+                if self.stopped {
+                    return 0
+                }
+                if score >= beta {
+                    // Don't trust mate scores from a null move
+                    return if score > MATE_BOUND { beta } else { score }
+                }
+            }
+        }
+
+        let futile = self.options.futility_pruning
+            && !is_pv
+            && !in_check
+            && depth < FUTILITY_MARGIN.len() as i32
+            && alpha.abs() < MATE_BOUND
+            && static_eval + FUTILITY_MARGIN[depth as usize] <= alpha;
+
+        let mut moves = pos.pseudo_legal_plies();
+        self.order(pos, &mut moves, tt_move, ply);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut legal = 0;
+        let mut child_pv = Vec::new();
+
+        for ply_ in moves {
+            if is_root && (self.excluded.contains(&ply_) || (!self.limits.searchmoves.is_empty() && !self.limits.searchmoves.contains(&ply_))) {
+                continue
+            }
+            let child = pos.make_ply(ply_);
+            if child.board.king_is_in_check(player) {
+                continue
+            }
+            legal += 1;
+
+            let quiet = !ply_.is_capture && ply_.promotion.is_none();
+            let gives_check = child.check.is_some();
+
+            // Futility pruning, quiet moves can't raise a hopeless static eval above alpha
+            if futile && legal > 1 && quiet && !gives_check {
+                continue
+            }
+
+            self.path.push(pos.hash);
+            self.evaluator.push(pos, &child);
+            let score = if legal == 1 {
+                -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, &mut child_pv, true)
+            } else {
+                // Late move reductions, well ordered late quiet moves are unlikely to be best
+                let reduction = match self.options.late_move_reductions && depth >= 3 && legal > 3 && quiet && !in_check && !gives_check {
+                    true  => self.lmr[depth.min(63) as usize][legal.min(63)].clamp(0, depth - 2),
+                    false => 0,
+                };
+                let mut score = -self.negamax(&child, depth - 1 - reduction, -alpha - 1, -alpha, ply + 1, &mut child_pv, true);
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(&child, depth - 1, -alpha - 1, -alpha, ply + 1, &mut child_pv, true);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, &mut child_pv, true);
+                }
+                score
+            };
+            self.evaluator.pop();
+            self.path.pop();
+
+            if self.stopped {
+                return 0
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(ply_);
+                    pv.clear();
+                    pv.push(ply_);
+                    pv.extend_from_slice(&child_pv);
+
+                    if score >= beta {
+                        if quiet {
+                            self.update_quiet_stats(ply_, depth, ply);
+                        }
+                        break
+                    }
+                }
+            }
+        }
+
+        if legal == 0 {
+            return if in_check { -MATE + ply as i32 } else { 0 }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(Entry {
+            key: pos.hash,
+            best: best_move,
+            score: tt::score_to_tt(best_score, ply),
+            depth: depth as u8,
+            bound,
+        });
+
+        best_score
+    }
+
+    fn quiescence(&mut self, pos: &Position, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if self.should_stop() {
+            return 0
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.evaluator.evaluate(pos)
+        }
+
+        let in_check = pos.check.is_some();
+        let mut best_score = -INFINITY;
+        if !in_check {
+            let stand_pat = self.evaluator.evaluate(pos);
+            if stand_pat >= beta {
+                return stand_pat
+            }
+            alpha = alpha.max(stand_pat);
+            best_score = stand_pat;
+        }
+
+        // Out of check we only look at captures and queen promotions, in check we need every evasion
+        let mut moves = pos.pseudo_legal_plies();
+        if !in_check {
+            moves.retain(|p| p.is_capture || p.promotion == Some(PromotablePiece::Queen));
+        }
+        self.order(pos, &mut moves, None, ply);
+
+        let player = pos.side_to_move();
+        let mut legal = 0;
+        for ply_ in moves {
+            let child = pos.make_ply(ply_);
+            if child.board.king_is_in_check(player) {
+                continue
+            }
+            legal += 1;
+
+            self.evaluator.push(pos, &child);
+            let score = -self.quiescence(&child, -beta, -alpha, ply + 1);
+            self.evaluator.pop();
+            if self.stopped {
+                return 0
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    if score >= beta {
+                        break
+                    }
+                }
+            }
+        }
+
+        if in_check && legal == 0 {
+            return -MATE + ply as i32
+        }
+        best_score
+    }
+
+    fn update_quiet_stats(&mut self, ply_: Ply, depth: i32, ply: usize) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(ply_) {
+            killers[1] = killers[0];
+            killers[0] = Some(ply_);
+        }
+
+        let history = &mut self.history[ply_.mov.player as usize][ply_.mov.from_sq as usize][ply_.mov.to_sq as usize];
+        *history += depth * depth;
+        if *history > 50_000 {
+            self.history.iter_mut().flatten().flatten().for_each(|h| *h /= 2);
+        }
+    }
+
+    fn move_score(&self, pos: &Position, ply_: Ply, tt_move: Option<Ply>, ply: usize) -> i32 {
+        if Some(ply_) == tt_move {
+            return 1_000_000
+        }
+        if ply_.is_capture || ply_.promotion.is_some() {
+            // Most valuable victim, least valuable attacker. En passant leaves the target square empty
+            let victim = match ply_.is_capture {
+                true  => pos.board.piece_at(ply_.mov.to_sq.to_bb()).map_or(Piece::Pawn, |(p, _)| p),
+                false => Piece::King, // worth nothing
+            };
+            let promotion = ply_.promotion.map_or(0, |p| piece_value(p.into()));
+            return 100_000 + piece_value(victim) * 10 - piece_value(ply_.mov.piece) / 10 + promotion
+        }
+        if self.killers[ply][0] == Some(ply_) {
+            return 90_000
+        }
+        if self.killers[ply][1] == Some(ply_) {
+            return 80_000
+        }
+        self.history[ply_.mov.player as usize][ply_.mov.from_sq as usize][ply_.mov.to_sq as usize]
+    }
+
+    fn order(&self, pos: &Position, moves: &mut [Ply], tt_move: Option<Ply>, ply: usize) {
+        moves.sort_by_cached_key(|&p| -self.move_score(pos, p, tt_move, ply));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ core::Square, game::Game };
+
+    fn search(fen: &str, depth: u8, options: Options) -> SearchResult {
+        let game = Game::try_from_fen(fen).unwrap();
+        let mut searcher = Searcher::new(options);
+        let limits = Limits { depth: Some(depth), ..Default::default() };
+        searcher.search(game.last_position(), &[], limits, |_| {})
+    }
+
+    fn no_selectivity() -> Options {
+        Options {
+            null_move: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            check_extensions: false,
+            aspiration_windows: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let res = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3, Options::default());
+        let best = res.best.unwrap();
+        assert_eq!((best.mov.from_sq, best.mov.to_sq), (Square::A1, Square::A8));
+        assert_eq!(mate_in(res.score), Some(1));
+    }
+
+    #[test]
+    fn mate_in_two() {
+        // Kb6 forces Kb8, then Rh8#
+        let fen = "k7/8/2K5/8/8/8/8/7R w - - 0 1";
+        for options in [Options::default(), no_selectivity()] {
+            let res = search(fen, 5, options);
+            assert_eq!(mate_in(res.score), Some(2));
+        }
+    }
+
+    #[test]
+    fn mate_limit_uses_the_solver() {
+        let game = Game::try_from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(Options::default());
+        let mut pvs = Vec::new();
+        let limits = Limits { mate: Some(3), ..Default::default() };
+        let res = searcher.search(game.last_position(), &[], limits, |info| pvs.push(info.pv.len()));
+        assert_eq!(mate_in(res.score), Some(2));
+        assert_eq!((res.depth, pvs), (3, vec![3]));
+        assert!(res.best.is_some() && res.ponder.is_some());
+    }
+
+    #[test]
+    fn stalemate_is_draw() {
+        let res = search("k7/8/1Q6/8/8/8/8/K7 b - - 0 1", 3, Options::default());
+        assert!(res.best.is_none());
+        assert_eq!(res.score, 0);
+    }
+
+    #[test]
+    fn wins_hanging_queen() {
+        let res = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 4, Options::default());
+        let best = res.best.unwrap();
+        assert_eq!((best.mov.from_sq, best.mov.to_sq), (Square::D2, Square::D5));
+    }
+
+    #[test]
+    fn threaded_search() {
+        let options = Options { threads: 4, ..Default::default() };
+        let res = search("k7/8/2K5/8/8/8/8/7R w - - 0 1", 6, options);
+        assert_eq!(mate_in(res.score), Some(2));
+    }
+
+    #[test]
+    fn multiple_lines() {
+        // Taking the queen is best, then the rook
+        let game = Game::try_from_fen("4k3/8/8/1n1R1r2/8/8/3q4/7K w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(Options { multi_pv: 3, ..Default::default() });
+        let limits = Limits { depth: Some(4), ..Default::default() };
+        let mut lines: Vec<Info> = Vec::new();
+        let res = searcher.search(game.last_position(), &[], limits, |info| {
+            if info.depth == 4 {
+                lines.push(info.clone());
+            }
+        });
+
+        assert_eq!(lines.iter().map(|l| l.multipv).collect::<Vec<_>>(), [1, 2, 3]);
+        let targets: Vec<Square> = lines.iter().map(|l| l.pv[0].mov.to_sq).collect();
+        assert_eq!(targets[..2], [Square::D2, Square::F5]);
+        assert_ne!(lines[2].pv[0], lines[0].pv[0]);
+        assert_ne!(lines[2].pv[0], lines[1].pv[0]);
+        assert!(lines.windows(2).all(|w| w[0].score >= w[1].score));
+        assert_eq!(res.best, Some(lines[0].pv[0]));
+    }
+
+    #[test]
+    fn stopping_threads() {
+        let game = Game::try_from_fen(crate::core::INITIAL_FEN).unwrap();
+        let mut searcher = Searcher::new(Options { threads: 3, ..Default::default() });
+        let stop = searcher.stop_handle();
+        let timer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        let mut reported = 0;
+        let res = searcher.search(game.last_position(), &[], Limits::default(), |info| reported = info.nodes);
+        timer.join().unwrap();
+        assert!(res.best.is_some());
+        // Every thread's nodes are counted, not just those reported by the main thread so far
+        assert!(res.nodes >= reported && reported > 0);
+    }
+
+    #[test]
+    fn forced_move_is_played_at_once() {
+        let game = Game::try_from_fen("k7/8/8/8/8/8/1q6/K7 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(Options::default());
+        let clock = Clock { time: Duration::from_secs(600), ..Default::default() };
+        let limits = Limits { clock: Some(clock), ..Default::default() };
+        let res = searcher.search(game.last_position(), &[], limits, |_| {});
+        assert_eq!(res.best.unwrap().mov.to_sq, Square::B2);
+        assert_eq!(res.depth, 1);
+    }
+}
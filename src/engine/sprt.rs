@@ -0,0 +1,156 @@
+/// Two-sided 95% confidence
+const Z_95: f64 = 1.959964;
+
+/// Match results from the first engine's point of view
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// Elo difference for an expected score between 0 and 1
+fn elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Expected score for an Elo difference
+fn expected(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+impl Score {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Adds a result of 1, 0.5 or 0
+    pub fn add(&mut self, result: f64) {
+        match result {
+            r if r > 0.75 => self.wins += 1,
+            r if r < 0.25 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    /// Mean points per game
+    pub fn mean(&self) -> f64 {
+        match self.games() {
+            0 => 0.5,
+            n => (self.wins as f64 + self.draws as f64 / 2.0) / n as f64,
+        }
+    }
+
+    /// Variance of a single game's points
+    fn variance(&self) -> f64 {
+        let (n, mean) = (self.games() as f64, self.mean());
+        if n == 0.0 {
+            return 0.0
+        }
+        (self.wins as f64 * (1.0 - mean).powi(2)
+            + self.draws as f64 * (0.5 - mean).powi(2)
+            + self.losses as f64 * mean.powi(2)) / n
+    }
+
+    /// Elo difference with the half width of its 95% confidence interval
+    pub fn elo(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return (0.0, 0.0)
+        }
+        let (mean, margin) = (self.mean(), Z_95 * (self.variance() / n).sqrt());
+        (elo(mean), (elo(mean + margin) - elo(mean - margin)) / 2.0)
+    }
+}
+
+/// What the test has concluded so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Continue,
+    /// The difference is at most `elo0`
+    AcceptH0,
+    /// The difference is at least `elo1`
+    AcceptH1,
+}
+
+/// Sequential probability ratio test of whether the first engine is `elo1` rather than `elo0` stronger
+#[derive(Debug, Clone, Copy)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// Chance of accepting H1 when H0 holds
+    pub alpha: f64,
+    /// Chance of accepting H0 when H1 holds
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 }
+    }
+}
+
+impl Sprt {
+    /// The log-likelihood ratio stops the test once it leaves these
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// Log-likelihood ratio of the results, using the normal approximation to the trinomial
+    pub fn llr(&self, score: &Score) -> f64 {
+        let variance = score.variance();
+        if variance == 0.0 {
+            return 0.0
+        }
+        let (s0, s1) = (expected(self.elo0), expected(self.elo1));
+        (s1 - s0) * (2.0 * score.mean() - s0 - s1) / (2.0 * variance) * score.games() as f64
+    }
+
+    pub fn decide(&self, score: &Score) -> Decision {
+        let (llr, (lower, upper)) = (self.llr(score), self.bounds());
+        if llr >= upper {
+            Decision::AcceptH1
+        } else if llr <= lower {
+            Decision::AcceptH0
+        } else {
+            Decision::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn score(wins: u32, draws: u32, losses: u32) -> Score {
+        Score { wins, draws, losses }
+    }
+
+    #[test]
+    fn elo_estimates() {
+        assert_eq!(score(0, 0, 0).elo(), (0.0, 0.0));
+        let (even, error) = score(30, 40, 30).elo();
+        assert!(even.abs() < 1e-9 && error > 0.0);
+
+        // Scoring 75% is about 191 Elo
+        let (elo, _) = score(50, 0, 0).elo();
+        assert!(elo > 1000.0);
+        let (elo, error) = score(60, 30, 10).elo();
+        assert!((elo - 190.8).abs() < 0.1, "{elo}");
+        // More games narrow the interval
+        assert!(score(600, 300, 100).elo().1 < error);
+    }
+
+    #[test]
+    fn sprt_decisions() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 0.001 && (lower + 2.944).abs() < 0.001);
+
+        assert_eq!(sprt.decide(&score(10, 10, 10)), Decision::Continue);
+        assert_eq!(sprt.decide(&score(600, 1000, 400)), Decision::AcceptH1);
+        assert_eq!(sprt.decide(&score(400, 1000, 600)), Decision::AcceptH0);
+        assert!(sprt.llr(&score(0, 0, 0)) == 0.0);
+    }
+}
@@ -0,0 +1,1131 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::sync::OnceLock;
+
+use crate    :: {
+    core     :: { bits, Square },
+    ply      :: { Colour, Piece, Ply },
+    position :: Position,
+};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+/// Letters in the order table names use, i.e. `KQRvKN`
+const PIECE_LETTERS: [(Piece, char); 6] = [
+    (Piece::King, 'K'), (Piece::Queen, 'Q'), (Piece::Rook, 'R'), (Piece::Bishop, 'B'), (Piece::Knight, 'N'), (Piece::Pawn, 'P'),
+];
+const MAX_PIECES: usize = 7;
+
+// Flags of a table's compressed data
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// Win/draw/loss for the side to move. Cursed wins and blessed losses are won or lost
+/// over the board but drawn by the fifty move rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+/// Lookup tables for the position index, shared by every table
+struct Indices {
+    /// Squares below the a1-h8 diagonal to 0..27
+    b1h1h7: [u64; 64],
+    /// The a1-d1-d4 triangle to 0..9, diagonal last
+    a1d1d4: [u64; 64],
+    /// The 462 ways to place two kings with the first in the a1-d1-d4 triangle
+    kk: [[u64; 64]; 10],
+    /// Indexed by [pieces][squares]
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// a2-h7 to 0..47, highest for the pawn nearest the edge and then the lowest rank
+    pawns: [u64; 64],
+    /// Indexed by [lead pawns][square]
+    lead_pawn_idx: [[u64; 64]; 6],
+    /// Indexed by [lead pawns][file]
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn file_of(sq: usize) -> usize { sq % 8 }
+fn rank_of(sq: usize) -> usize { sq / 8 }
+/// Negative below the a1-h8 diagonal, positive above
+fn off_diagonal(sq: usize) -> i32 { rank_of(sq) as i32 - file_of(sq) as i32 }
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(|| {
+        let mut ix = Indices {
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        for (code, sq) in (0..64).filter(|&sq| off_diagonal(sq) < 0).enumerate() {
+            ix.b1h1h7[sq] = code as u64;
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for sq in (0..=Square::D4 as usize).filter(|&sq| file_of(sq) <= 3) {
+            match off_diagonal(sq) {
+                ..0 => {
+                    ix.a1d1d4[sq] = code;
+                    code += 1;
+                },
+                0 => diagonal.push(sq),
+                _ => {},
+            }
+        }
+        for sq in diagonal {
+            ix.a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            let triangle = (0..=Square::D4 as usize)
+                .filter(|&s1| file_of(s1) <= 3 && off_diagonal(s1) <= 0 && ix.a1d1d4[s1] == idx as u64);
+            for s1 in triangle {
+                for s2 in 0..64 {
+                    let touching = file_of(s1).abs_diff(file_of(s2)) <= 1 && rank_of(s1).abs_diff(rank_of(s2)) <= 1;
+                    if touching || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        ix.kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            ix.kk[idx][s2] = code;
+            code += 1;
+        }
+
+        ix.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                ix.binomial[k][n] = if k > 0 { ix.binomial[k - 1][n - 1] } else { 0 }
+                                  + if k < n { ix.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        for lead in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead == 1 {
+                        // Free squares left for the other pawns, a2 leaves 47, h2 46, a3 45...
+                        let available = 47 - 2 * (file * 6 + rank - 1) as u64;
+                        ix.pawns[sq] = available;
+                        ix.pawns[sq ^ 7] = available - 1;
+                    }
+                    ix.lead_pawn_idx[lead][sq] = idx;
+                    idx += ix.binomial[lead - 1][ix.pawns[sq] as usize];
+                }
+                ix.lead_pawns_size[lead][file] = idx;
+            }
+        }
+        ix
+    })
+}
+
+/// The material a table covers, from its name
+#[derive(Debug, Clone)]
+struct Material {
+    piece_count: usize,
+    has_pawns: bool,
+    /// Both sides have pawns
+    pawns_both: bool,
+    /// Some piece other than a king is alone of its kind, so three pieces can be indexed together
+    unique_pieces: bool,
+    /// Same material on both sides
+    symmetric: bool,
+}
+
+impl Material {
+    fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let valid = |side: &str| side.starts_with('K') && side.chars().filter(|&c| c == 'K').count() == 1
+            && side.chars().all(|c| PIECE_LETTERS.iter().any(|&(_, l)| l == c));
+        if !valid(white) || !valid(black) {
+            return None
+        }
+        let unique = |side: &str| "QRBNP".chars().any(|c| side.matches(c).count() == 1);
+
+        Some(Self {
+            piece_count: white.len() + black.len(),
+            has_pawns: name.contains('P'),
+            pawns_both: white.contains('P') && black.contains('P'),
+            unique_pieces: unique(white) || unique(black),
+            symmetric: white == black,
+        })
+    }
+}
+
+/// One side's and file's compressed values
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    /// Piece codes in the order they're indexed, see [`piece_code`]
+    pieces: [u8; MAX_PIECES],
+    group_len: [usize; MAX_PIECES + 1],
+    group_idx: [u64; MAX_PIECES + 1],
+    block_size: usize,
+    span: u64,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_lengths: usize,
+    block_lengths_size: usize,
+    blocks: usize,
+    data: usize,
+    max_sym_len: u8,
+    /// Also the value of single valued tables
+    min_sym_len: u8,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    btree: usize,
+    /// Offsets of the DTZ value maps for wins, losses, cursed wins and blessed losses
+    map_idx: [usize; 4],
+}
+
+/// Syzygy's piece codes: pawn to king are 1 to 6, plus 8 for black
+fn piece_code(piece: Piece, colour: Colour) -> u8 {
+    let code = match piece {
+        Piece::Pawn   => 1,
+        Piece::Knight => 2,
+        Piece::Bishop => 3,
+        Piece::Rook   => 4,
+        Piece::Queen  => 5,
+        Piece::King   => 6,
+    };
+    if colour == Colour::Black { code + 8 } else { code }
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// A parsed `.rtbw` or `.rtbz` file
+#[derive(Debug)]
+struct Table {
+    data: Vec<u8>,
+    material: Material,
+    /// Indexed by [side][file], files only matter with pawns
+    pairs: Vec<Vec<PairsData>>,
+    /// Offset of the DTZ value maps
+    map: usize,
+}
+
+impl Table {
+    fn open(path: &Path, material: &Material, kind: Kind) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Could not read `{}`: {e}", path.display()))?;
+        Self::parse(data, material, kind).map_err(|e| format!("{e} in `{}`", path.display()))
+    }
+
+    fn parse(data: Vec<u8>, material: &Material, kind: Kind) -> Result<Self, String> {
+        let magic = match kind {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        };
+        if data.get(..4) != Some(magic.as_slice()) {
+            return Err("Not a Syzygy table".to_string())
+        }
+        let truncated = || "Truncated table".to_string();
+        let byte = |at: usize| data.get(at).copied().ok_or_else(truncated);
+
+        let flags = byte(4)?;
+        if (flags & 2 != 0) != material.has_pawns || (kind == Kind::Wdl && (flags & 1 != 0) == material.symmetric) {
+            return Err("Table doesn't match its file name".to_string())
+        }
+
+        let sides = if kind == Kind::Wdl && !material.symmetric { 2 } else { 1 };
+        let files = if material.has_pawns { 4 } else { 1 };
+        let pp = material.pawns_both;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+        let mut at = 5;
+
+        for file in 0..files {
+            let (first, second) = (byte(at)?, if pp { byte(at + 1)? } else { 0xFF });
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            at += 1 + pp as usize;
+
+            for k in 0..material.piece_count {
+                let b = byte(at)?;
+                for (side, pairs) in pairs.iter_mut().enumerate() {
+                    pairs[file].pieces[k] = if side == 1 { b >> 4 } else { b & 0xF };
+                }
+                at += 1;
+            }
+            for (side, pairs) in pairs.iter_mut().enumerate() {
+                set_groups(&mut pairs[file], material, order[side], file);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side in pairs.iter_mut() {
+                at = set_sizes(&mut side[file], &data, at).ok_or_else(truncated)?;
+            }
+        }
+
+        let map = at;
+        if kind == Kind::Dtz {
+            for d in pairs[0].iter_mut().filter(|d| d.flags & FLAG_MAPPED != 0) {
+                if d.flags & FLAG_WIDE != 0 {
+                    at += at & 1;
+                    for idx in d.map_idx.iter_mut() {
+                        *idx = (at - map) / 2 + 1;
+                        at += 2 * read_u16(&data, at).ok_or_else(truncated)? as usize + 2;
+                    }
+                } else {
+                    for idx in d.map_idx.iter_mut() {
+                        *idx = at - map + 1;
+                        at += byte(at)? as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side in pairs.iter_mut() {
+                side[file].sparse_index = at;
+                at += side[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side in pairs.iter_mut() {
+                side[file].block_lengths = at;
+                at += side[file].block_lengths_size * 2;
+            }
+        }
+        if at > data.len() {
+            return Err(truncated())
+        }
+        for file in 0..files {
+            for side in pairs.iter_mut() {
+                at = (at + 0x3F) & !0x3F;
+                side[file].data = at;
+                at += side[file].blocks * side[file].block_size;
+            }
+        }
+        // Single valued tables have no blocks, and so no padding either
+        if pairs.iter().flatten().any(|d| d.blocks > 0 && d.data + d.blocks * d.block_size > data.len()) {
+            return Err(truncated())
+        }
+
+        Ok(Self { data, material: material.clone(), pairs, map })
+    }
+
+    /// Reads the value stored at an index
+    fn decompress(&self, d: &PairsData, idx: u64) -> Option<i32> {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(d.min_sym_len as i32)
+        }
+        let data = &self.data;
+
+        // The sparse index points near the block holding the value, then walk the block lengths
+        let k = (idx / d.span) as usize;
+        let entry = d.sparse_index + 6 * k;
+        let mut block = read_u32(data, entry)? as usize;
+        let mut offset = read_u16(data, entry + 4)? as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+        let block_length = |block: usize| read_u16(data, d.block_lengths + 2 * block).map(|l| l as i64);
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        // Canonical Huffman codes, longer codes have lower values
+        let mut at = d.data + block * d.block_size;
+        let mut buf = u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?);
+        at += 8;
+        let mut buf_size = 64;
+        let mut sym;
+
+        loop {
+            let mut len = 0;
+            while buf < *d.base64.get(len)? {
+                len += 1;
+            }
+            let shift = 64 - len as u32 - d.min_sym_len as u32;
+            sym = ((buf - d.base64[len]).checked_shr(shift).unwrap_or(0)) as usize;
+            sym += read_u16(data, d.lowest_sym + 2 * len)? as usize;
+
+            let sym_len = *d.symlen.get(sym)? as i64;
+            if offset < sym_len + 1 {
+                break
+            }
+            offset -= sym_len + 1;
+            let len = len as u32 + d.min_sym_len as u32;
+            buf = buf.checked_shl(len).unwrap_or(0);
+            buf_size -= len as i32;
+
+            if buf_size <= 32 {
+                buf_size += 32;
+                let next = u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?);
+                buf |= (next as u64) << (64 - buf_size);
+                at += 4;
+            }
+        }
+
+        // Symbols expand into pairs of symbols, find the leaf holding the value
+        while *d.symlen.get(sym)? != 0 {
+            let left = self.btree(d, sym, false)?;
+            let left_len = *d.symlen.get(left)? as i64;
+            if offset < left_len + 1 {
+                sym = left;
+            } else {
+                offset -= left_len + 1;
+                sym = self.btree(d, sym, true)?;
+            }
+        }
+        self.btree(d, sym, false).map(|s| s as i32)
+    }
+
+    /// A symbol's left or right hand symbol, 12 bits each
+    fn btree(&self, d: &PairsData, sym: usize, right: bool) -> Option<usize> {
+        let lr = self.data.get(d.btree + 3 * sym..d.btree + 3 * sym + 3)?;
+        Some(match right {
+            false => ((lr[1] as usize & 0xF) << 8) | lr[0] as usize,
+            true  => ((lr[2] as usize) << 4) | (lr[1] as usize >> 4),
+        })
+    }
+
+    /// Converts a DTZ table's stored value to plies
+    fn map_dtz(&self, file: usize, value: i32, wdl: Wdl) -> Option<i32> {
+        let d = &self.pairs[0][file];
+        let mut value = value;
+        if d.flags & FLAG_MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let idx = d.map_idx[map] + value as usize;
+            value = match d.flags & FLAG_WIDE != 0 {
+                true  => read_u16(&self.data, self.map + 2 * idx)? as i32,
+                false => *self.data.get(self.map + idx)? as i32,
+            };
+        }
+
+        let in_moves = match wdl {
+            Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+            Wdl::CursedWin | Wdl::BlessedLoss => true,
+            Wdl::Draw => false,
+        };
+        Some(if in_moves { value * 2 } else { value } + 1)
+    }
+}
+
+/// Splits the pieces into groups indexed together and works out each group's factor in the index
+fn set_groups(d: &mut PairsData, material: &Material, order: [u8; 2], file: usize) {
+    let ix = indices();
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns { 0 } else if material.unique_pieces { 3 } else { 2 };
+    d.group_len[0] = 1;
+
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let pp = material.has_pawns && material.pawns_both;
+    let mut next = if pp { 2 } else { 1 };
+    let mut free = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+    let mut idx = 1;
+    let mut k = 0;
+
+    while next < n || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            d.group_idx[0] = idx;
+            idx *= match (material.has_pawns, material.unique_pieces) {
+                (true, _) => ix.lead_pawns_size[d.group_len[0]][file],
+                (false, true) => 31332,
+                (false, false) => 462,
+            };
+        } else if k == order[1] as usize {
+            d.group_idx[1] = idx;
+            idx *= ix.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= ix.binomial[d.group_len[next]][free];
+            free -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+/// Reads the sizes and Huffman code of one side and file, returning where the next one starts
+fn set_sizes(d: &mut PairsData, data: &[u8], mut at: usize) -> Option<usize> {
+    let byte = |at: usize| data.get(at).copied();
+    d.flags = byte(at)?;
+    at += 1;
+
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        d.min_sym_len = byte(at)?;
+        return Some(at + 1)
+    }
+
+    let end = d.group_len.iter().position(|&l| l == 0)?;
+    let size = d.group_idx[end];
+
+    d.block_size = 1usize.checked_shl(byte(at)? as u32)?;
+    d.span = 1u64.checked_shl(byte(at + 1)? as u32)?;
+    d.sparse_index_size = size.div_ceil(d.span) as usize;
+    let padding = byte(at + 2)? as usize;
+    d.blocks = read_u32(data, at + 3)? as usize;
+    d.block_lengths_size = d.blocks + padding;
+    d.max_sym_len = byte(at + 7)?;
+    d.min_sym_len = byte(at + 8)?;
+    at += 9;
+    d.lowest_sym = at;
+
+    if d.max_sym_len < d.min_sym_len {
+        return None
+    }
+    let lengths = (d.max_sym_len - d.min_sym_len) as usize + 1;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        let (lower, upper) = (read_u16(data, at + 2 * i)? as u64, read_u16(data, at + 2 * i + 2)? as u64);
+        d.base64[i] = d.base64[i + 1].wrapping_add(lower).wrapping_sub(upper) / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base.checked_shl(64 - i as u32 - d.min_sym_len as u32).unwrap_or(0);
+    }
+    at += 2 * lengths;
+
+    let symbols = read_u16(data, at)? as usize;
+    at += 2;
+    d.btree = at;
+    data.get(at + 3 * symbols - 1)?;
+
+    // Each symbol expands into this many values, minus one
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(d, data, sym, &mut visited)?;
+        }
+    }
+    Some(at + 3 * symbols + (symbols & 1))
+}
+
+fn set_symlen(d: &mut PairsData, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+    let lr = &data[d.btree + 3 * sym..d.btree + 3 * sym + 3];
+    let right = ((lr[2] as usize) << 4) | (lr[1] as usize >> 4);
+    if right == 0xFFF {
+        return Some(0)
+    }
+    let left = ((lr[1] as usize & 0xF) << 8) | lr[0] as usize;
+
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            d.symlen[child] = set_symlen(d, data, child, visited)?;
+        }
+    }
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+/// The name of the table for a position, white's pieces first, i.e. `KRPvKR`
+fn material_name(pos: &Position, white: Colour) -> String {
+    let side = |colour| -> String {
+        PIECE_LETTERS.iter()
+            .flat_map(|&(piece, letter)| std::iter::repeat_n(letter, pos.board.pieces(piece, colour).count_ones() as usize))
+            .collect()
+    };
+    format!("{}v{}", side(white), side(white.opposite()))
+}
+
+/// A table file, only read the first time it's probed
+#[derive(Debug)]
+struct LazyTable {
+    path: PathBuf,
+    table: OnceLock<Option<Table>>,
+}
+
+impl LazyTable {
+    fn new(path: PathBuf) -> Self {
+        Self { path, table: OnceLock::new() }
+    }
+
+    fn get(&self, material: &Material, kind: Kind) -> Option<&Table> {
+        self.table.get_or_init(|| Table::open(&self.path, material, kind).ok()).as_ref()
+    }
+}
+
+#[derive(Debug)]
+struct TableFiles {
+    material: Material,
+    wdl: Option<LazyTable>,
+    dtz: Option<LazyTable>,
+}
+
+/// The result of reading a table, DTZ tables only hold one side to move
+enum Probed {
+    Value(i32),
+    ChangeSide,
+}
+
+/// Syzygy endgame tablebases, probed for the win/draw/loss (WDL) and the distance to a
+/// capture or pawn move (DTZ) that keeps the result
+#[derive(Debug)]
+pub struct Tablebases {
+    tables: HashMap<String, TableFiles>,
+    largest: usize,
+}
+
+/// Ply counts as DTZ is measured just before a capture or pawn move that keeps the result
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+fn is_zeroing(ply: Ply) -> bool {
+    ply.is_capture || ply.mov.piece == Piece::Pawn
+}
+
+impl Tablebases {
+    /// Finds the tables in one or more directories, separated as in the `PATH` environment variable
+    pub fn open(paths: &str) -> Result<Self, String> {
+        let mut tables: HashMap<String, TableFiles> = HashMap::new();
+        for dir in std::env::split_paths(paths) {
+            let entries = fs::read_dir(&dir).map_err(|e| format!("Could not read `{}`: {e}", dir.display()))?;
+            for path in entries.flatten().map(|e| e.path()) {
+                let (Some(name), Some(ext)) = (path.file_stem().and_then(|s| s.to_str()), path.extension()) else { continue };
+                let Some(material) = Material::from_name(name) else { continue };
+                let files = tables.entry(name.to_string()).or_insert(TableFiles { material, wdl: None, dtz: None });
+                match ext.to_str() {
+                    Some("rtbw") => files.wdl = Some(LazyTable::new(path)),
+                    Some("rtbz") => files.dtz = Some(LazyTable::new(path)),
+                    _ => {},
+                }
+            }
+        }
+
+        tables.retain(|_, files| files.wdl.is_some() || files.dtz.is_some());
+        let largest = tables.values().map(|t| t.material.piece_count).max().unwrap_or(0);
+        if tables.is_empty() {
+            return Err(format!("No Syzygy tables found in `{paths}`"))
+        }
+        Ok(Self { tables, largest })
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// The most pieces of any table found
+    pub fn largest(&self) -> usize {
+        self.largest
+    }
+
+    /// Whether the position could be in the tables. Tables never hold castling rights
+    pub fn covers(&self, pos: &Position) -> bool {
+        pos.castling == 0 && (pos.board.occupied_bb().count_ones() as usize) <= self.largest
+    }
+
+    /// The table for the position and whether it's stored with colours reversed
+    fn find(&self, pos: &Position, kind: Kind) -> Option<(&Table, bool)> {
+        let name = material_name(pos, Colour::White);
+        let (files, black_stronger) = match self.tables.get(&name) {
+            Some(files) => (files, false),
+            None => (self.tables.get(&material_name(pos, Colour::Black))?, true),
+        };
+        let lazy = match kind {
+            Kind::Wdl => files.wdl.as_ref()?,
+            Kind::Dtz => files.dtz.as_ref()?,
+        };
+        Some((lazy.get(&files.material, kind)?, black_stronger))
+    }
+
+    /// Looks up the position's raw table value, without the fixes for captures and en passant
+    fn probe_table(&self, pos: &Position, kind: Kind, wdl: Wdl) -> Option<Probed> {
+        if pos.board.occupied_bb().count_ones() == 2 {
+            return Some(Probed::Value(0))
+        }
+        let (table, black_stronger) = self.find(pos, kind)?;
+        let ix = indices();
+        let material = &table.material;
+        let black_to_move = pos.side_to_move() == Colour::Black;
+
+        // Tables are stored with the stronger side as white, and symmetric ones for white to move
+        let flip = (material.symmetric && black_to_move) || black_stronger;
+        let (flip_colour, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut squares = Vec::with_capacity(MAX_PIECES);
+        let mut pieces = Vec::with_capacity(MAX_PIECES);
+        let mut lead_pawns = 0;
+        let mut file = 0;
+
+        // With pawns, tables are split by the file of the leading pawn: the one nearest the edge
+        if material.has_pawns {
+            let code = table.pairs[0][0].pieces[0] ^ flip_colour;
+            let colour = if code & 8 != 0 { Colour::Black } else { Colour::White };
+            lead_pawns = pos.board.pieces(Piece::Pawn, colour);
+            for sq in bits(lead_pawns) {
+                squares.push(Square::from_bb(sq) as usize ^ flip_squares);
+                pieces.push(code);
+            }
+            let lead = (0..squares.len()).max_by_key(|&i| ix.pawns[squares[i]])?;
+            squares.swap(0, lead);
+            file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+        let lead_count = squares.len();
+
+        if kind == Kind::Dtz {
+            let stored = (table.pairs[0][file].flags & FLAG_STM) as usize;
+            if stored != stm && (!material.symmetric || material.has_pawns) {
+                return Some(Probed::ChangeSide)
+            }
+        }
+
+        for sq in bits(pos.board.occupied_bb() ^ lead_pawns) {
+            let (piece, colour) = pos.board.piece_at(sq)?;
+            squares.push(Square::from_bb(sq) as usize ^ flip_squares);
+            pieces.push(piece_code(piece, colour) ^ flip_colour);
+        }
+
+        let d = &table.pairs[if kind == Kind::Dtz { 0 } else { stm }][file];
+
+        // Put the pieces in the order the table indexes them
+        for i in lead_count..squares.len() - 1 {
+            if let Some(j) = (i + 1..squares.len()).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // Mirror so the leading piece is on files a-d
+        if file_of(squares[0]) > 3 {
+            squares.iter_mut().for_each(|sq| *sq ^= 7);
+        }
+
+        let mut idx;
+        if material.has_pawns {
+            idx = ix.lead_pawn_idx[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|&sq| ix.pawns[sq]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += ix.binomial[i][ix.pawns[sq] as usize];
+            }
+        } else {
+            // Mirror the leading piece into the a1-d1-d4 triangle
+            if rank_of(squares[0]) > 3 {
+                squares.iter_mut().for_each(|sq| *sq ^= 56);
+            }
+            if let Some(i) = (0..d.group_len[0]).find(|&i| off_diagonal(squares[i]) != 0) {
+                if off_diagonal(squares[i]) > 0 {
+                    squares[i..].iter_mut().for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63);
+                }
+            }
+
+            idx = if material.unique_pieces {
+                let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+                let (r0, r1, r2) = (rank_of(s0) as u64, rank_of(s1) as u64, rank_of(s2) as u64);
+
+                if off_diagonal(s0) != 0 {
+                    (ix.a1d1d4[s0] * 63 + (s1 as u64 - adjust1)) * 62 + s2 as u64 - adjust2
+                } else if off_diagonal(s1) != 0 {
+                    (6 * 63 + r0 * 28 + ix.b1h1h7[s1]) * 62 + s2 as u64 - adjust2
+                } else if off_diagonal(s2) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62 + r0 * 7 * 28 + (r1 - adjust1) * 28 + ix.b1h1h7[s2]
+                } else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + r0 * 7 * 6 + (r1 - adjust1) * 6 + (r2 - adjust2)
+                }
+            } else {
+                ix.kk[ix.a1d1d4[squares[0]] as usize][squares[1]]
+            };
+        }
+
+        // The remaining groups, each as a combination of the squares the earlier groups left free
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawns_both;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let end = start + d.group_len[next];
+            squares[start..end].sort();
+            let mut n = 0;
+            for i in 0..d.group_len[next] {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                n += ix.binomial[i + 1][sq - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start = end;
+            next += 1;
+        }
+
+        debug_assert!(idx < d.group_idx[next], "index {idx} past the table's {} positions", d.group_idx[next]);
+        let value = table.decompress(d, idx)?;
+        Some(Probed::Value(match kind {
+            Kind::Wdl => value - 2,
+            Kind::Dtz => table.map_dtz(file, value, wdl)?,
+        }))
+    }
+
+    /// Resolves captures, which the tables may store "don't care" values for, and en passant, which they ignore.
+    /// Also reports whether the best move is a capture, or with `pawn_moves` a pawn move
+    fn search(&self, pos: &Position, pawn_moves: bool) -> Option<(Wdl, bool)> {
+        let legal = pos.legal_plies();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for ply in legal.iter().filter(|p| p.is_capture || (pawn_moves && p.mov.piece == Piece::Pawn)) {
+            searched += 1;
+            let value = -self.search(&pos.make_ply(*ply), false)?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true))
+                }
+            }
+        }
+
+        // With every move searched the table isn't needed, and may be wrong with en passant
+        let exhausted = searched > 0 && searched == legal.len();
+        let value = match exhausted {
+            true  => best,
+            false => match self.probe_table(pos, Kind::Wdl, Wdl::Draw)? {
+                Probed::Value(v) => Wdl::from_value(v),
+                Probed::ChangeSide => return None,
+            },
+        };
+
+        if best >= value {
+            return Some((best, best > Wdl::Draw || exhausted))
+        }
+        Some((value, false))
+    }
+
+    /// The result with best play, `None` if the position isn't in the tables
+    pub fn probe_wdl(&self, pos: &Position) -> Option<Wdl> {
+        if !self.covers(pos) {
+            return None
+        }
+        self.search(pos, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies until a capture or pawn move that keeps the result, positive when winning.
+    /// Cursed wins and blessed losses are over 100. `None` if the position isn't in the tables
+    pub fn probe_dtz(&self, pos: &Position) -> Option<i32> {
+        if !self.covers(pos) {
+            return None
+        }
+        let (wdl, zeroing) = self.search(pos, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0)
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl))
+        }
+
+        match self.probe_table(pos, Kind::Dtz, wdl)? {
+            Probed::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum())
+            },
+            // The table holds the other side to move, so look one ply ahead
+            Probed::ChangeSide => {
+                let mut min = i32::MAX;
+                for ply in pos.legal_plies() {
+                    let child = pos.make_ply(ply);
+                    let zeroing = is_zeroing(ply);
+                    let mut dtz = match zeroing {
+                        true  => -dtz_before_zeroing(self.search(&child, false)?.0),
+                        false => -self.probe_dtz(&child)?,
+                    };
+                    if dtz == 1 && child.check.is_some() && child.legal_plies().is_empty() {
+                        min = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min && dtz.signum() == wdl.signum() {
+                        min = dtz;
+                    }
+                }
+                Some(if min == i32::MAX { -1 } else { min })
+            },
+        }
+    }
+
+    /// The DTZ after playing `ply`, counted from before it
+    fn root_dtz(&self, pos: &Position, ply: Ply) -> Option<i32> {
+        let child = pos.make_ply(ply);
+        if child.check.is_some() && child.legal_plies().is_empty() {
+            return Some(1)
+        }
+        if is_zeroing(ply) {
+            return Some(dtz_before_zeroing(-self.probe_wdl(&child)?))
+        }
+        let dtz = -self.probe_dtz(&child)?;
+        Some(dtz + dtz.signum())
+    }
+
+    /// The root moves that keep the best result, and of those the ones quickest to convert a win
+    /// or slowest to lose. `None` if the position isn't in the tables
+    pub fn root_moves(&self, pos: &Position) -> Option<Vec<Ply>> {
+        if !self.covers(pos) {
+            return None
+        }
+        // Higher is better: quick wins, then slow wins, cursed wins, draws, and losses
+        let rank = |dtz: i32| match dtz.signum() {
+            1  => 1000 - dtz,
+            -1 => -1000 - dtz,
+            _  => 0,
+        };
+        let ranked: Vec<(Ply, i32)> = pos.legal_plies().into_iter()
+            .map(|ply| Some((ply, rank(self.root_dtz(pos, ply)?))))
+            .collect::<Option<_>>()?;
+        let best = ranked.iter().map(|&(_, r)| r).max()?;
+        // Any draw will do
+        Some(ranked.into_iter().filter(|&(_, r)| r == best || (best == 0 && r == 0)).map(|(ply, _)| ply).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+
+    /// Tests against real tables only run with some copied here, i.e. the 3 and 4 piece ones
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+
+    fn pos(fen: &str) -> Position {
+        *Game::try_from_fen(fen).unwrap().last_position()
+    }
+
+    #[test]
+    fn index_tables() {
+        let ix = indices();
+        assert_eq!(ix.kk.iter().flatten().max(), Some(&461));
+        assert_eq!(ix.a1d1d4[Square::B1 as usize], 0);
+        assert_eq!(ix.a1d1d4[Square::D4 as usize], 9);
+        assert_eq!(ix.pawns[Square::A2 as usize], 47);
+        assert_eq!(ix.pawns[Square::H2 as usize], 46);
+        assert_eq!(ix.binomial[2][5], 10);
+        assert_eq!(ix.lead_pawns_size[1], [6, 6, 6, 6]);
+    }
+
+    #[test]
+    fn naming() {
+        let p = pos("8/8/4k3/3n4/8/2PR4/4K3/8 b - - 0 1");
+        assert_eq!(material_name(&p, Colour::White), "KRPvKN");
+        assert_eq!(material_name(&p, Colour::Black), "KNvKRP");
+
+        let m = Material::from_name("KRPvKR").unwrap();
+        assert_eq!((m.piece_count, m.has_pawns, m.pawns_both, m.unique_pieces, m.symmetric), (5, true, false, true, false));
+        assert!(!Material::from_name("KRRvK").unwrap().unique_pieces);
+        assert!(Material::from_name("KBvKB").unwrap().symmetric);
+        assert!(Material::from_name("KRvR").is_none());
+    }
+
+    /// A table holding one value everywhere, with the pieces in the usual order
+    fn single_valued(name: &str, kind: Kind, value: u8) -> Vec<u8> {
+        let material = Material::from_name(name).unwrap();
+        let (white, black) = name.split_once('v').unwrap();
+        let code = |c: char, colour| piece_code(PIECE_LETTERS.iter().find(|&&(_, l)| l == c).unwrap().0, colour);
+        let mut pieces: Vec<u8> = white.chars().map(|c| code(c, Colour::White))
+            .chain(black.chars().map(|c| code(c, Colour::Black)))
+            .collect();
+        // Pawns first, led by the side with fewer
+        let lead = if !black.contains('P') || (white.contains('P') && black.matches('P').count() >= white.matches('P').count()) { 1 } else { 9 };
+        pieces.sort_by_key(|&p| if p == lead { 0 } else if p & 7 == 1 { 1 } else { 2 });
+
+        let sides = if kind == Kind::Wdl && !material.symmetric { 2 } else { 1 };
+        let mut data = match kind {
+            Kind::Wdl => WDL_MAGIC.to_vec(),
+            Kind::Dtz => DTZ_MAGIC.to_vec(),
+        };
+        data.push((sides == 2) as u8 | (material.has_pawns as u8) << 1);
+        let files = if material.has_pawns { 4 } else { 1 };
+        for _ in 0..files {
+            data.push(0x00);
+            if material.pawns_both {
+                data.push(0x11);
+            }
+            data.extend(pieces.iter().map(|p| p | p << 4));
+        }
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        for _ in 0..files * sides {
+            data.extend([FLAG_SINGLE_VALUE, value]);
+        }
+        data
+    }
+
+    #[test]
+    fn encoding_stays_in_range() {
+        let dir = std::env::temp_dir().join(format!("syzygy-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["KRvK", "KRRvK", "KPvK", "KPvKP", "KBNvK"] {
+            fs::write(dir.join(format!("{name}.rtbw")), single_valued(name, Kind::Wdl, 4)).unwrap();
+        }
+        let tb = Tablebases::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!((tb.table_count(), tb.largest()), (5, 4));
+
+        // Every placement of the pieces, both sides to move and with colours reversed
+        let mut probes = 0;
+        for (white, black) in [("KR", "K"), ("KRR", "K"), ("KP", "K"), ("K", "KP"), ("KP", "KP"), ("KBN", "K")] {
+            let letters: Vec<(Piece, Colour)> = white.chars().map(|c| (c, Colour::White))
+                .chain(black.chars().map(|c| (c, Colour::Black)))
+                .map(|(c, colour)| (PIECE_LETTERS.iter().find(|&&(_, l)| l == c).unwrap().0, colour))
+                .collect();
+            let total = 64usize.pow(letters.len() as u32);
+            let step = total / 40_000 + 1;
+            for n in (0..total).step_by(step) {
+                let squares: Vec<usize> = (0..letters.len()).map(|i| n / 64usize.pow(i as u32) % 64).collect();
+                let pawn_on_edge = letters.iter().zip(&squares).any(|(&(p, _), &sq)| p == Piece::Pawn && !(8..56).contains(&sq));
+                let (wk, bk) = (squares[0], squares[white.len()]);
+                let touching = file_of(wk).abs_diff(file_of(bk)) <= 1 && rank_of(wk).abs_diff(rank_of(bk)) <= 1;
+                if pawn_on_edge || touching || (1..squares.len()).any(|i| squares[..i].contains(&squares[i])) {
+                    continue
+                }
+
+                let mut p = Position::default();
+                for (&(piece, colour), &sq) in letters.iter().zip(&squares) {
+                    p.board.toggle_piece(piece, colour, 1 << sq);
+                }
+                for white_to_move in [true, false] {
+                    p.was_blacks_move = white_to_move;
+                    assert!(matches!(tb.probe_table(&p, Kind::Wdl, Wdl::Draw), Some(Probed::Value(2))));
+                    probes += 1;
+                }
+            }
+        }
+        assert!(probes > 100_000);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Needs the 3 piece tables and KPvKP, see `tests/syzygy/.gitignore` for where to get them
+    #[test]
+    #[ignore = "needs the 3 piece Syzygy tables and KPvKP in tests/syzygy"]
+    fn probing() {
+        let tb = Tablebases::open(FIXTURES).unwrap_or_else(|e| panic!("No tables in {FIXTURES}: {e}"));
+        for name in ["KQvK", "KNvK", "KPvK", "KRvK", "KPvKP"] {
+            assert!(tb.tables.get(name).is_some_and(|t| t.wdl.is_some() && t.dtz.is_some()), "Missing {name}.rtbw or {name}.rtbz");
+        }
+
+        // Mate in one
+        let p = pos("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1");
+        assert_eq!(tb.probe_wdl(&p), Some(Wdl::Win));
+        assert_eq!(tb.probe_dtz(&p), Some(1));
+        assert_eq!(tb.root_moves(&p).unwrap().iter().map(|p| p.to_uci()).collect::<Vec<_>>(), ["b1b8"]);
+        // The same, colours reversed
+        let p = pos("1q6/8/8/8/8/6k1/8/7K b - - 0 1");
+        assert_eq!(tb.probe_dtz(&p), Some(1));
+        // The queen hangs
+        assert_eq!(tb.probe_wdl(&pos("8/8/8/8/8/2k5/8/Kq6 w - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tb.probe_wdl(&pos("8/8/4k3/8/8/2N5/8/4K3 w - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tb.probe_wdl(&pos("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1")), Some(Wdl::Win));
+        assert_eq!(tb.probe_wdl(&pos("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")), Some(Wdl::Loss));
+        assert_eq!(tb.probe_wdl(&pos("4k3/8/8/4K3/4P3/8/8/8 b - - 0 1")), Some(Wdl::Draw));
+        // Winning DTZ counts down along the best line
+        let mut p = pos("8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        let mut dtz = tb.probe_dtz(&p).unwrap();
+        assert!(dtz > 0);
+        while dtz > 1 {
+            p = p.make_ply(tb.root_moves(&p).unwrap()[0]);
+            p = p.make_ply(tb.root_moves(&p).unwrap()[0]);
+            let next = tb.probe_dtz(&p).unwrap();
+            assert!(next < dtz, "{next} after {dtz}");
+            dtz = next;
+        }
+        // The king takes the rook, so DTZ resolves through the capture to a draw
+        let p = pos("8/8/8/8/8/8/1k6/1R2K3 b - - 0 1");
+        assert_eq!((tb.probe_wdl(&p), tb.probe_dtz(&p)), (Some(Wdl::Draw), Some(0)));
+
+        // The tables don't store en passant, so the capture is searched: the result is the best of the replies
+        let p = pos("7K/8/8/8/3pP3/8/8/k7 b - e3 0 1");
+        let plies = p.legal_plies();
+        assert!(plies.iter().any(|ply| ply.to_uci() == "d4e3"));
+        let best = plies.iter().map(|&ply| -tb.probe_wdl(&p.make_ply(ply)).unwrap()).max();
+        assert_eq!(tb.probe_wdl(&p), best);
+        assert_eq!(tb.probe_wdl(&p), Some(Wdl::Win));
+        assert!(tb.probe_dtz(&p).unwrap() > 0);
+
+        assert_eq!(tb.probe_wdl(&pos("8/8/4k3/8/8/8/8/4K3 w - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tb.probe_wdl(&pos("r3k3/8/8/8/8/8/8/4K3 w q - 0 1")), None);
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use crate::ply::Ply;
+
+/// Moves assumed to be left in the game when the time control doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Never plan to use more than this share of the remaining time on one move
+const MAX_SHARE: f64 = 0.8;
+/// How far past the soft limit the search may run to finish a troubled iteration
+const HARD_FACTOR: u32 = 4;
+/// A score drop of this much between iterations counts as a fail low
+const FAIL_LOW_MARGIN: i32 = 30;
+
+/// The state of the engine's clock at the start of a search
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clock {
+    pub time: Duration,
+    pub inc: Duration,
+    /// Moves until the next time control, sudden death if `None`
+    pub movestogo: Option<u32>,
+}
+
+/// Decides how long to think in clocked games.
+///
+/// The soft limit is checked between iterations: a new iteration isn't started once it's passed.
+/// It grows while the best move keeps changing or the score drops, and the hard limit aborts
+/// the search no matter what.
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    soft: Duration,
+    hard: Duration,
+    /// Decaying count of best move changes between iterations
+    instability: f64,
+    fail_low: bool,
+    best: Option<(Ply, i32)>,
+}
+
+impl TimeManager {
+    /// `overhead` is kept back from every move for communication and process scheduling delays
+    pub fn new(clock: &Clock, overhead: Duration) -> Self {
+        let available = clock.time.saturating_sub(overhead).max(Duration::from_millis(1));
+        let movestogo = clock.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+        let max = available.mul_f64(MAX_SHARE);
+        let soft = (available / movestogo + clock.inc * 3 / 4).min(max);
+        let hard = (soft * HARD_FACTOR).min(max);
+
+        Self { soft, hard, instability: 0.0, fail_low: false, best: None }
+    }
+
+    /// The soft limit after adjusting for how the search is going
+    pub fn soft(&self) -> Duration {
+        let mut scale = 1.0 + self.instability;
+        if self.fail_low {
+            scale *= 1.5;
+        }
+        self.soft.mul_f64(scale).min(self.hard)
+    }
+
+    /// Records the best move and score of a completed iteration
+    pub fn update(&mut self, best: Ply, score: i32) {
+        self.instability *= 0.5;
+        self.fail_low = false;
+        if let Some((previous, previous_score)) = self.best {
+            if previous != best {
+                self.instability += 1.0;
+            }
+            self.fail_low = score <= previous_score - FAIL_LOW_MARGIN;
+        }
+        self.best = Some((best, score));
+    }
+
+    /// Notes that the root search failed low mid-iteration, so the current best move is in doubt
+    pub fn root_failed_low(&mut self) {
+        self.fail_low = true;
+    }
+
+    /// Whether another iteration is worth starting
+    pub fn can_continue(&self, elapsed: Duration) -> bool {
+        elapsed < self.soft()
+    }
+
+    /// Whether the hard limit has passed, which stops the search mid-iteration
+    pub fn out_of_time(&self, elapsed: Duration) -> bool {
+        elapsed >= self.hard
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+
+    fn clock(time: u64, inc: u64, movestogo: Option<u32>) -> Clock {
+        Clock { time: Duration::from_millis(time), inc: Duration::from_millis(inc), movestogo }
+    }
+
+    #[test]
+    fn limits() {
+        let tm = TimeManager::new(&clock(60_000, 0, None), Duration::ZERO);
+        assert_eq!(tm.soft(), Duration::from_millis(2000));
+        assert!(!tm.out_of_time(Duration::from_millis(7999)) && tm.out_of_time(Duration::from_millis(8000)));
+
+        // The last move before the time control may use most of the clock, minus the overhead
+        let tm = TimeManager::new(&clock(10_000, 0, Some(1)), Duration::from_millis(100));
+        assert!(!tm.out_of_time(Duration::from_millis(7919)) && tm.out_of_time(Duration::from_millis(7920)));
+        assert!(tm.soft() <= Duration::from_millis(7920));
+
+        // Nearly flagging
+        let tm = TimeManager::new(&clock(20, 1000, None), Duration::from_millis(50));
+        assert!(tm.out_of_time(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn extends_when_unstable() {
+        let game = Game::new();
+        let (e4, d4) = (game.last_position().parse_uci("e2e4").unwrap(), game.last_position().parse_uci("d2d4").unwrap());
+        let mut tm = TimeManager::new(&clock(60_000, 0, None), Duration::ZERO);
+        tm.update(e4, 20);
+        tm.update(e4, 25);
+        let stable = tm.soft();
+
+        tm.update(d4, 25);
+        assert!(tm.soft() > stable);
+        tm.update(d4, -40);
+        assert!(tm.soft() > stable);
+        // Never past the hard limit
+        assert!(tm.soft() <= Duration::from_millis(8000));
+    }
+}
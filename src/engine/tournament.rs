@@ -0,0 +1,717 @@
+use std::fs::File;
+use std::io::{ BufRead, BufReader, Write };
+use std::process::{ Child, ChildStdin, Command, Stdio };
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError };
+use std::thread;
+use std::time::{ Duration, Instant, SystemTime };
+
+use crate    :: {
+    core     :: { parsers :: { pgn::{ self, PgnGame }, san }, INITIAL_FEN },
+    game     :: { DrawKind, Game, State, WinKind },
+    ply      :: { Colour, Ply },
+    engine   :: {
+        options :: Options,
+        search  :: { Limits, Searcher, MATE },
+        sprt    :: { Decision, Score, Sprt },
+        time    :: Clock,
+    },
+};
+
+/// How long a UCI engine gets to start up and answer `isready`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long past its limit a UCI engine may take before it's told to stop, and then to reply
+const GRACE: Duration = Duration::from_secs(5);
+
+/// How long each side may think
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeControl {
+    /// Time for the whole game, plus an increment after every move
+    Clock { base: Duration, inc: Duration },
+    MoveTime(Duration),
+    Depth(u8),
+    Nodes(u64),
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        TimeControl::Clock { base: Duration::from_secs(10), inc: Duration::from_millis(100) }
+    }
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(format!("Expected a number of seconds, got `{value}`")),
+    }
+}
+
+impl TimeControl {
+    /// Reads `tc <base>[+<inc>]`, `movetime <secs>`, `depth <plies>` or `nodes <n>`
+    pub fn parse(kind: &str, value: &str) -> Result<Self, String> {
+        match kind {
+            "tc" => {
+                let (base, inc) = value.split_once('+').unwrap_or((value, "0"));
+                match (parse_secs(base)?, parse_secs(inc)?) {
+                    (base, _) if base.is_zero() => Err("The base time must be positive".to_string()),
+                    (base, inc) => Ok(TimeControl::Clock { base, inc }),
+                }
+            },
+            "movetime" => Ok(TimeControl::MoveTime(parse_secs(value)?)),
+            "depth" => match value.parse() {
+                Ok(depth @ 1..) => Ok(TimeControl::Depth(depth)),
+                _ => Err(format!("Expected a depth of at least 1, got `{value}`")),
+            },
+            "nodes" => match value.parse() {
+                Ok(nodes @ 1..) => Ok(TimeControl::Nodes(nodes)),
+                _ => Err(format!("Expected a positive number of nodes, got `{value}`")),
+            },
+            _ => Err(format!("Unknown time control `{kind}`")),
+        }
+    }
+
+    /// As written in the PGN `TimeControl` tag
+    fn tag(&self) -> String {
+        match self {
+            TimeControl::Clock { base, inc } => format!("{}+{}", base.as_secs_f64(), inc.as_secs_f64()),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+/// One side of a match
+pub trait Contestant {
+    fn name(&self) -> &str;
+    /// Readies for a new game, failing if it can no longer play
+    fn new_game(&mut self) -> Result<(), String>;
+    /// Picks a move in the game's current position, reached by `moves` from `start`, with its score
+    /// for the side to move if it gave one. `clocks` are white's and black's remaining time
+    fn go(&mut self, game: &Game, start: &str, moves: &[Ply], tc: &TimeControl, clocks: [Duration; 2]) -> Result<(Ply, Option<i32>), String>;
+}
+
+/// Launches `self[,Name=Value...]` as this engine with those options, and anything else as the
+/// command line of a UCI engine, i.e. `./other uci,Hash=64`
+pub fn launch(spec: &str) -> Result<Box<dyn Contestant>, String> {
+    let mut parts = spec.split(',');
+    let command = parts.next().unwrap_or_default().trim();
+    let options = parts
+        .map(|option| option.split_once('=').map(|(n, v)| (n.trim(), v.trim())).ok_or_else(|| format!("Expected `Name=Value`, got `{option}`")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match command {
+        "" => Err("Missing engine".to_string()),
+        "self" => Ok(Box::new(SelfEngine::new(&options)?)),
+        _ => Ok(Box::new(UciEngine::launch(command, &options)?)),
+    }
+}
+
+/// This engine, searching in-process
+pub struct SelfEngine {
+    name: String,
+    searcher: Searcher,
+}
+
+impl SelfEngine {
+    pub fn new(options: &[(&str, &str)]) -> Result<Self, String> {
+        let mut searcher = Searcher::new(Options::default());
+        for (name, value) in options {
+            searcher.set_option(name, value)?;
+        }
+        let name = match options.is_empty() {
+            true => "ACP".to_string(),
+            false => format!("ACP ({})", options.iter().map(|(n, v)| format!("{n}={v}")).collect::<Vec<_>>().join(", ")),
+        };
+        Ok(Self { name, searcher })
+    }
+}
+
+impl Contestant for SelfEngine {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.searcher.clear();
+        Ok(())
+    }
+
+    fn go(&mut self, game: &Game, _: &str, _: &[Ply], tc: &TimeControl, clocks: [Duration; 2]) -> Result<(Ply, Option<i32>), String> {
+        let pos = game.last_position();
+        let limits = match *tc {
+            TimeControl::Clock { inc, .. } => {
+                let time = clocks[pos.side_to_move() as usize];
+                Limits { clock: Some(Clock { time, inc, movestogo: None }), ..Default::default() }
+            },
+            TimeControl::MoveTime(time) => Limits { movetime: Some(time), ..Default::default() },
+            TimeControl::Depth(depth) => Limits { depth: Some(depth), ..Default::default() },
+            TimeControl::Nodes(nodes) => Limits { nodes: Some(nodes), ..Default::default() },
+        };
+        let result = self.searcher.search(pos, &game.previous_hashes(), limits, |_| {});
+        let best = result.best.ok_or_else(|| format!("{} found no move", self.name))?;
+        Ok((best, Some(result.score)))
+    }
+}
+
+/// An engine run as a subprocess and spoken to over UCI
+pub struct UciEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines from the engine, read on their own thread so waiting can time out
+    lines: Receiver<String>,
+}
+
+fn millis(time: Duration) -> u128 {
+    time.as_millis()
+}
+
+/// Reads the score of an `info` line, converting mates to mate scores
+fn parse_score(line: &str) -> Option<i32> {
+    let mut tokens = line.split_whitespace().skip_while(|t| *t != "score").skip(1);
+    match (tokens.next()?, tokens.next()?.parse::<i32>().ok()?) {
+        ("cp", cp) => Some(cp),
+        ("mate", moves) if moves > 0 => Some(MATE - 2 * moves + 1),
+        ("mate", moves) => Some(-MATE - 2 * moves),
+        _ => None,
+    }
+}
+
+impl UciEngine {
+    /// Starts the engine and completes the UCI handshake, then sets the options
+    pub fn launch(command: &str, options: &[(&str, &str)]) -> Result<Self, String> {
+        let mut args = command.split_whitespace();
+        let program = args.next().ok_or("Missing engine command")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Could not start `{command}`: {e}"))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break
+                }
+            }
+        });
+
+        let mut engine = Self { name: command.to_string(), child, stdin, lines };
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine.read(Some(deadline))?.ok_or_else(|| format!("`{command}` did not answer `uci`"))?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+            if line.trim() == "uciok" {
+                break
+            }
+        }
+        for (name, value) in options {
+            engine.send(&format!("setoption name {name} value {value}"))?;
+        }
+        engine.ready()?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Could not write to {}: {e}", self.name))
+    }
+
+    /// The next line from the engine, or `None` if the deadline passes first
+    fn read(&mut self, deadline: Option<Instant>) -> Result<Option<String>, String> {
+        let exited = || format!("{} exited", self.name);
+        match deadline {
+            Some(deadline) => match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => Ok(Some(line)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(exited()),
+            },
+            None => self.lines.recv().map(Some).map_err(|_| exited()),
+        }
+    }
+
+    fn ready(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            match self.read(Some(deadline))? {
+                Some(line) if line.trim() == "readyok" => return Ok(()),
+                Some(_) => {},
+                None => return Err(format!("{} did not answer `isready`", self.name)),
+            }
+        }
+    }
+}
+
+impl Contestant for UciEngine {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.ready()
+    }
+
+    fn go(&mut self, game: &Game, start: &str, moves: &[Ply], tc: &TimeControl, clocks: [Duration; 2]) -> Result<(Ply, Option<i32>), String> {
+        let mut position = match start == INITIAL_FEN {
+            true => "position startpos".to_string(),
+            false => format!("position fen {start}"),
+        };
+        if !moves.is_empty() {
+            position.push_str(" moves");
+            for ply in moves {
+                position.push(' ');
+                position.push_str(&ply.to_uci());
+            }
+        }
+        self.send(&position)?;
+
+        let pos = game.last_position();
+        let (go, limit) = match *tc {
+            TimeControl::Clock { inc, .. } => (
+                format!("go wtime {} btime {} winc {} binc {}", millis(clocks[0]), millis(clocks[1]), millis(inc), millis(inc)),
+                Some(clocks[pos.side_to_move() as usize]),
+            ),
+            TimeControl::MoveTime(time) => (format!("go movetime {}", millis(time)), Some(time)),
+            TimeControl::Depth(depth) => (format!("go depth {depth}"), None),
+            TimeControl::Nodes(nodes) => (format!("go nodes {nodes}"), None),
+        };
+        self.send(&go)?;
+
+        let mut deadline = limit.map(|limit| Instant::now() + limit + GRACE);
+        let (mut score, mut stopped) = (None, false);
+        loop {
+            let line = match self.read(deadline)? {
+                Some(line) => line,
+                None if !stopped => {
+                    self.send("stop")?;
+                    (deadline, stopped) = (Some(Instant::now() + GRACE), true);
+                    continue
+                },
+                None => return Err(format!("{} did not return a move", self.name)),
+            };
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("info") => score = parse_score(&line).or(score),
+                Some("bestmove") => {
+                    let mov = tokens.next().unwrap_or_default();
+                    let ply = pos.parse_uci(mov).ok_or_else(|| format!("{} played the illegal move `{mov}`", self.name))?;
+                    return Ok((ply, score))
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..20 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A starting position for a pair of games, with the moves that led to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opening {
+    pub fen: String,
+    pub moves: Vec<Ply>,
+}
+
+impl Default for Opening {
+    fn default() -> Self {
+        Self { fen: INITIAL_FEN.to_string(), moves: Vec::new() }
+    }
+}
+
+/// The FEN of an EPD or FEN line, whose move counters may be missing or replaced by operations
+fn fen_from_epd(line: &str) -> String {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let counters = fields.len() >= 6 && fields[4].parse::<u16>().is_ok() && fields[5].parse::<u16>().is_ok();
+    match counters {
+        true => fields[..6].join(" "),
+        false => format!("{} 0 1", fields[..fields.len().min(4)].join(" ")),
+    }
+}
+
+/// Reads the openings of a PGN file, or of a file with a FEN or EPD position on each line
+pub fn load_openings(path: &str) -> Result<Vec<Opening>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Could not read `{path}`: {e}"))?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    let openings = match path.to_lowercase().ends_with(".pgn") {
+        true => pgn::parse_pgn(&text)
+            .iter()
+            .enumerate()
+            .map(|(i, game)| {
+                let (start, moves) = game.plies().map_err(|e| format!("Game {} of `{path}`: {e}", i + 1))?;
+                Ok(Opening { fen: start.as_fen(), moves })
+            })
+            .collect::<Result<Vec<_>, String>>()?,
+        false => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| {
+                let fen = fen_from_epd(line);
+                let game = Game::try_from_fen(&fen).map_err(|e| format!("Line {} of `{path}`: {e}", i + 1))?;
+                Ok(Opening { fen: game.as_fen(), moves: Vec::new() })
+            })
+            .collect::<Result<Vec<_>, String>>()?,
+    };
+    match openings.is_empty() {
+        true => Err(format!("No openings in `{path}`")),
+        false => Ok(openings),
+    }
+}
+
+/// When to end games early on the engines' scores, in centipawns from the mover's point of view
+#[derive(Debug, Clone, Copy)]
+pub struct Adjudication {
+    /// A win once both engines agree a side is at least this far ahead, 0 to never adjudicate wins
+    pub resign_score: i32,
+    pub resign_moves: usize,
+    /// A draw once both engines' scores have stayed within this, 0 to never adjudicate draws
+    pub draw_score: i32,
+    pub draw_moves: usize,
+    /// Draws aren't adjudicated before this move
+    pub draw_after: u16,
+    /// A draw once a game reaches this many moves, 0 for no limit
+    pub max_moves: u16,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self { resign_score: 600, resign_moves: 3, draw_score: 10, draw_moves: 8, draw_after: 40, max_moves: 400 }
+    }
+}
+
+impl Adjudication {
+    /// Checks the scores of the last moves, from white's point of view
+    fn check(&self, game: &Game, scores: &[Option<i32>]) -> Option<&'static str> {
+        let last = |moves: usize| -> Option<Vec<i32>> {
+            (scores.len() >= moves * 2).then(|| scores[scores.len() - moves * 2..].iter().copied().collect())?
+        };
+        if self.resign_score > 0 {
+            if let Some(last) = last(self.resign_moves) {
+                if last.iter().all(|s| *s >= self.resign_score) {
+                    return Some("1-0")
+                }
+                if last.iter().all(|s| *s <= -self.resign_score) {
+                    return Some("0-1")
+                }
+            }
+        }
+        if self.draw_score > 0 && game.mov >= self.draw_after {
+            if let Some(last) = last(self.draw_moves) {
+                if last.iter().all(|s| s.abs() <= self.draw_score) {
+                    return Some("1/2-1/2")
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchOptions {
+    pub games: usize,
+    pub tc: TimeControl,
+    /// Each is played twice with colours reversed, in order and starting over once all have been played
+    pub openings: Vec<Opening>,
+    pub adjudication: Adjudication,
+    /// The match stops early once the test is decided
+    pub sprt: Sprt,
+    /// File the games are written to
+    pub pgn: Option<String>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            games: 100,
+            tc: TimeControl::default(),
+            openings: vec![Opening::default()],
+            adjudication: Adjudication::default(),
+            sprt: Sprt::default(),
+            pgn: None,
+        }
+    }
+}
+
+/// How a game ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ending {
+    pub result: &'static str,
+    /// As in the PGN `Termination` tag
+    pub termination: &'static str,
+    pub reason: String,
+}
+
+/// Reported after every game
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub round: usize,
+    pub white: String,
+    pub black: String,
+    pub ending: Ending,
+    /// Running results from the first engine's point of view
+    pub score: Score,
+}
+
+fn describe(state: &State) -> String {
+    let reason = match state {
+        State::White(WinKind::Checkmate) => "white mates",
+        State::Black(WinKind::Checkmate) => "black mates",
+        State::White(WinKind::Surrender) => "black resigns",
+        State::Black(WinKind::Surrender) => "white resigns",
+        State::White(WinKind::Timeout) => "black loses on time",
+        State::Black(WinKind::Timeout) => "white loses on time",
+        State::Draw(DrawKind::Stalemate) => "stalemate",
+        State::Draw(DrawKind::Mutual) => "draw agreed",
+        State::Draw(DrawKind::ThreeFold) => "threefold repetition",
+        State::Draw(DrawKind::FiveFold) => "fivefold repetition",
+        State::Draw(DrawKind::FiftyMove) => "fifty-move rule",
+        State::Draw(DrawKind::InsufficientMaterial) => "insufficient material",
+        State::Draw(DrawKind::TimeoutVsInsufficientMaterial) => "time forfeit against insufficient material",
+        State::Terminated => "terminated",
+    };
+    reason.to_string()
+}
+
+/// A loss for the side to move
+fn forfeit(side: Colour, termination: &'static str, reason: String) -> Ending {
+    let result = match side {
+        Colour::White => "0-1",
+        Colour::Black => "1-0",
+    };
+    Ending { result, termination, reason }
+}
+
+/// Plays one game between white and black from the opening, returning how it ended and its moves in SAN
+pub fn play_game(players: [&mut dyn Contestant; 2], opening: &Opening, options: &MatchOptions) -> Result<(Ending, Vec<String>), String> {
+    let mut game = Game::try_from_fen(&opening.fen)?;
+    let mut sans = Vec::with_capacity(opening.moves.len() + 160);
+    for &ply in &opening.moves {
+        sans.push(san::to_san(game.last_position(), ply));
+        game.make_ply(ply);
+    }
+    let mut moves = opening.moves.clone();
+    let mut clocks = match options.tc {
+        TimeControl::Clock { base, .. } => [base; 2],
+        _ => [Duration::ZERO; 2],
+    };
+    // White's point of view, for each move played by the engines
+    let mut scores = Vec::new();
+    let adjudication = &options.adjudication;
+
+    let ending = loop {
+        if let Some(state) = &game.state {
+            break Ending { result: state.result(), termination: "normal", reason: describe(state) }
+        }
+        if let Some(result) = adjudication.check(&game, &scores) {
+            let reason = match result {
+                "1/2-1/2" => "draw by adjudication",
+                _ => "win by adjudication",
+            };
+            break Ending { result, termination: "adjudication", reason: reason.to_string() }
+        }
+        if adjudication.max_moves > 0 && game.mov > adjudication.max_moves {
+            break Ending { result: "1/2-1/2", termination: "adjudication", reason: "move limit".to_string() }
+        }
+
+        let side = game.last_position().side_to_move();
+        let engine = &mut *players[side as usize];
+        let started = Instant::now();
+        let reply = engine.go(&game, &opening.fen, &moves, &options.tc, clocks);
+        let elapsed = started.elapsed();
+
+        let (ply, score) = match reply {
+            Ok(reply) => reply,
+            Err(e) => break forfeit(side, "abandoned", e),
+        };
+        if let TimeControl::Clock { inc, .. } = options.tc {
+            let clock = &mut clocks[side as usize];
+            if elapsed > *clock {
+                break forfeit(side, "time forfeit", format!("{} loses on time", engine.name()))
+            }
+            *clock = *clock - elapsed + inc;
+        }
+
+        scores.push(score.map(|s| if side == Colour::White { s } else { -s }));
+        sans.push(san::to_san(game.last_position(), ply));
+        moves.push(ply);
+        game.make_ply(ply);
+    };
+    Ok((ending, sans))
+}
+
+/// Today as `YYYY.MM.DD`, for the PGN `Date` tag
+fn today() -> String {
+    let days = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86_400) as i64;
+    // Days since 1970 to a proleptic Gregorian date, with years starting in March
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+/// Plays the match, reporting after every game, until all games are played or the SPRT is decided.
+/// Results are from the first engine's point of view
+pub fn run(contestants: &mut [Box<dyn Contestant>; 2], options: &MatchOptions, mut report: impl FnMut(&Outcome)) -> Result<Score, String> {
+    let mut file = match &options.pgn {
+        Some(path) => Some(File::create(path).map_err(|e| format!("Could not create `{path}`: {e}"))?),
+        None => None,
+    };
+    let default = [Opening::default()];
+    let openings = match options.openings.is_empty() {
+        true => &default[..],
+        false => &options.openings[..],
+    };
+    let date = today();
+    let mut score = Score::default();
+
+    for round in 0..options.games {
+        let opening = &openings[round / 2 % openings.len()];
+        let [first, second] = contestants;
+        let (white, black, swapped) = match round % 2 {
+            0 => (first, second, false),
+            _ => (second, first, true),
+        };
+        white.new_game()?;
+        black.new_game()?;
+        let (ending, moves) = play_game([white.as_mut(), black.as_mut()], opening, options)?;
+
+        let points = match ending.result {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            _ => 0.5,
+        };
+        score.add(if swapped { 1.0 - points } else { points });
+
+        if let Some(file) = &mut file {
+            let mut tags = vec![
+                ("Event", "ACP match".to_string()),
+                ("Site", "?".to_string()),
+                ("Date", date.clone()),
+                ("Round", (round + 1).to_string()),
+                ("White", white.name().to_string()),
+                ("Black", black.name().to_string()),
+                ("Result", ending.result.to_string()),
+            ];
+            if opening.fen != INITIAL_FEN {
+                tags.push(("SetUp", "1".to_string()));
+                tags.push(("FEN", opening.fen.clone()));
+            }
+            tags.push(("TimeControl", options.tc.tag()));
+            tags.push(("Termination", ending.termination.to_string()));
+            let game = PgnGame {
+                tags: tags.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+                moves,
+                result: Some(ending.result.to_string()),
+            };
+            file.write_all(game.to_pgn().as_bytes()).map_err(|e| format!("Could not write the game: {e}"))?;
+        }
+
+        report(&Outcome { round: round + 1, white: white.name().to_string(), black: black.name().to_string(), ending, score });
+        if options.sprt.decide(&score) != Decision::Continue {
+            break
+        }
+    }
+    Ok(score)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(TimeControl::parse("tc", "10+0.1"), Ok(TimeControl::Clock { base: Duration::from_secs(10), inc: Duration::from_millis(100) }));
+        assert_eq!(TimeControl::parse("tc", "60"), Ok(TimeControl::Clock { base: Duration::from_secs(60), inc: Duration::ZERO }));
+        assert_eq!(TimeControl::parse("depth", "5"), Ok(TimeControl::Depth(5)));
+        assert!(TimeControl::parse("tc", "0+1").is_err());
+        assert!(TimeControl::parse("nodes", "many").is_err());
+
+        assert_eq!(parse_score("info depth 5 score cp -35 nodes 100 pv e2e4"), Some(-35));
+        assert_eq!(parse_score("info depth 5 score mate 2 pv e2e4"), Some(MATE - 3));
+        assert_eq!(parse_score("info depth 5 score mate -1"), Some(-MATE + 2));
+        assert_eq!(parse_score("info string hello"), None);
+
+        assert_eq!(fen_from_epd("4k3/8/8/8/8/8/8/4K2R w K - bm O-O; id \"castle\";"), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(fen_from_epd("4k3/8/8/8/8/8/8/4K2R b K - 3 40"), "4k3/8/8/8/8/8/8/4K2R b K - 3 40");
+
+        assert!(launch("self,Hash").is_err());
+        assert!(launch("self,NoSuchOption=1").is_err());
+        assert_eq!(launch("self,NullMove=false").unwrap().name(), "ACP (NullMove=false)");
+    }
+
+    #[test]
+    fn adjudicating() {
+        let game = Game::new();
+        let adjudication = Adjudication { resign_moves: 2, draw_after: 0, draw_moves: 2, ..Default::default() };
+        assert_eq!(adjudication.check(&game, &[Some(700), Some(650), Some(900), Some(800)]), Some("1-0"));
+        assert_eq!(adjudication.check(&game, &[Some(700), None, Some(900), Some(800)]), None);
+        assert_eq!(adjudication.check(&game, &[Some(-700), Some(-650), Some(-900)]), None);
+        assert_eq!(adjudication.check(&game, &[Some(5), Some(-5), Some(0), Some(3)]), Some("1/2-1/2"));
+        // Not before the draw move
+        let late = Adjudication { draw_after: 40, ..adjudication };
+        assert_eq!(late.check(&game, &[Some(5), Some(-5), Some(0), Some(3)]), None);
+    }
+
+    #[test]
+    fn playing_a_match() {
+        let path = std::env::temp_dir().join(format!("acp-match-{}.pgn", std::process::id()));
+        let options = MatchOptions {
+            games: 4,
+            tc: TimeControl::Depth(2),
+            openings: vec![
+                Opening { fen: "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1".to_string(), moves: Vec::new() },
+                Opening::default(),
+            ],
+            adjudication: Adjudication { max_moves: 20, ..Default::default() },
+            pgn: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let mut contestants: [Box<dyn Contestant>; 2] = [launch("self").unwrap(), launch("self,NullMove=false").unwrap()];
+        let mut outcomes = Vec::new();
+        let score = run(&mut contestants, &options, |outcome| outcomes.push(outcome.clone())).unwrap();
+
+        assert_eq!(score.games(), 4);
+        assert_eq!(outcomes.len(), 4);
+        assert_eq!((outcomes[0].white.as_str(), outcomes[1].white.as_str()), ("ACP", "ACP (NullMove=false)"));
+        // The rook wins, whichever engine has it
+        assert_eq!((outcomes[0].ending.result, outcomes[1].ending.result), ("1-0", "1-0"));
+        assert_eq!(score.wins, score.losses);
+
+        let games = pgn::parse_pgn(&std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(games.len(), 4);
+        assert_eq!(games[0].tag("FEN"), Some("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"));
+        assert_eq!(games[3].tag("Round"), Some("4"));
+        for game in &games {
+            assert!(game.plies().is_ok());
+        }
+    }
+}
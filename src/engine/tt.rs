@@ -0,0 +1,180 @@
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+use crate :: {
+    core  :: Square,
+    ply   :: { Colour, Piece, Ply, PromotablePiece },
+};
+
+use super::search::MATE_BOUND;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,  // Failed high, score is at least this
+    Upper,  // Failed low, score is at most this
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub key: u64,
+    pub best: Option<Ply>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+const PIECES: [Piece; 6] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King];
+const PROMOTIONS: [PromotablePiece; 4] = [PromotablePiece::Knight, PromotablePiece::Bishop, PromotablePiece::Rook, PromotablePiece::Queen];
+const BOUNDS: [Bound; 3] = [Bound::Exact, Bound::Lower, Bound::Upper];
+
+/// Set in every stored entry so an empty slot is all zeros
+const OCCUPIED: u64 = 1 << 63;
+
+/// Packs a move into its low 20 bits: from, to, piece, colour, capture and promotion
+fn pack_ply(ply: Ply) -> u64 {
+    let promotion = ply.promotion.map_or(0, |p| p as u64 + 1);
+    ply.mov.from_sq as u64
+        | (ply.mov.to_sq as u64) << 6
+        | (ply.mov.piece as u64) << 12
+        | (ply.mov.player as u64) << 15
+        | (ply.is_capture as u64) << 16
+        | promotion << 17
+}
+
+fn unpack_ply(bits: u64) -> Ply {
+    let player = if bits >> 15 & 1 == 0 { Colour::White } else { Colour::Black };
+    let promotion = match bits >> 17 & 7 {
+        0 => None,
+        p => Some(PROMOTIONS[p as usize - 1]),
+    };
+    Ply::new(
+        player,
+        PIECES[(bits >> 12 & 7) as usize],
+        Square::from_bb(1 << (bits & 63)),
+        Square::from_bb(1 << (bits >> 6 & 63)),
+        bits >> 16 & 1 == 1,
+        promotion,
+    )
+}
+
+/// Layout: move (20 bits) | has move (1) | score as i16 (16) | depth (8) | bound (2) | ... | occupied (1)
+fn pack(entry: &Entry) -> u64 {
+    let best = entry.best.map_or(0, |p| pack_ply(p) | 1 << 20);
+    best
+        | (entry.score as i16 as u16 as u64) << 21
+        | (entry.depth as u64) << 37
+        | (entry.bound as u64) << 45
+        | OCCUPIED
+}
+
+fn unpack(key: u64, data: u64) -> Entry {
+    Entry {
+        key,
+        best: (data >> 20 & 1 == 1).then(|| unpack_ply(data)),
+        score: (data >> 21) as u16 as i16 as i32,
+        depth: (data >> 37) as u8,
+        bound: BOUNDS[(data >> 45 & 3) as usize],
+    }
+}
+
+/// A slot holds the key xor'd with the data, so an entry torn by two threads writing at once
+/// fails the key check rather than being read back wrong
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Shared between search threads without locking
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb.max(1) * 1024 * 1024 / std::mem::size_of::<Slot>()).max(1);
+        Self { slots: (0..len).map(|_| Slot::default()).collect() }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        (data != 0 && slot.key.load(Ordering::Relaxed) ^ data == key).then(|| unpack(key, data))
+    }
+
+    /// Always replaces, unless the slot holds a deeper search of the same position
+    pub fn store(&self, entry: Entry) {
+        let slot = self.slot(entry.key);
+        if let Some(old) = self.probe(entry.key) {
+            if old.depth > entry.depth && entry.bound != Bound::Exact {
+                return
+            }
+        }
+        let data = pack(&entry);
+        slot.key.store(entry.key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// Permille of slots in use, sampled from the start of the table
+    pub fn hashfull(&self) -> usize {
+        let sample = self.slots.len().min(1000);
+        let used = self.slots[..sample].iter().filter(|s| s.data.load(Ordering::Relaxed) != 0).count();
+        used * 1000 / sample.max(1)
+    }
+}
+
+/// Mate scores are stored relative to the node rather than the root, so they stay valid at any ply
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score + ply as i32
+    } else if score < -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score - ply as i32
+    } else if score < -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn entries_round_trip() {
+        let pos = *Game::try_from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap().last_position();
+        let tt = TranspositionTable::new(1);
+        for (i, ply) in pos.legal_plies().into_iter().enumerate() {
+            let entry = Entry { key: i as u64 * 7919, best: Some(ply), score: -31_000 + i as i32, depth: 64, bound: BOUNDS[i % 3] };
+            tt.store(entry);
+            let read = tt.probe(entry.key).unwrap();
+            assert_eq!((read.best, read.score, read.depth, read.bound), (entry.best, entry.score, entry.depth, entry.bound));
+        }
+        assert!(tt.probe(12345).is_none());
+
+        tt.store(Entry { key: 1, best: None, score: 0, depth: 3, bound: Bound::Upper });
+        assert!(tt.probe(1).unwrap().best.is_none());
+        tt.clear();
+        assert!(tt.probe(1).is_none());
+    }
+}
@@ -0,0 +1,311 @@
+use std::thread;
+
+use crate    :: {
+    core     :: bits,
+    game     :: Game,
+    ply      :: { Colour, Piece },
+    position :: Position,
+};
+
+use super :: {
+    eval   :: { self, piece_value, pst_index, EvalParams, MAX_PHASE, PIECES },
+    search :: INFINITY,
+};
+
+/// Captures followed when looking for a quiet position
+const MAX_QUIESCENCE: usize = 8;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+/// A position labelled with the result of the game it was taken from, 1 for a white win down to 0
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub pos: Position,
+    pub result: f64,
+}
+
+fn parse_result(token: &str) -> Option<f64> {
+    match token.trim_matches(|c| matches!(c, '"' | ';' | '[' | ']')) {
+        "1-0" | "1.0" => Some(1.0),
+        "0-1" | "0.0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+/// Reads an EPD line ending in a result opcode, i.e. `... c9 "1-0";`, or a FEN followed by its result
+/// as `1-0`, `0-1`, `1/2-1/2` or `[1.0]`, `[0.5]`, `[0.0]`
+pub fn parse_sample(line: &str) -> Option<Sample> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 5 {
+        return None
+    }
+    let result = parse_result(tokens.last()?)?;
+    // EPD leaves out the clocks, a FEN might not
+    let clocks = tokens.len() >= 7 && tokens[4].parse::<u32>().is_ok() && tokens[5].parse::<u32>().is_ok();
+    let fen = match clocks {
+        true  => tokens[..6].join(" "),
+        false => format!("{} 0 1", tokens[..4].join(" ")),
+    };
+    let game = Game::try_from_fen(&fen).ok()?;
+    Some(Sample { pos: *game.last_position(), result })
+}
+
+/// Reads every sample in a dataset, one per line, along with the number of lines that couldn't be read.
+/// Blank lines and those starting with `#` are ignored
+pub fn parse_dataset(text: &str) -> (Vec<Sample>, usize) {
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        match parse_sample(line) {
+            Some(sample) => samples.push(sample),
+            None => skipped += 1,
+        }
+    }
+    (samples, skipped)
+}
+
+/// Quiescence search with the given weights, returning the score for the side to move
+/// along with the quiet position at the end of the best line
+fn quiesce(pos: &Position, mut alpha: i32, beta: i32, params: &EvalParams, depth: usize) -> (i32, Position) {
+    let sign = if pos.side_to_move() == Colour::White { 1 } else { -1 };
+    let mut best = (sign * eval::evaluate_white(pos, params), *pos);
+    if best.0 >= beta || depth >= MAX_QUIESCENCE {
+        return best
+    }
+    alpha = alpha.max(best.0);
+
+    let mut captures: Vec<_> = pos.legal_plies().into_iter().filter(|p| p.is_capture).collect();
+    // En passant leaves the target square empty
+    captures.sort_by_cached_key(|p| -piece_value(pos.board.piece_at(p.mov.to_sq.to_bb()).map_or(Piece::Pawn, |(p, _)| p)));
+    for ply in captures {
+        let (score, leaf) = quiesce(&pos.make_ply(ply), -beta, -alpha, params, depth + 1);
+        if -score > best.0 {
+            best = (-score, leaf);
+            alpha = alpha.max(-score);
+            if -score >= beta {
+                break
+            }
+        }
+    }
+    best
+}
+
+/// The position's evaluation as a sum of weights, each with its coefficient. Like `evaluate_white`,
+/// without the rounding or the endgame rules which the weights don't take part in
+fn features(pos: &Position) -> Vec<(usize, f64)> {
+    let mg = eval::phase(pos) as f64 / MAX_PHASE as f64;
+    let eg = 1.0 - mg;
+    let (eg_values, mg_pst, eg_pst) = (6, 12, 12 + 6 * 64);
+
+    let mut features = Vec::new();
+    for colour in [Colour::White, Colour::Black] {
+        let sign = if colour == Colour::White { 1.0 } else { -1.0 };
+        for piece in PIECES {
+            let p = piece as usize;
+            for sq in bits(pos.board.pieces(piece, colour)) {
+                let i = pst_index(sq.trailing_zeros() as usize, colour);
+                features.push((p, sign * mg));
+                features.push((eg_values + p, sign * eg));
+                features.push((mg_pst + p * 64 + i, sign * mg));
+                features.push((eg_pst + p * 64 + i, sign * eg));
+            }
+        }
+    }
+
+    features.sort_by_key(|&(i, _)| i);
+    let mut merged: Vec<(usize, f64)> = Vec::with_capacity(features.len());
+    for (i, c) in features {
+        match merged.last_mut() {
+            Some(last) if last.0 == i => last.1 += c,
+            _ => merged.push((i, c)),
+        }
+    }
+    merged.retain(|&(_, c)| c != 0.0);
+    merged
+}
+
+/// Expected score for white given an evaluation
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+struct Entry {
+    features: Vec<(usize, f64)>,
+    result: f64,
+}
+
+/// Tunes the evaluation weights with Texel's method: the mean squared error between game results and
+/// the results predicted by the evaluation of each position's quiet leaf, minimised by gradient descent
+pub struct Tuner {
+    entries: Vec<Entry>,
+    weights: Vec<f64>,
+    /// Scales evaluations into the sigmoid, fitted to the starting weights
+    pub k: f64,
+    /// Adam's moment estimates and step count
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+}
+
+impl Tuner {
+    /// Resolves each sample to a quiet position with the starting weights
+    pub fn new(samples: &[Sample], params: &EvalParams) -> Self {
+        let entries = samples
+            .iter()
+            .map(|s| Entry { features: features(&quiesce(&s.pos, -INFINITY, INFINITY, params, 0).1), result: s.result })
+            .collect();
+        Self {
+            entries,
+            weights: params.to_vec().into_iter().map(f64::from).collect(),
+            k: 1.0,
+            m: vec![0.0; EvalParams::LEN],
+            v: vec![0.0; EvalParams::LEN],
+            t: 0,
+        }
+    }
+
+    fn evaluate(&self, entry: &Entry) -> f64 {
+        entry.features.iter().map(|&(i, c)| c * self.weights[i]).sum()
+    }
+
+    /// Mean squared error of the predicted results
+    pub fn loss(&self) -> f64 {
+        self.loss_with(self.k)
+    }
+
+    fn loss_with(&self, k: f64) -> f64 {
+        let total: f64 = self.entries.iter().map(|e| (e.result - sigmoid(self.evaluate(e), k)).powi(2)).sum();
+        total / self.entries.len().max(1) as f64
+    }
+
+    /// Finds the scaling constant that best fits the current weights, by golden section search
+    pub fn fit_k(&mut self) {
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut lo, mut hi) = (0.0, 10.0);
+        while hi - lo > 1e-4 {
+            let a = hi - ratio * (hi - lo);
+            let b = lo + ratio * (hi - lo);
+            if self.loss_with(a) < self.loss_with(b) {
+                hi = b;
+            } else {
+                lo = a;
+            }
+        }
+        self.k = (lo + hi) / 2.0;
+    }
+
+    /// Sum of the loss's gradient and of the loss over some entries
+    fn gradient(&self, entries: &[Entry]) -> (Vec<f64>, f64) {
+        let mut gradient = vec![0.0; EvalParams::LEN];
+        let mut loss = 0.0;
+        let scale = self.k * 10f64.ln() / 400.0;
+        for entry in entries {
+            let s = sigmoid(self.evaluate(entry), self.k);
+            let error = entry.result - s;
+            loss += error * error;
+            let g = -2.0 * error * s * (1.0 - s) * scale;
+            for &(i, c) in &entry.features {
+                gradient[i] += g * c;
+            }
+        }
+        (gradient, loss)
+    }
+
+    /// One pass of Adam over every entry, spread across the available cores. Returns the loss before the step
+    pub fn step(&mut self, rate: f64) -> f64 {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = self.entries.len().div_ceil(threads).max(1);
+        let parts: Vec<(Vec<f64>, f64)> = thread::scope(|s| {
+            let handles: Vec<_> = self.entries.chunks(chunk).map(|c| s.spawn(|| self.gradient(c))).collect();
+            handles.into_iter().map(|h| h.join().expect("gradient threads don't panic")).collect()
+        });
+
+        let n = self.entries.len().max(1) as f64;
+        let mut gradient = vec![0.0; EvalParams::LEN];
+        let mut loss = 0.0;
+        for (part, part_loss) in parts {
+            gradient.iter_mut().zip(part).for_each(|(g, p)| *g += p / n);
+            loss += part_loss / n;
+        }
+
+        self.t += 1;
+        let (bias1, bias2) = (1.0 - BETA1.powi(self.t), 1.0 - BETA2.powi(self.t));
+        for (i, g) in gradient.into_iter().enumerate() {
+            self.m[i] = BETA1 * self.m[i] + (1.0 - BETA1) * g;
+            self.v[i] = BETA2 * self.v[i] + (1.0 - BETA2) * g * g;
+            self.weights[i] -= rate * (self.m[i] / bias1) / ((self.v[i] / bias2).sqrt() + 1e-8);
+        }
+        loss
+    }
+
+    /// The current weights, rounded
+    pub fn params(&self) -> EvalParams {
+        let weights: Vec<i32> = self.weights.iter().map(|w| w.round() as i32).collect();
+        EvalParams::from_slice(&weights)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::eval::DEFAULT_PARAMS;
+
+    #[test]
+    fn reading_datasets() {
+        let text = r#"
+# Comments and blank lines are ignored
+rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - c9 "1/2-1/2";
+4k3/8/8/8/8/8/8/4K2R w K - 0 1 [1.0]
+4k3/8/8/8/8/8/8/4K2R w K - 3 40 1-0
+4k3/8/8/8/8/8/8/4K2r w - - 0-1
+4k3/8/8/8/8/8/8/4K2r w - - 0 1
+not a position 1-0
+"#;
+        let (samples, skipped) = parse_dataset(text);
+        assert_eq!(samples.iter().map(|s| s.result).collect::<Vec<_>>(), [0.5, 1.0, 1.0, 0.0]);
+        assert_eq!(skipped, 2);
+        assert_eq!(samples[2].pos.ply_clock, 3);
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let weights = DEFAULT_PARAMS.to_vec();
+        assert_eq!(weights.len(), EvalParams::LEN);
+        assert_eq!(EvalParams::from_slice(&weights).to_vec(), weights);
+        assert!(DEFAULT_PARAMS.to_rust().starts_with("#[rustfmt::skip]\npub const DEFAULT_PARAMS: EvalParams"));
+    }
+
+    #[test]
+    fn quiet_leaves() {
+        // The hanging queen is taken before the position is scored
+        let sample = parse_sample("4k3/pp6/8/3q4/8/8/PP1R4/4K3 w - - [1.0]").unwrap();
+        let (_, leaf) = quiesce(&sample.pos, -INFINITY, INFINITY, &DEFAULT_PARAMS, 0);
+        assert_eq!(leaf.board.pieces(Piece::Queen, Colour::Black), 0);
+
+        // Linear in the weights, so it matches the evaluation up to rounding
+        let tuner = Tuner::new(&[sample], &DEFAULT_PARAMS);
+        let score = eval::evaluate_white(&leaf, &DEFAULT_PARAMS) as f64;
+        assert!((tuner.evaluate(&tuner.entries[0]) - score).abs() < 1.0);
+    }
+
+    #[test]
+    fn tuning_reduces_the_loss() {
+        // White wins every game with the extra rook and knight, which are worth too little to start with
+        let mut params = DEFAULT_PARAMS.clone();
+        params.mg_values[Piece::Rook as usize] = 100;
+        params.eg_values[Piece::Rook as usize] = 100;
+        let samples: Vec<Sample> = ["4k3/8/8/8/8/8/8/R3K3 w - - 1-0", "4k3/8/8/8/8/8/1N6/R3K3 b - - 1-0", "r3k3/8/8/8/8/8/8/4K3 w - - 0-1"]
+            .iter()
+            .map(|l| parse_sample(l).unwrap())
+            .collect();
+
+        let mut tuner = Tuner::new(&samples, &params);
+        let before = tuner.loss();
+        for _ in 0..50 {
+            tuner.step(5.0);
+        }
+        assert!(tuner.loss() < before);
+        assert!(tuner.params().eg_values[Piece::Rook as usize] > 100);
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::{ atomic::{ AtomicBool, Ordering }, Arc };
+use std::thread::{ self, JoinHandle };
+
+use crate::game::Game;
+
+use super :: {
+    options :: Options,
+    search  :: { Info, Limits, SearchResult, Searcher },
+};
+
+/// Runs searches on a background thread so front-ends can keep reading commands while the engine thinks
+pub struct Worker {
+    /// `None` while a search thread owns it
+    searcher: Option<Searcher>,
+    handle: Option<JoinHandle<(Searcher, SearchResult)>>,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+}
+
+impl Worker {
+    pub fn new(options: Options) -> Self {
+        let searcher = Searcher::new(options);
+        Self {
+            stop: searcher.stop_handle(),
+            ponder: searcher.ponder_handle(),
+            searcher: Some(searcher),
+            handle: None,
+        }
+    }
+
+    /// Waits for any running search and returns the searcher
+    pub fn searcher(&mut self) -> &mut Searcher {
+        self.wait();
+        self.searcher.as_mut().expect("searcher is returned by the search thread")
+    }
+
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        self.ponder.clone()
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Starts searching the game's current position. `report` is called after each iteration
+    /// and `done` with the final result, both on the search thread
+    pub fn start<R, D>(&mut self, game: &Game, limits: Limits, ponder: bool, mut report: R, done: D)
+    where
+        R: FnMut(&Info) + Send + 'static,
+        D: FnOnce(&SearchResult) + Send + 'static,
+    {
+        self.wait();
+        let mut searcher = self.searcher.take().expect("no search is running");
+        let root = *game.last_position();
+        let previous = game.previous_hashes();
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(ponder, Ordering::Relaxed);
+
+        self.handle = Some(thread::spawn(move || {
+            let result = searcher.search(&root, &previous, limits, &mut report);
+            done(&result);
+            (searcher, result)
+        }));
+    }
+
+    /// Switches a pondering search over to its normal limits
+    pub fn ponderhit(&self) {
+        self.ponder.store(false, Ordering::Relaxed);
+    }
+
+    /// Aborts any running search, returning its result
+    pub fn stop(&mut self) -> Option<SearchResult> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.ponder.store(false, Ordering::Relaxed);
+        self.wait()
+    }
+
+    /// Waits for any running search to finish, returning its result
+    pub fn wait(&mut self) -> Option<SearchResult> {
+        let handle = self.handle.take()?;
+        let (searcher, result) = handle.join().expect("search thread doesn't panic");
+        self.searcher = Some(searcher);
+        Some(result)
+    }
+}
@@ -1,14 +1,27 @@
 use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use colored::Colorize;
 
 use crate    :: {
+    board    :: Marks,
+    clock    :: { self, Clocks },
     position :: Position, 
-    ply      :: { Colour, Piece, Ply, Movement, PromotablePiece }, 
+    tree     :: MoveTree,
+    ply      :: { Colour, Piece, Ply }, 
     core     :: { 
-        repl :: { self, InputType }, 
-        parsers, Square, INITIAL_FEN,
+        repl :: { self, say, Cmd, InputBuffer, InputType }, 
+        theme :: { Theme, THEME_FILE },
+        parsers :: { self, pgn::SUFFIXES, san }, Square, INITIAL_FEN,
     }, 
+    engine   :: {
+        eval    :: evaluate,
+        mate    :: { self, MAX_MATE },
+        options :: { Options, MULTI_PV_RANGE },
+        search  :: { Info, Limits, SearchResult, Searcher },
+        syzygy  :: Wdl,
+    },
 };
 
 #[derive(Debug)]
@@ -18,12 +31,16 @@ pub enum DrawKind {
     ThreeFold,
     FiveFold,
     FiftyMove,
+    InsufficientMaterial,
+    /// A flag fell, but the opponent had nothing left to mate with
+    TimeoutVsInsufficientMaterial,
 }
 
 #[derive(Debug)]
 pub enum WinKind {
     Surrender,
-    Checkmate
+    Checkmate,
+    Timeout,
 }
 
 #[derive(Debug)]
@@ -34,12 +51,83 @@ pub enum State {
     Terminated
 }
 
+impl State {
+    /// Result as written in PGN
+    pub fn result(&self) -> &'static str {
+        match self {
+            State::White(_) => "1-0",
+            State::Black(_) => "0-1",
+            State::Draw(_)  => "1/2-1/2",
+            State::Terminated => "*",
+        }
+    }
+}
+
+/// How long the engine thinks when asked for a hint in a game between humans
+const HINT_TIME: Duration = Duration::from_secs(1);
+/// Time and number of lines for `analyse`
+const ANALYSE_TIME: Duration = Duration::from_secs(3);
+const ANALYSE_LINES: usize = 3;
+/// The engine accepts a draw offer once its score is this far below even
+const DRAW_ACCEPT_SCORE: i32 = -50;
+
+/// Splits a `draw` offer from the move it's made with, i.e. `e4 draw` or `draw e4`
+fn split_draw_offer(input: &str) -> (String, bool) {
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    let rest: Vec<&str> = tokens.iter().copied().filter(|t| !t.eq_ignore_ascii_case("draw")).collect();
+    (rest.join(" "), rest.len() < tokens.len())
+}
+
+/// Which side of the board is shown at the bottom
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    /// The side of whoever the board is shown to, the player on move between humans
+    #[default]
+    Auto,
+    White,
+    Black,
+}
+
+impl View {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "auto" => Some(View::Auto),
+            "white" | "w" => Some(View::White),
+            "black" | "b" => Some(View::Black),
+            _ => None,
+        }
+    }
+}
+
+/// Who makes the moves for one side
+#[derive(Debug, Clone)]
+pub enum Player {
+    Human,
+    Engine(Limits),
+}
+
+impl Player {
+    pub fn is_human(&self) -> bool {
+        matches!(self, Player::Human)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Game {
     pub history: Vec<Position>,
+    /// Positions taken back by `undo`, the most recent last, until `redo` replays them or a new ply is made
+    pub undone: Vec<Position>,
     pub ply: u16,
     pub mov: u16,
-    pub state: Option<State>
+    pub state: Option<State>,
+    /// Both players' clocks in a timed game
+    pub clocks: Option<Clocks>,
+    /// Which way round the board is shown, set by `flip` and kept for the session
+    pub view: View,
+    /// How boards are drawn, changed by `theme`
+    pub theme: Theme,
+    /// Every line played, with comments and NAGs. Its cursor follows the current position
+    pub tree: MoveTree,
 }
 
 impl Game {
@@ -101,212 +189,758 @@ impl Game {
         parsers::fen::parse_fen(fen)
     }
 
+    /// The game as PGN, with the result once it's over
     pub fn as_pgn(&self) -> String {
-        todo!()
+        let mut tree = self.tree.clone();
+        tree.result = self.state.as_ref().map(|state| state.result().to_string());
+        let result = tree.result.clone().unwrap_or("*".to_string());
+        tree.tags = [("Event", "?"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "?"), ("White", "?"), ("Black", "?"), ("Result", &result)]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .to_vec();
+        if let Some(clocks) = &self.clocks {
+            tree.tags.push(("TimeControl".to_string(), clocks.control.pgn_tag()));
+            // Each move's clock, as the mover had left after it. Moves from before the clocks were last reset have none
+            let path = tree.path(tree.cursor());
+            for (&id, time) in path[path.len().saturating_sub(clocks.history.len())..].iter().zip(&clocks.history) {
+                let clk = format!("[%clk {}]", clock::format_clk(*time));
+                let comment = &mut tree.node_mut(id).comment;
+                *comment = Some(comment.take().map_or(clk.clone(), |c| format!("{c} {clk}")));
+            }
+        }
+        tree.to_pgn()
     }
 
-    pub fn from_pgn(pgn: &str) -> Self {
-        todo!()
+    /// The first game in a PGN file, at the end of its main line
+    pub fn from_pgn(pgn: &str) -> Result<Self, String> {
+        let mut tree = MoveTree::from_pgn(pgn)?.into_iter().next().ok_or("No game in the PGN")?;
+        tree.go_to_end();
+        Ok(Self { tree, ..Self::new() }.follow_tree())
     }
 
     pub fn last_position(&self) -> &Position {
         self.history.last().expect("Game History is not empty")
     }
 
-    pub fn stringify_board(&self, colour: Colour) -> String {
-        match colour {
-            Colour::White => self.last_position().board.get_board(true),
-            Colour::Black => self.last_position().board.get_board(false)
+    /// Hashes of every position before the current one, for repetition detection
+    pub fn previous_hashes(&self) -> Vec<u64> {
+        self.history[..self.history.len() - 1].iter().map(|p| p.hash).collect()
+    }
+
+    /// Plays an already validated ply, updating the move and ply counters and checking if the game is over
+    pub fn make_ply(&mut self, ply: Ply) {
+        let pos = self.last_position().make_ply(ply);
+        if ply.mov.player == Colour::Black {
+            self.mov += 1;
         }
+        self.ply += 1;
+        self.history.push(pos);
+        self.tree.play(ply);
+        self.undone = self.continuation();
+        self.update_state();
+    }
+
+    /// Positions along the tree's main line after the cursor, the furthest first as `undone` keeps them
+    fn continuation(&self) -> Vec<Position> {
+        self.tree.mainline(self.tree.cursor()).iter().rev().map(|&id| self.tree.node(id).position).collect()
     }
 
-    pub fn print_help() {
-        println!("TODO\n");
+    /// The game up to the tree's cursor, after the cursor moves to another line
+    fn follow_tree(self) -> Self {
+        let Self { history, ply, mov, .. } = self.tree.to_game();
+        let mut game = Self { history, ply, mov, ..self };
+        game.undone = game.continuation();
+        game.update_state();
+        game
+    }
+
+    /// Ends the game when `colour` runs out of time. It's a draw if the opponent has only their king left, and a loss otherwise.
+    /// NOTE: The rule also draws when the opponent has material but no sequence of legal moves could mate, i.e. a lone
+    /// bishop or knight against a bare king, which isn't detected
+    pub fn flag(&mut self, colour: Colour) {
+        let board = &self.last_position().board;
+        let opponent = colour.opposite();
+        let can_mate = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].into_iter().any(|p| board.pieces(p, opponent) != 0);
+        self.state = Some(match (can_mate, opponent) {
+            (false, _) => State::Draw(DrawKind::TimeoutVsInsufficientMaterial),
+            (true, Colour::White) => State::White(WinKind::Timeout),
+            (true, Colour::Black) => State::Black(WinKind::Timeout),
+        });
+    }
+
+    /// Ends the game in a draw both players agreed to
+    pub fn agree_draw(&mut self) {
+        self.state = Some(State::Draw(DrawKind::Mutual));
+    }
+
+    /// Takes back the last ply, keeping it for `redo`. Returns false if there is nothing to take back
+    pub fn undo(&mut self) -> bool {
+        if self.history.len() < 2 {
+            return false
+        }
+        self.undone.extend(self.history.pop());
+        self.tree.back();
+        if self.last_position().side_to_move() == Colour::Black {
+            self.mov -= 1;
+        }
+        self.ply -= 1;
+        self.update_state();
+        true
+    }
+
+    /// Replays the last ply taken back. Returns false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        let Some(pos) = self.undone.pop() else { return false };
+        let next = self.tree.current().children.iter().copied().find(|&id| self.tree.node(id).position.hash == pos.hash);
+        self.tree.go_to(next.expect("moves taken back are in the tree"));
+        if self.last_position().side_to_move() == Colour::Black {
+            self.mov += 1;
+        }
+        self.ply += 1;
+        self.history.push(pos);
+        self.update_state();
+        true
+    }
+
+    /// Number of times the current position has occurred
+    pub fn repetitions(&self) -> usize {
+        let hash = self.last_position().hash;
+        self.history.iter().filter(|p| p.hash == hash).count()
+    }
+
+    /// Ends the game if the current position is checkmate or a draw, or clears a previous ending after an undo
+    fn update_state(&mut self) {
+        let pos = self.last_position();
+        self.state = if pos.legal_plies().is_empty() {
+            match (pos.check, pos.side_to_move()) {
+                (Some(_), Colour::White) => Some(State::Black(WinKind::Checkmate)),
+                (Some(_), Colour::Black) => Some(State::White(WinKind::Checkmate)),
+                (None, _) => Some(State::Draw(DrawKind::Stalemate)),
+            }
+        } else if self.repetitions() >= 5 {
+            Some(State::Draw(DrawKind::FiveFold))
+        } else if self.repetitions() >= 3 {
+            Some(State::Draw(DrawKind::ThreeFold))
+        } else if pos.ply_clock >= 100 {
+            Some(State::Draw(DrawKind::FiftyMove))
+        } else if pos.is_insufficient_material() {
+            Some(State::Draw(DrawKind::InsufficientMaterial))
+        } else {
+            None
+        };
+    }
+
+    /// Whether a board shown to `colour` has White at the bottom
+    pub fn shows_white(&self, colour: Colour) -> bool {
+        match self.view {
+            View::Auto => colour == Colour::White,
+            View::White => true,
+            View::Black => false,
+        }
+    }
+
+    /// The last move, which every shown board marks
+    fn board_marks(&self) -> Marks {
+        let last_move = self.last_position().last_ply.map_or(0, |ply| ply.mov.from_sq.as_bb() | ply.mov.to_sq.as_bb());
+        Marks { last_move, ..Default::default() }
+    }
+
+    pub fn stringify_board(&self, colour: Colour) -> String {
+        self.last_position().board.render(self.shows_white(colour), &self.board_marks(), &self.theme)
+    }
+
+    /// The board shown to `colour` with where the piece on `sq` can go
+    pub fn stringify_moves_board(&self, colour: Colour, sq: Square) -> String {
+        let board = &self.last_position().board;
+        let Marks { attacks, moves, .. } = board.selection_marks(sq);
+        board.render(self.shows_white(colour), &Marks { attacks, moves, ..self.board_marks() }, &self.theme)
     }
 
     pub fn play_two_player(&mut self) {
+        self.play([Player::Human, Player::Human]);
+    }
+
+    /// Plays against the engine, which replies automatically and shows its thinking under the board
+    pub fn play_engine(&mut self, human: Colour, limits: Limits) {
+        match human {
+            Colour::White => self.play([Player::Human, Player::Engine(limits)]),
+            Colour::Black => self.play([Player::Engine(limits), Player::Human]),
+        }
+    }
+
+    /// Game loop for any mix of human and engine players, indexed white then black
+    pub fn play(&mut self, players: [Player; 2]) {
+        let mut searcher = Searcher::new(Options::default());
+        // Watch from the human's side when there is only one
+        let pov = match &players {
+            [Player::Human, Player::Engine(_)] => Some(Colour::White),
+            [Player::Engine(_), Player::Human] => Some(Colour::Black),
+            _ => None,
+        };
+        let hint_limits = players.iter().find_map(|p| match p {
+            Player::Engine(limits) => Some(limits.clone()),
+            Player::Human => None,
+        }).unwrap_or(Limits { movetime: Some(HINT_TIME), ..Default::default() });
+        // Whether the last move came with a draw offer, for the player to move to answer
+        let mut draw_offered = false;
+        let mut input = InputBuffer::default();
+        if let Some(warning) = repl::fit_theme(&mut self.theme) {
+            say!("Theme: {warning}\n");
+        }
 
         while self.state.is_none() {
             let (player, prompt) = match self.last_position().was_blacks_move {
                 true  => (Colour::White, "White to play: ".bright_blue()),
                 false => (Colour::Black, "Black to play: ".bright_red()),
             };
-                
-            // self.get_board(player);
-            // println!("Move: {} Ply: {}\r\n", self.mov, self.ply);
-
-            let usr_input = match repl::get_input(&self, player, &prompt).unwrap() {
-                InputType::String(inp) => match inp.to_lowercase().trim() {
-                    "" => unreachable!(),
-                    "quit" | "exit" => return,
-                    "help" | "-h" => {
-                        Self::print_help();
-                        continue
+            let offered = std::mem::take(&mut draw_offered);
+            if let Some(clocks) = &mut self.clocks {
+                clocks.start();
+            }
+
+            if let Player::Engine(limits) = &players[player as usize] {
+                say!("{}\n", self.stringify_board(pov.unwrap_or(player)));
+                say!("{}", repl::status_line(self));
+                let result = self.think(&mut searcher, limits.clone(), true);
+                if offered && result.score <= DRAW_ACCEPT_SCORE {
+                    say!("{player:?} accepts the draw\n");
+                    self.agree_draw();
+                    break
+                }
+                if offered {
+                    say!("{player:?} declines the draw");
+                }
+                let Some(best) = result.best else { break };
+                if !self.press_clock(player) {
+                    break
+                }
+                say!("{player:?} plays {}\n", san::to_san(self.last_position(), best));
+                self.make_ply(best);
+                continue
+            }
+
+            if offered {
+                let question = match player {
+                    Colour::White => "White: Black offers a draw, do you accept? (y/n) ".bright_blue(),
+                    Colour::Black => "Black: White offers a draw, do you accept? (y/n) ".bright_red(),
+                };
+                match repl::ask(&question.to_string()).as_deref() {
+                    Some("y" | "yes") => {
+                        self.agree_draw();
+                        break
+                    },
+                    Some(_) => say!("Draw declined\n"),
+                    None => return,
+                }
+            }
+
+            let usr_input = match repl::get_input(self, player, &prompt, &mut input).unwrap() {
+                InputType::String(inp) => inp,
+                InputType::Flagged => {
+                    self.press_clock(player);
+                    break
+                },
+                InputType::Termination => return,
+            };
+
+            if let Some((cmd, arg)) = repl::parse_command(&usr_input) {
+                match cmd {
+                    Cmd::Quit => return,
+                    Cmd::Help => repl::print_help(arg),
+                    Cmd::Moves => self.moves_command(arg),
+                    Cmd::Fen => say!("{}\n", self.as_fen()),
+                    Cmd::Pgn => say!("{}", self.as_pgn()),
+                    Cmd::Eval => {
+                        let score = evaluate(self.last_position());
+                        let white = match player { Colour::White => score, Colour::Black => -score };
+                        say!("Static evaluation: {}\n", repl::format_eval(white));
+                    },
+                    Cmd::Hint => {
+                        if let Some(best) = self.think(&mut searcher, hint_limits.clone(), false).best {
+                            say!("Hint: {}\n", san::to_san(self.last_position(), best));
+                        }
+                    },
+                    Cmd::Analyse => self.analyse_command(&mut searcher, arg),
+                    Cmd::Mate => self.mate_command(arg),
+                    Cmd::Book => self.book_command(&mut searcher, arg),
+                    Cmd::Tb => self.tablebase_command(&mut searcher, arg),
+                    Cmd::Flip => self.flip_command(arg, pov.unwrap_or(player)),
+                    Cmd::Theme => self.theme_command(arg),
+                    Cmd::Undo | Cmd::Redo | Cmd::Takeback | Cmd::Variations | Cmd::Delete if self.clocks.as_ref().is_some_and(Clocks::is_running) => {
+                        say!("Moves can't be taken back while the clocks are running\n");
+                    },
+                    Cmd::Undo | Cmd::Redo => {
+                        // Against the engine, step over its reply too so it stays the human's turn
+                        let plies = if players[player.opposite() as usize].is_human() { 1 } else { 2 };
+                        let (step, available, name): (fn(&mut Self) -> bool, usize, _) = match cmd {
+                            Cmd::Undo => (Self::undo, self.history.len() - 1, "undo"),
+                            _ => (Self::redo, self.undone.len(), "redo"),
+                        };
+                        match available >= plies {
+                            true => (0..plies).for_each(|_| { step(self); }),
+                            false => say!("Nothing to {name}\n"),
+                        }
+                    },
+                    Cmd::Takeback => {
+                        // The player's own last move and the reply to it
+                        if self.history.len() < 3 {
+                            say!("Nothing to take back\n");
+                            continue
+                        }
+                        if players[player.opposite() as usize].is_human() {
+                            let question = match player {
+                                Colour::White => "Black: Do you accept taking back White's last move? (y/n) ".bright_red(),
+                                Colour::Black => "White: Do you accept taking back Black's last move? (y/n) ".bright_blue(),
+                            };
+                            match repl::ask(&question.to_string()).as_deref() {
+                                Some("y" | "yes") => {},
+                                Some(_) => {
+                                    say!("Takeback declined\n");
+                                    continue
+                                },
+                                None => return,
+                            }
+                        }
+                        self.undo();
+                        self.undo();
+                    },
+                    Cmd::Variations => self.variations_command(arg),
+                    Cmd::Promote => self.promote_command(),
+                    Cmd::Delete => self.delete_command(),
+                    Cmd::Comment => self.comment_command(arg),
+                    Cmd::Annotate => self.annotate_command(arg),
+                    Cmd::Draw => say!("Offer a draw along with your move, i.e. `e4 draw`\n"),
+                    Cmd::New => {
+                        let clocks = self.clocks.as_ref().map(|c| c.reset(Colour::White));
+                        *self = Self { view: self.view, theme: self.theme, clocks, ..Self::new() };
                     },
-                    "draw" => { 
-                        // let (_nxt_player, prompt) = match player {
-                        //     Colour::White => (Colour::Black, "Black: Do you except a draw? (y/n) ".bright_red()),
-                        //     Colour::Black => (Colour::White, "White: Do you except a draw? (y/n) ".bright_blue()),
-                        // };
-
-                        // match repl::get_input(&prompt).unwrap() {
-                        //     InputType::String(inp) => match inp.to_lowercase().trim() {
-                        //         "y" | "yes" => println!("Draw"),
-                        //         "n" | "no"  => continue,
-                        //         _ => continue
-                        //     },
-                        //     InputType::Termination => return
-                        // }
-                        // return
-                        unreachable!()
+                    Cmd::Load => match std::fs::read_to_string(arg).map_err(|e| e.to_string()).and_then(|pgn| Self::from_pgn(&pgn)) {
+                        Ok(game) => {
+                            let clocks = self.clocks.as_ref().map(|c| c.reset(game.last_position().side_to_move()));
+                            *self = Self { view: self.view, theme: self.theme, clocks, ..game };
+                        },
+                        Err(e) => say!("Couldn't load `{arg}`: {e}\n"),
                     },
-                    "surrender" | "surr" | "sur" => { 
+                    Cmd::Save => match std::fs::write(arg, self.as_pgn()) {
+                        Ok(()) => say!("Saved to `{arg}`\n"),
+                        Err(e) => say!("Couldn't save to `{arg}`: {e}\n"),
+                    },
+                    Cmd::Surrender => {
                         match player {
                             Colour::White => {
-                                println!("Black wins by surrender");
+                                say!("Black wins by surrender");
                                 self.state = Some(State::Black(WinKind::Surrender));
                             },
                             Colour::Black => {
-                                println!("White wins by surrender");
+                                say!("White wins by surrender");
                                 self.state = Some(State::White(WinKind::Surrender));
                             },
                         }
                         break
                     },
-                    _ => inp,
-                },
-                InputType::Termination => return,
-            };
+                }
+                continue
+            }
 
-            let Some(mov) = self.parse_user_input(player, &usr_input).unwrap() else {
-                println!("Move not valid");
+            let (usr_input, offer) = split_draw_offer(&usr_input);
+            let Some(ply) = san::parse_san(self.last_position(), &usr_input.replace(' ', "")) else {
+                say!("Move not valid\n");
                 continue;
             };
-            if self.validate_movement(mov).is_none() {
-                println!("Move not possible");
-                continue;
-            }
-
-            if self.update(mov).is_err() {
+            if !self.press_clock(player) {
                 break
             }
+            self.make_ply(ply);
+            if offer {
+                say!("{player:?} offers a draw\n");
+                draw_offered = true;
+            }
+        }
+
+        if let Some(state) = &self.state {
+            say!("{}\n", self.stringify_board(pov.unwrap_or(Colour::White)));
+            say!("{} {state:?}", state.result());
         }
     }
 
-    pub fn promote(&mut self, ply: Ply) -> Option<Ply> {
-        let mut ply = ply;
+    /// Stops the mover's clock once they've chosen a move, ending the game if their time ran out first.
+    /// Returns whether the move can still be played
+    fn press_clock(&mut self, player: Colour) -> bool {
+        match self.clocks.as_mut().map(Clocks::press) {
+            Some(false) => {
+                say!("{player:?} ran out of time");
+                self.flag(player);
+                false
+            },
+            _ => true,
+        }
+    }
 
-        let prompt = match ply.mov.player {
-            Colour::White => "Promote to what? (q/b/n/r): ".bright_blue(),
-            Colour::Black => "Promote to what? (q/b/n/r): ".bright_red(),
+    /// `moves [square]` lists the legal moves in SAN, or only those of the piece on a square
+    fn moves_command(&self, arg: &str) {
+        let pos = self.last_position();
+        let from = match arg {
+            "" => None,
+            sq => match Square::from_str(sq) {
+                Some(sq) => Some(sq),
+                None => return say!("Expected a square, got `{sq}`\n"),
+            },
         };
+        let moves: Vec<String> = pos.legal_plies().into_iter()
+            .filter(|ply| from.is_none_or(|sq| ply.mov.from_sq == sq))
+            .map(|ply| san::to_san(pos, ply))
+            .collect();
+        match moves.is_empty() {
+            true => say!("No legal moves\n"),
+            false => say!("{}\n", moves.join(" ")),
+        }
+    }
 
-        match repl::get_input(&self, ply.mov.player, &prompt).unwrap() {
-            InputType::String(inp) => match inp.to_lowercase().as_str() {
-                "quit" | "exit" => return None,
-                "q" => {
-                    ply.promotion = Some(PromotablePiece::Queen);
-                    // todo
-                },
-                "b" => {
-                    ply.promotion = Some(PromotablePiece::Bishop);
-                    // todo
-                }
-                "n" => {
-                    ply.promotion = Some(PromotablePiece::Knight);
-                    // todo
-                },
-                "r" => {
-                    ply.promotion = Some(PromotablePiece::Rook);
-                    // todo
-                },
-                _ => todo!(),
-            },
-            InputType::Termination => return None,
-        }
-        Some(ply)
-    }
-
-    fn update(&mut self, next_move: Movement) -> Result<(),()> {
-        // Note: Assumes move has been validated
-        let mut pos = self.last_position().clone();
-        // Update Position, promote PLyMove to Ply by checking is_capture/is_promotion
-        let ply = Ply::from_move(next_move, false, None);
-        // check capture
-        let ply = self.promote(ply).ok_or(())?;
-        // Update Castling
-        // Update Ply_Clock
-        // Update En_Passant_Targ
-        // Update Check
-        
-        // Update Last_Ply
-        pos.last_ply = Some(ply);
-
-        self.mov = if pos.was_blacks_move { self.mov + 1} else { self.mov };
-        self.ply = (self.ply % 2) + 1;
-        self.history.push(pos);
+    /// `var` lists the moves played from the current position, the main line first. `var <n>` plays the nth of them
+    fn variations_command(&mut self, arg: &str) {
+        let pos = *self.last_position();
+        let children = self.tree.current().children.clone();
+        if children.is_empty() {
+            return say!("No moves played from here\n")
+        }
+        if arg.is_empty() {
+            let sans: Vec<String> = children.iter().enumerate()
+                .map(|(i, &id)| format!("{}. {}", i + 1, san::to_san(&pos, self.tree.node(id).ply.expect("only the root has no ply"))))
+                .collect();
+            return say!("{}\n", sans.join("  "))
+        }
+        match arg.parse::<usize>() {
+            Ok(n @ 1..) if self.tree.enter(n - 1) => *self = std::mem::take(self).follow_tree(),
+            _ => say!("Expected a number from 1 to {}, got `{arg}`\n", children.len()),
+        }
+    }
 
-        Ok(())
+    fn promote_command(&mut self) {
+        match self.tree.cursor() {
+            MoveTree::ROOT => say!("Nothing to promote\n"),
+            id => {
+                self.tree.promote(id);
+                say!("This is now the main line\n");
+            },
+        }
     }
 
-    fn parse_user_input(&self, player: Colour, inp: &str) -> Result<Option<Movement>, String> {
-        // parse input i.e. e4
-        // find corresponding piece i.e. e-pawn
-        // return 
+    fn delete_command(&mut self) {
+        match self.tree.delete(self.tree.cursor()) {
+            true => *self = std::mem::take(self).follow_tree(),
+            false => say!("Nothing to delete\n"),
+        }
+    }
 
-        // Pe4  => E Pawn to e4
-        // Pexd => E Pawn captures d side
-        // K b1 c3 => Knight B1 to C3
+    /// Comments on the move to the current position, or on the whole game before the first move
+    fn comment_command(&mut self, text: &str) {
+        let id = self.tree.cursor();
+        self.tree.node_mut(id).comment = (!text.is_empty()).then(|| text.to_string());
+    }
 
-        let inp = inp.to_lowercase();
-        let mut chars = inp.chars().peekable();
+    /// `annotate !?` adds the NAG a move suffix stands for to the last move, `annotate` alone clears them
+    fn annotate_command(&mut self, arg: &str) {
+        let id = self.tree.cursor();
+        if id == MoveTree::ROOT {
+            return say!("No move to annotate\n")
+        }
+        let nags = &mut self.tree.node_mut(id).nags;
+        match SUFFIXES.iter().find(|(suffix, _)| *suffix == arg) {
+            _ if arg.is_empty() => nags.clear(),
+            Some((_, nag)) => {
+                nags.retain(|n| !SUFFIXES.iter().any(|(_, s)| s == n));
+                nags.push(*nag);
+            },
+            None => say!("Expected one of {}, got `{arg}`\n", SUFFIXES.map(|(suffix, _)| suffix).join(" ")),
+        }
+    }
 
-        let piece = match chars.next().expect("inp has at least one char") {
-            'n' => Piece::Knight,
-            'b' => match chars.peek() {
-                Some('1'..='8') => Piece::Pawn,
-                Some(_) => Piece::Bishop,
-                _ => Piece::Bishop
+    /// `flip` turns the board around from how it's shown to `colour`, fixing it that way.
+    /// `flip white`, `flip black` and `flip auto` choose the view outright
+    fn flip_command(&mut self, arg: &str, colour: Colour) {
+        self.view = match arg {
+            "" if self.shows_white(colour) => View::Black,
+            "" => View::White,
+            arg => match View::parse(arg) {
+                Some(view) => view,
+                None => return say!("Expected white, black or auto, got `{arg}`\n"),
             },
-            'r' => Piece::Rook,
-            'q' => Piece::Queen,
-            'k' => Piece::King,
-            'a'..='h' => Piece::Pawn,
-            c => return Err(format!("Unknown char: '{}'", c))
         };
+        match self.view {
+            View::Auto => say!("The board turns to the player on move\n"),
+            view => say!("The board is shown from {view:?}'s side\n"),
+        }
+    }
 
-        match chars.peek() {
-            Some('x') => {
-                chars.next();
+    /// `theme` shows the settings, `theme <setting> <value>` changes one, `theme save` keeps them for next time
+    /// and `theme reset` goes back to the defaults
+    fn theme_command(&mut self, arg: &str) {
+        let (name, value) = arg.split_once(char::is_whitespace).map_or((arg, ""), |(n, v)| (n, v.trim()));
+        match (name, value) {
+            ("", _) => {},
+            ("save", "") => match self.theme.save(THEME_FILE) {
+                Ok(()) => return say!("Saved the theme to `{THEME_FILE}`\n"),
+                Err(e) => return say!("Couldn't save the theme: {e}\n"),
+            },
+            ("reset", "") => self.theme = Theme::default(),
+            (name, value) => if let Err(e) = self.theme.set(name, value) {
+                return say!("{e}\n")
             },
-            None => return Err(format!("No more chars")),
-            _ => {},
         }
+        if let Some(warning) = repl::fit_theme(&mut self.theme) {
+            say!("{warning}");
+        }
+        let settings: Vec<String> = self.theme.settings().iter().map(|(name, value)| format!("{name} {value}")).collect();
+        say!("{}\n", settings.join(", "));
+    }
 
-        let pos = match chars.next() {
-            Some(file @ 'a'..='h') => match chars.next() {
-                Some(rank @ '1'..='8') => {
-                    format!("{file}{rank}")
+    /// `book <file>` loads a Polyglot book for the engine to play from, `book` lists the book moves
+    fn book_command(&self, searcher: &mut Searcher, path: &str) {
+        if !path.is_empty() {
+            match searcher.set_option("BookFile", path) {
+                Ok(()) => {
+                    searcher.options.own_book = true;
+                    match searcher.book() {
+                        Some(book) if !book.is_empty() => say!("Loaded {} book entries\n", book.len()),
+                        _ => say!("The book has no entries, so the engine won't play from it\n"),
+                    }
                 },
-                None => return Err(format!("No more chars")),
-                Some(c) => return Err(format!("aUnknown char: '{}'", c))
+                Err(e) => say!("{e}\n"),
+            }
+            return
+        }
+
+        let Some(book) = searcher.book() else {
+            return say!("No book loaded, use `book <file>`\n")
+        };
+        let pos = self.last_position();
+        let moves = book.moves(pos);
+        if moves.is_empty() {
+            return say!("Out of book\n")
+        }
+        let total: u32 = moves.iter().map(|m| m.weight as u32).sum();
+        for m in moves {
+            let share = m.weight as f64 * 100.0 / total.max(1) as f64;
+            say!("{:<8} {:>5.1}%  weight {}", san::to_san(pos, m.ply), share, m.weight);
+        }
+        say!("");
+    }
+
+    /// `tb <directory>` loads Syzygy tablebases, `tb` shows the result of the current position with perfect play
+    fn tablebase_command(&self, searcher: &mut Searcher, path: &str) {
+        if !path.is_empty() {
+            match searcher.set_option("SyzygyPath", path) {
+                Ok(()) => {
+                    let tb = searcher.tablebases().expect("tablebases were just loaded");
+                    say!("Found {} tables of up to {} pieces\n", tb.table_count(), tb.largest());
+                },
+                Err(e) => say!("{e}\n"),
+            }
+            return
+        }
+
+        let Some(tb) = searcher.tablebases() else {
+            return say!("No tablebases loaded, use `tb <directory>`\n")
+        };
+        let pos = self.last_position();
+        let (Some(wdl), Some(dtz)) = (tb.probe_wdl(pos), tb.probe_dtz(pos)) else {
+            return say!("Not in the tablebases\n")
+        };
+        let player = pos.side_to_move();
+        let verdict = match wdl {
+            Wdl::Win => format!("{player:?} wins"),
+            Wdl::CursedWin => format!("{player:?} wins, but not within fifty moves"),
+            Wdl::Draw => "Draw".to_string(),
+            Wdl::BlessedLoss => format!("{:?} wins, but not within fifty moves", player.opposite()),
+            Wdl::Loss => format!("{:?} wins", player.opposite()),
+        };
+        let best: Vec<String> = tb.root_moves(pos).unwrap_or_default().into_iter().map(|p| san::to_san(pos, p)).collect();
+        match dtz {
+            0 => say!("{verdict}"),
+            _ => say!("{verdict}, {} plies to the next capture or pawn move", dtz.abs()),
+        }
+        say!("Best: {}\n", best.join(" "));
+    }
+
+    /// `analyse [N]` prints the engine's best N lines in SAN, scored for the side to move
+    fn analyse_command(&self, searcher: &mut Searcher, arg: &str) {
+        let lines = match arg {
+            "" => ANALYSE_LINES,
+            n => match n.parse::<usize>() {
+                Ok(n) if (MULTI_PV_RANGE.0..=MULTI_PV_RANGE.1).contains(&n) => n,
+                _ => return say!("Expected a number of lines up to {}, got `{n}`\n", MULTI_PV_RANGE.1),
             },
-            None => return Err(format!("No more chars")),
-            Some(c) => return Err(format!("bUnknown char: '{}'", c))
         };
 
-        println!("P: {piece:?}\nM: {pos}");
+        let multi_pv = searcher.options.multi_pv;
+        searcher.options.multi_pv = lines;
+        let (pos, mov) = (self.last_position(), self.mov);
+        let limits = Limits { movetime: Some(ANALYSE_TIME), ..Default::default() };
+        // Every iteration reports its lines in order, keep those of the last one
+        let mut infos: Vec<Info> = Vec::new();
+        searcher.search(pos, &self.previous_hashes(), limits, |info| {
+            if info.multipv == 1 {
+                infos.clear();
+            }
+            infos.push(info.clone());
+        });
+        searcher.options.multi_pv = multi_pv;
+
+        let Some(depth) = infos.first().map(|info| info.depth) else {
+            return say!("No moves to analyse\n")
+        };
+        say!("Depth {depth}");
+        for info in &infos {
+            say!("{:>3}. {:>6}  {}", info.multipv, repl::format_eval(info.score), san::line_to_san(pos, mov, &info.pv));
+        }
+        say!("");
+    }
+
+    /// `mate N` solves for a forced mate in N moves, listing every key so problems can be checked for cooks
+    fn mate_command(&self, arg: &str) {
+        let moves = match arg.parse::<u8>() {
+            Ok(n @ 1..=MAX_MATE) => n,
+            _ => return say!("Expected a number of moves from 1 to {MAX_MATE}, got `{arg}`\n"),
+        };
+        let (pos, mov) = (self.last_position(), self.mov);
+        if !repl::is_line_mode() {
+            say!("Looking for a mate in {moves}, press any key to stop");
+        }
+        let (solution, stopped) = repl::until_key(|stop| (mate::solve(pos, moves, stop), stop.load(Ordering::Relaxed))).unwrap();
+        let Some(solution) = solution else {
+            return match stopped {
+                true => say!("Stopped before finding a mate in {moves}\n"),
+                false => say!("No mate in {moves}\n"),
+            }
+        };
 
-        // if 
+        say!("Mate in {}: {}", solution.moves, san::line_to_san(pos, mov, &solution.line));
+        let keys: Vec<String> = solution.keys.iter().map(|&ply| san::to_san(pos, ply)).collect();
+        match keys.len() {
+            1 => say!("Key: {}, unique\n", keys[0]),
+            _ => say!("Keys: {}, not unique\n", keys.join(" ")),
+        }
+    }
 
-        // for c in inp.chars() {
+    /// Searches the current position, printing the engine's thinking if `verbose`
+    fn think(&self, searcher: &mut Searcher, limits: Limits, verbose: bool) -> SearchResult {
+        let (pos, mov) = (self.last_position(), self.mov);
+        searcher.search(pos, &self.previous_hashes(), limits, |info| {
+            if verbose {
+                say!("{}", repl::format_thinking(info, pos, mov));
+            }
+        })
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for mov in moves {
+            let ply = game.last_position().parse_uci(mov).unwrap();
+            game.make_ply(ply);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut game = Game::new();
+        play(&mut game, &["e2e4", "e7e5", "g1f3"]);
+        let fen = game.as_fen();
+
+        assert!(game.undo() && game.undo());
+        assert_eq!((game.mov, game.ply, game.as_fen()), (1, 2, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_string()));
+        assert!(game.redo() && game.redo());
+        assert!(!game.redo());
+        assert_eq!((game.mov, game.as_fen()), (2, fen));
+
+        // A new move drops what was taken back
+        game.undo();
+        play(&mut game, &["b1c3"]);
+        assert!(!game.redo());
+        assert!(game.undo() && game.undo() && game.undo());
+        assert!(!game.undo());
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let mut game = Game::new();
+        play(&mut game, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let pgn = game.as_pgn();
+        assert!(pgn.starts_with("[Event \"?\"]\n"));
+        assert!(pgn.ends_with("[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n"), "{pgn}");
+
+        let read = Game::from_pgn(&pgn).unwrap();
+        assert_eq!(read.as_fen(), game.as_fen());
+        assert!(matches!(read.state, Some(State::Black(WinKind::Checkmate))));
+        assert!(Game::from_pgn("").is_err());
+    }
 
-        // }
+    #[test]
+    fn draw_offers() {
+        assert_eq!(split_draw_offer("e4 draw"), ("e4".to_string(), true));
+        assert_eq!(split_draw_offer("Draw e2 e4"), ("e2 e4".to_string(), true));
+        assert_eq!(split_draw_offer("nf3"), ("nf3".to_string(), false));
+
+        let mut game = Game::new();
+        play(&mut game, &["e2e4"]);
+        game.agree_draw();
+        assert!(game.as_pgn().ends_with("[Result \"1/2-1/2\"]\n\n1. e4 1/2-1/2\n\n"));
+        // Taking the move back takes the agreement with it
+        game.undo();
+        assert!(game.state.is_none());
+    }
 
-        todo!()
+    #[test]
+    fn views() {
+        let mut game = Game::new();
+        assert!(game.shows_white(Colour::White) && !game.shows_white(Colour::Black));
+        game.flip_command("", Colour::Black);
+        assert_eq!(game.view, View::White);
+        assert!(game.shows_white(Colour::Black));
+        game.flip_command("", Colour::Black);
+        assert_eq!(game.view, View::Black);
+        game.flip_command("Auto", Colour::White);
+        assert_eq!(game.view, View::Auto);
+        game.flip_command("sideways", Colour::White);
+        assert_eq!(game.view, View::Auto);
     }
 
-    pub fn validate_movement(&self, inp: Movement) -> Option<Ply> {
-        todo!()
+    #[test]
+    fn variations() {
+        let mut game = Game::new();
+        play(&mut game, &["e2e4", "e7e5", "g1f3"]);
+        game.undo();
+        game.undo();
+        play(&mut game, &["c7c5"]);
+        game.annotate_command("!?");
+        game.comment_command("The Sicilian");
+        assert!(game.as_pgn().ends_with("1. e4 e5 (1... c5 $5 {The Sicilian}) 2. Nf3 *\n\n"), "{}", game.as_pgn());
+        game.promote_command();
+        assert!(game.as_pgn().ends_with("1. e4 c5 $5 {The Sicilian} (1... e5 2. Nf3) *\n\n"), "{}", game.as_pgn());
+
+        // Back into the old line, with its moves to redo
+        game.undo();
+        game.variations_command("2");
+        assert_eq!((game.mov, game.undone.len()), (2, 1));
+        assert!(game.redo());
+        assert_eq!(game.as_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        game.undo();
+        game.delete_command();
+        assert_eq!((game.history.len(), game.undone.len()), (2, 1));
+        assert!(game.as_pgn().ends_with("1. e4 c5 $5 {The Sicilian} *\n\n"), "{}", game.as_pgn());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn undo_restores_the_state() {
+        // Fool's mate
+        let mut game = Game::new();
+        play(&mut game, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert!(matches!(game.state, Some(State::Black(WinKind::Checkmate))));
+        game.undo();
+        assert!(game.state.is_none());
+        game.redo();
+        assert!(matches!(game.state, Some(State::Black(WinKind::Checkmate))));
+    }
+}
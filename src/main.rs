@@ -2,14 +2,69 @@ mod board;
 mod game;
 mod position;
 mod ply;
+mod tree;
+mod clock;
 mod core;
+mod engine;
 
-use core::Square;
+use std::time::Duration;
+
+use core::{ repl, theme::{ Theme, THEME_FILE }, Square };
+use engine::{
+    book::{ BookBuilder, BuildOptions }, eval::DEFAULT_PARAMS, search::Limits, sprt::Decision,
+    tournament::{ self, MatchOptions, TimeControl }, tune::{ self, Tuner },
+};
+use clock::Clocks;
+use ply::Colour;
 
 // use crate::core::{repl, utils::pp_bb, Square};
 use game::*;
 
 fn main() {
+    // `--lines` anywhere reads plain lines even at a terminal
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (flags, args): (Vec<String>, Vec<String>) = args.into_iter().partition(|arg| arg == "--lines");
+    repl::init_line_mode(!flags.is_empty());
+
+    match args.first().map(String::as_str) {
+        Some("uci") => return core::uci::run(),
+        Some("xboard") => return core::xboard::run(),
+        Some("play") => {
+            if let Err(e) = play_engine(&args[1..].join(" ")) {
+                eprintln!("{e}");
+            }
+            return
+        },
+        Some("clock") => {
+            if let Err(e) = play_timed(&args[1..].join(" ")) {
+                eprintln!("{e}");
+            }
+            return
+        },
+        Some("makebook") => {
+            if let Err(e) = make_book(&args[1..]) {
+                eprintln!("{e}");
+            }
+            return
+        },
+        Some("tune") => {
+            if let Err(e) = tune_eval(&args[1..]) {
+                eprintln!("{e}");
+            }
+            return
+        },
+        Some("match") => {
+            if let Err(e) = play_match(&args[1..]) {
+                eprintln!("{e}");
+            }
+            return
+        },
+        Some(arg) => return eprintln!("Unknown argument `{arg}`. Run without arguments for a two player game, with `clock` for a timed one, with `play` to play the engine, \
+            with `uci` or `xboard` for engine mode, with `makebook` to build an opening book, with `tune` to tune the evaluation, \
+            or with `match` to play engines against each other. Add `--lines` to read plain lines at a terminal"),
+        None => {},
+    }
+
     // let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
     // let bb = game.last_position().board.occupied_bb();
     // println!("{}\n", pp_bb(bb));
@@ -31,5 +86,208 @@ fn main() {
     // println!("{}", new_game.last_position().board.get_possible_moves_board(true, square));
 
     // let mut new_game = Game::new();
+    new_game.theme = load_theme();
     new_game.play_two_player();
 }
+
+/// The board theme saved with `theme save`, or the default one if it can't be read
+fn load_theme() -> Theme {
+    Theme::load(THEME_FILE).unwrap_or_else(|e| {
+        eprintln!("{e}, using the default theme");
+        Theme::default()
+    })
+}
+
+/// `play [white|black] [depth <plies>|time <seconds>]`, asking for anything left out
+fn play_engine(args: &str) -> Result<(), String> {
+    let args = args.to_lowercase();
+    let mut tokens = args.split_whitespace().peekable();
+    let (mut colour, mut limits) = (None, None);
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "white" | "w" => colour = Some(Colour::White),
+            "black" | "b" => colour = Some(Colour::Black),
+            "depth" | "time" => limits = Some(parse_strength(token, tokens.next().unwrap_or_default())?),
+            _ => return Err(format!("Unknown argument `{token}`")),
+        }
+    }
+
+    let colour = match colour {
+        Some(colour) => colour,
+        None => match repl::ask("Play as white or black? (w/b): ").as_deref() {
+            Some("w" | "white") => Colour::White,
+            Some("b" | "black") => Colour::Black,
+            _ => return Err("Expected `w` or `b`".to_string()),
+        },
+    };
+    let limits = match limits {
+        Some(limits) => limits,
+        None => {
+            let answer = repl::ask("Engine strength, as a search depth (i.e. `depth 6`) or seconds per move (i.e. `time 2`): ")
+                .unwrap_or_default();
+            let (kind, value) = answer.split_once(' ').unwrap_or((&answer, ""));
+            parse_strength(kind, value.trim())?
+        },
+    };
+
+    let mut game = Game::new();
+    game.theme = load_theme();
+    game.play_engine(colour, limits);
+    Ok(())
+}
+
+/// `clock <time control>`, a two player game on the clock, i.e. `clock 5+3` or `clock 40/90+30,30+30`
+fn play_timed(control: &str) -> Result<(), String> {
+    let control = clock::TimeControl::parse(control)?;
+    let mut game = Game::new();
+    game.clocks = Some(Clocks::new(control, Colour::White));
+    game.theme = load_theme();
+    game.play_two_player();
+    Ok(())
+}
+
+/// `makebook <games.pgn> <book.bin> [depth <plies>] [mingames <n>] [minscore <0-1>] [minelo <elo>]`
+fn make_book(args: &[String]) -> Result<(), String> {
+    let [pgn, out, rest @ ..] = args else {
+        return Err("Usage: makebook <games.pgn> <book.bin> [depth <plies>] [mingames <n>] [minscore <0-1>] [minelo <elo>]".to_string())
+    };
+    let mut options = BuildOptions::default();
+    for pair in rest.chunks(2) {
+        let [name, value] = pair else {
+            return Err(format!("Missing value for `{}`", pair[0]))
+        };
+        let invalid = || format!("Invalid value `{value}` for `{name}`");
+        match name.to_lowercase().as_str() {
+            "depth" => options.depth = value.parse().map_err(|_| invalid())?,
+            "mingames" => options.min_games = value.parse().map_err(|_| invalid())?,
+            "minscore" => options.min_score = value.parse::<f64>().ok().filter(|s| (0.0..=1.0).contains(s)).ok_or_else(invalid)?,
+            "minelo" => options.min_elo = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown argument `{name}`")),
+        }
+    }
+
+    // PGN files are often Latin-1 rather than UTF-8, which only matters for the tags
+    let bytes = std::fs::read(pgn).map_err(|e| format!("Could not read `{pgn}`: {e}"))?;
+    let mut builder = BookBuilder::new(options);
+    builder.add_pgn(&String::from_utf8_lossy(&bytes));
+    let book = builder.build();
+    book.save(out)?;
+    println!("Read {} games, skipped {}, wrote {} entries to `{out}`", builder.games, builder.skipped, book.len());
+    Ok(())
+}
+
+/// `tune <positions.epd> <out.rs> [epochs <n>] [rate <r>]`
+fn tune_eval(args: &[String]) -> Result<(), String> {
+    let [dataset, out, rest @ ..] = args else {
+        return Err("Usage: tune <positions.epd> <out.rs> [epochs <n>] [rate <r>]".to_string())
+    };
+    let (mut epochs, mut rate) = (1000, 1.0);
+    for pair in rest.chunks(2) {
+        let [name, value] = pair else {
+            return Err(format!("Missing value for `{}`", pair[0]))
+        };
+        let invalid = || format!("Invalid value `{value}` for `{name}`");
+        match name.to_lowercase().as_str() {
+            "epochs" => epochs = value.parse().map_err(|_| invalid())?,
+            "rate" => rate = value.parse::<f64>().ok().filter(|r| *r > 0.0).ok_or_else(invalid)?,
+            _ => return Err(format!("Unknown argument `{name}`")),
+        }
+    }
+
+    let text = std::fs::read_to_string(dataset).map_err(|e| format!("Could not read `{dataset}`: {e}"))?;
+    let (samples, skipped) = tune::parse_dataset(&text);
+    if samples.is_empty() {
+        return Err(format!("No labelled positions in `{dataset}`"))
+    }
+    println!("Read {} positions, skipped {skipped}", samples.len());
+
+    let mut tuner = Tuner::new(&samples, &DEFAULT_PARAMS);
+    tuner.fit_k();
+    println!("K = {:.4}, loss {:.6}", tuner.k, tuner.loss());
+    for epoch in 1..=epochs {
+        let loss = tuner.step(rate);
+        if epoch % 50 == 0 || epoch == epochs {
+            println!("Epoch {epoch:>5}  loss {loss:.6}");
+        }
+    }
+
+    std::fs::write(out, tuner.params().to_rust()).map_err(|e| format!("Could not write `{out}`: {e}"))?;
+    println!("Loss {:.6}, wrote the tuned weights to `{out}`", tuner.loss());
+    Ok(())
+}
+
+const MATCH_USAGE: &str = "Usage: match <engine> <engine> [games <n>] [tc <base>+<inc>|movetime <secs>|depth <plies>|nodes <n>] \
+    [openings <file>] [pgn <out.pgn>] [elo0 <elo>] [elo1 <elo>] [alpha <a>] [beta <b>] [resign <cp>] [draw <cp>] [maxmoves <n>]\n\
+    where an engine is `self[,Name=Value...]` or the command line of a UCI engine, i.e. `./other uci,Hash=64`";
+
+/// `match <engine> <engine> [games <n>] [tc|movetime|depth|nodes <limit>] [openings <file>] [pgn <file>] ...`
+fn play_match(args: &[String]) -> Result<(), String> {
+    let [first, second, rest @ ..] = args else {
+        return Err(MATCH_USAGE.to_string())
+    };
+    let mut options = MatchOptions::default();
+    for pair in rest.chunks(2) {
+        let [name, value] = pair else {
+            return Err(format!("Missing value for `{}`", pair[0]))
+        };
+        let invalid = || format!("Invalid value `{value}` for `{name}`");
+        let probability = |value: &str| value.parse::<f64>().ok().filter(|p| *p > 0.0 && *p < 0.5).ok_or_else(invalid);
+        let adjudication = &mut options.adjudication;
+        match name.to_lowercase().as_str() {
+            "games" => options.games = value.parse().map_err(|_| invalid())?,
+            kind @ ("tc" | "movetime" | "depth" | "nodes") => options.tc = TimeControl::parse(kind, value)?,
+            "openings" => options.openings = tournament::load_openings(value)?,
+            "pgn" => options.pgn = Some(value.clone()),
+            "elo0" => options.sprt.elo0 = value.parse().map_err(|_| invalid())?,
+            "elo1" => options.sprt.elo1 = value.parse().map_err(|_| invalid())?,
+            "alpha" => options.sprt.alpha = probability(value)?,
+            "beta" => options.sprt.beta = probability(value)?,
+            "resign" => adjudication.resign_score = value.parse().map_err(|_| invalid())?,
+            "draw" => adjudication.draw_score = value.parse().map_err(|_| invalid())?,
+            "maxmoves" => adjudication.max_moves = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("Unknown argument `{name}`")),
+        }
+    }
+    if options.sprt.elo1 <= options.sprt.elo0 {
+        return Err("`elo1` must be greater than `elo0`".to_string())
+    }
+
+    let mut contestants = [tournament::launch(first)?, tournament::launch(second)?];
+    println!("{} vs {}, {} games", contestants[0].name(), contestants[1].name(), options.games);
+    let sprt = options.sprt;
+    let (lower, upper) = sprt.bounds();
+    let score = tournament::run(&mut contestants, &options, |outcome| {
+        let (elo, error) = outcome.score.elo();
+        let score = outcome.score;
+        println!(
+            "Game {:>4}: {} - {} {} ({})  Score {}-{}-{}  Elo {elo:+.1} ± {error:.1}  LLR {:.2} ({lower:.2}, {upper:.2})",
+            outcome.round, outcome.white, outcome.black, outcome.ending.result, outcome.ending.reason,
+            score.wins, score.losses, score.draws, sprt.llr(&score),
+        );
+    })?;
+
+    let (elo, error) = score.elo();
+    println!("Score of {} vs {}: {}-{}-{} [{:.3}]", contestants[0].name(), contestants[1].name(), score.wins, score.losses, score.draws, score.mean());
+    println!("Elo difference {elo:+.1} ± {error:.1}");
+    match sprt.decide(&score) {
+        Decision::AcceptH1 => println!("SPRT: H1 accepted, {} is at least {} Elo stronger", contestants[0].name(), sprt.elo1),
+        Decision::AcceptH0 => println!("SPRT: H0 accepted, {} is at most {} Elo stronger", contestants[0].name(), sprt.elo0),
+        Decision::Continue => println!("SPRT: undecided, LLR {:.2} ({lower:.2}, {upper:.2})", sprt.llr(&score)),
+    }
+    Ok(())
+}
+
+fn parse_strength(kind: &str, value: &str) -> Result<Limits, String> {
+    match kind {
+        "depth" => match value.parse::<u8>() {
+            Ok(depth @ 1..) => Ok(Limits { depth: Some(depth), ..Default::default() }),
+            _ => Err(format!("Expected a depth of at least 1, got `{value}`")),
+        },
+        "time" => match value.parse::<f64>() {
+            Ok(secs) if secs > 0.0 => Ok(Limits { movetime: Some(Duration::from_secs_f64(secs)), ..Default::default() }),
+            _ => Err(format!("Expected a positive number of seconds, got `{value}`")),
+        },
+        _ => Err(format!("Expected `depth` or `time`, got `{kind}`")),
+    }
+}
@@ -25,7 +25,7 @@ impl Display for Piece {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PromotablePiece {
     Knight,
     Bishop,
@@ -33,13 +33,33 @@ pub enum PromotablePiece {
     Queen
 }
 
+impl From<PromotablePiece> for Piece {
+    fn from(value: PromotablePiece) -> Self {
+        match value {
+            PromotablePiece::Knight => Piece::Knight,
+            PromotablePiece::Bishop => Piece::Bishop,
+            PromotablePiece::Rook   => Piece::Rook,
+            PromotablePiece::Queen  => Piece::Queen,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Colour {
     White,
     Black
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Colour {
+    pub fn opposite(&self) -> Self {
+        match self {
+            Colour::White => Colour::Black,
+            Colour::Black => Colour::White,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Movement {
     pub player: Colour,
     pub piece: Piece,
@@ -53,7 +73,7 @@ impl Movement {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Represents a ply or turn
 pub struct Ply {
     pub mov: Movement,
@@ -67,7 +87,15 @@ impl Ply {
         Self { mov: Movement::new(player, piece, from_sq, to_sq), is_capture, promotion: is_promotion }
     }
 
-    pub fn from_move(mov: Movement, is_capture: bool, promotion: Option<PromotablePiece>) -> Self {
-        Self { mov, is_capture, promotion }
+    /// Long algebraic notation as used by UCI, i.e. `e2e4` or `e7e8q`
+    pub fn to_uci(self) -> String {
+        let promotion = match self.promotion {
+            Some(PromotablePiece::Knight) => "n",
+            Some(PromotablePiece::Bishop) => "b",
+            Some(PromotablePiece::Rook)   => "r",
+            Some(PromotablePiece::Queen)  => "q",
+            None => "",
+        };
+        format!("{}{}{}", self.mov.from_sq, self.mov.to_sq, promotion)
     }
 }
@@ -1,8 +1,13 @@
 use crate :: { 
-    board :: *, core::{Square, RANK_1, RANK_8}, 
-    ply   :: { Colour, Movement, Piece, Ply }
+    board :: *, 
+    core  :: { self, bits, zobrist, Square, FILE_A, FILE_B, FILE_C, FILE_D, FILE_F, FILE_G, RANK_1, RANK_8 }, 
+    ply   :: { Colour, Movement, Piece, Ply, PromotablePiece }
 };
 
+const PROMOTIONS: [PromotablePiece; 4] = [
+    PromotablePiece::Queen, PromotablePiece::Knight, PromotablePiece::Rook, PromotablePiece::Bishop
+];
+
 #[derive(Debug, Default, Copy, Clone)]
 pub struct Position {
     pub board: Board,
@@ -14,7 +19,8 @@ pub struct Position {
     pub ply_clock: u8,                  // For 50 move rule
     pub en_passant_targ: Option<u64>,   // is None if last ply was not a double push
     pub check: Option<Colour>,          // is None if neither king is in check
-    pub last_ply: Option<Ply>           // is None if start of a game or if unknowable from FEN input
+    pub last_ply: Option<Ply>,          // is None if start of a game or if unknowable from FEN input
+    pub hash: u64,                      // Zobrist hash, used for repetitions and the transposition table
 }
 
 impl Position {
@@ -83,4 +89,302 @@ impl Position {
             Colour::Black => self.board.white_bb() & ply.to_sq.as_bb() != 0
         }
     }
+
+    pub fn side_to_move(&self) -> Colour {
+        match self.was_blacks_move {
+            true  => Colour::White,
+            false => Colour::Black,
+        }
+    }
+
+    /// Whether the side to move has any piece other than pawns and its king
+    pub fn has_non_pawn_material(&self, colour: Colour) -> bool {
+        let b = &self.board;
+          b.pieces(Piece::Knight, colour)
+        | b.pieces(Piece::Bishop, colour)
+        | b.pieces(Piece::Rook,   colour)
+        | b.pieces(Piece::Queen,  colour) != 0
+    }
+
+    /// Neither side can possibly deliver mate (KvK, KNvK, KBvK)
+    pub fn is_insufficient_material(&self) -> bool {
+        let b = &self.board;
+        let heavies = b.w_p_bb | b.b_p_bb | b.w_r_bb | b.b_r_bb | b.w_q_bb | b.b_q_bb;
+        let minors = b.w_n_bb | b.b_n_bb | b.w_b_bb | b.b_b_bb;
+
+        heavies == 0 && minors.count_ones() <= 1
+    }
+
+    /// En passant only affects the hash if the capture is actually available to the side to move.
+    /// This way transpositions that differ only by an unusable target square are hashed identically
+    fn en_passant_hash(&self) -> u64 {
+        match self.en_passant_targ {
+            Some(ep) if core::pawn_attacks_bb(ep, self.side_to_move() != Colour::White) 
+                      & self.board.pieces(Piece::Pawn, self.side_to_move()) != 0 => {
+                zobrist::en_passant_key(Square::from_bb(ep) as usize % 8)
+            },
+            _ => 0,
+        }
+    }
+
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for colour in [Colour::White, Colour::Black] {
+            for piece in [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King] {
+                for sq in bits(self.board.pieces(piece, colour)) {
+                    hash ^= zobrist::piece_key(piece, colour, Square::from_bb(sq) as usize);
+                }
+            }
+        }
+        hash ^= zobrist::castling_key(self.castling);
+        hash ^= self.en_passant_hash();
+        if self.side_to_move() == Colour::Black {
+            hash ^= zobrist::side_key();
+        }
+
+        hash
+    }
+
+    /// Generates all moves of the side to move, including those that would leave their king in check
+    pub fn pseudo_legal_plies(&self) -> Vec<Ply> {
+        let mut plies = Vec::with_capacity(48);
+        let player = self.side_to_move();
+        let board = &self.board;
+        let own = board.colour_bb(player);
+        let enemies = board.colour_bb(player.opposite());
+        let empties = board.unoccupied_bb();
+        let last_rank = if player == Colour::White { RANK_8 } else { RANK_1 };
+
+        let mut push = |piece: Piece, from: u64, targets: u64| {
+            for to in bits(targets) {
+                let is_capture = to & enemies != 0 || (piece == Piece::Pawn && Some(to) == self.en_passant_targ);
+                let (from_sq, to_sq) = (Square::from_bb(from), Square::from_bb(to));
+                if piece == Piece::Pawn && to & last_rank != 0 {
+                    for promotion in PROMOTIONS {
+                        plies.push(Ply::new(player, piece, from_sq, to_sq, is_capture, Some(promotion)));
+                    }
+                } else {
+                    plies.push(Ply::new(player, piece, from_sq, to_sq, is_capture, None));
+                }
+            }
+        };
+
+        let is_white = player == Colour::White;
+        let ep = self.en_passant_targ.unwrap_or(0);
+        for from in bits(board.pieces(Piece::Pawn, player)) {
+            let pushes = board.pawn_single_push(from, player) | board.pawn_double_push(from, player);
+            let captures = core::pawn_attacks_bb(from, is_white) & (enemies | ep);
+            push(Piece::Pawn, from, pushes | captures);
+        }
+        for from in bits(board.pieces(Piece::Knight, player)) {
+            push(Piece::Knight, from, core::knight_moves_bb(from) & !own);
+        }
+        for from in bits(board.pieces(Piece::Bishop, player)) {
+            push(Piece::Bishop, from, core::bish_moves_bb(from, empties) & !own);
+        }
+        for from in bits(board.pieces(Piece::Rook, player)) {
+            push(Piece::Rook, from, core::rook_moves_bb(from, empties) & !own);
+        }
+        for from in bits(board.pieces(Piece::Queen, player)) {
+            push(Piece::Queen, from, (core::rook_moves_bb(from, empties) | core::bish_moves_bb(from, empties)) & !own);
+        }
+        for from in bits(board.pieces(Piece::King, player)) {
+            push(Piece::King, from, core::king_moves_bb(from) & !own);
+        }
+
+        // Castling
+        let back_rank = if is_white { RANK_1 } else { RANK_8 };
+        let king = board.pieces(Piece::King, player) & back_rank & core::FILE_E;
+        let rooks = board.pieces(Piece::Rook, player);
+        let enemy = player.opposite();
+        let safe = |mask: u64| bits(mask).all(|sq| !board.square_is_attacked_by(sq, enemy));
+
+        if king != 0 && self.colour_can_short_castle(player) {
+            let (between, rook) = ((FILE_F | FILE_G) & back_rank, core::FILE_H & back_rank);
+            if between & !empties == 0 && rooks & rook != 0 && safe(king | between) {
+                let to = Square::from_bb(FILE_G & back_rank);
+                plies.push(Ply::new(player, Piece::King, Square::from_bb(king), to, false, None));
+            }
+        }
+        if king != 0 && self.colour_can_long_castle(player) {
+            let (between, rook) = ((FILE_B | FILE_C | FILE_D) & back_rank, FILE_A & back_rank);
+            let path = (FILE_C | FILE_D) & back_rank;
+            if between & !empties == 0 && rooks & rook != 0 && safe(king | path) {
+                let to = Square::from_bb(FILE_C & back_rank);
+                plies.push(Ply::new(player, Piece::King, Square::from_bb(king), to, false, None));
+            }
+        }
+
+        plies
+    }
+
+    /// Generates all legal moves of the side to move
+    pub fn legal_plies(&self) -> Vec<Ply> {
+        let player = self.side_to_move();
+        self.pseudo_legal_plies()
+            .into_iter()
+            .filter(|&ply| !self.make_ply(ply).board.king_is_in_check(player))
+            .collect()
+    }
+
+    pub fn is_legal(&self, ply: Ply) -> bool {
+        self.legal_plies().contains(&ply)
+    }
+
+    /// Finds the legal ply matching a move in long algebraic notation, i.e. `e2e4` or `e7e8q`
+    pub fn parse_uci(&self, inp: &str) -> Option<Ply> {
+        let inp = inp.to_lowercase();
+        self.legal_plies().into_iter().find(|ply| ply.to_uci() == inp)
+    }
+
+    /// Returns the position after the given ply has been played.
+    /// NOTE: Assumes the ply is at least pseudo-legal
+    pub fn make_ply(&self, ply: Ply) -> Position {
+        let mut pos = *self;
+        let Movement { player, piece, from_sq, to_sq } = ply.mov;
+        let (from, to) = (from_sq.to_bb(), to_sq.to_bb());
+        let enemy = player.opposite();
+        let mut hash = self.hash ^ self.en_passant_hash() ^ zobrist::castling_key(self.castling);
+        let mut is_capture = false;
+
+        // Remove captured piece
+        if let Some((captured, colour)) = self.board.piece_at(to) {
+            debug_assert_eq!(colour, enemy);
+            pos.board.toggle_piece(captured, enemy, to);
+            hash ^= zobrist::piece_key(captured, enemy, to_sq as usize);
+            is_capture = true;
+        } else if piece == Piece::Pawn && Some(to) == self.en_passant_targ {
+            let victim = if player == Colour::White { to >> 8 } else { to << 8 };
+            pos.board.toggle_piece(Piece::Pawn, enemy, victim);
+            hash ^= zobrist::piece_key(Piece::Pawn, enemy, Square::from_bb(victim) as usize);
+            is_capture = true;
+        }
+
+        // Move piece
+        pos.board.toggle_piece(piece, player, from);
+        hash ^= zobrist::piece_key(piece, player, from_sq as usize);
+        let landed = ply.promotion.map_or(piece, Piece::from);
+        pos.board.toggle_piece(landed, player, to);
+        hash ^= zobrist::piece_key(landed, player, to_sq as usize);
+
+        // Move rook if castling
+        if piece == Piece::King && (from_sq as i8 - to_sq as i8).abs() == 2 {
+            let (rook_from, rook_to) = if to & FILE_G != 0 { (to << 1, to >> 1) } else { (to >> 2, to << 1) };
+            pos.board.toggle_piece(Piece::Rook, player, rook_from | rook_to);
+            hash ^= zobrist::piece_key(Piece::Rook, player, Square::from_bb(rook_from) as usize);
+            hash ^= zobrist::piece_key(Piece::Rook, player, Square::from_bb(rook_to) as usize);
+        }
+
+        // Update castling rights, moving to or from a king or rook's home square forfeits the right
+        for sq in [from_sq, to_sq] {
+            pos.castling &= match sq {
+                Square::A1 => !(1 << 6),
+                Square::E1 => !(1 << 7 | 1 << 6),
+                Square::H1 => !(1 << 7),
+                Square::A8 => !(1 << 4),
+                Square::E8 => !(1 << 5 | 1 << 4),
+                Square::H8 => !(1 << 5),
+                _ => !0,
+            };
+        }
+
+        // Update en passant target
+        pos.en_passant_targ = match piece {
+            Piece::Pawn if to == from << 16 => Some(from << 8),
+            Piece::Pawn if to == from >> 16 => Some(from >> 8),
+            _ => None,
+        };
+
+        // Update ply clock
+        pos.ply_clock = if piece == Piece::Pawn || is_capture { 0 } else { self.ply_clock.saturating_add(1) };
+
+        pos.was_blacks_move = player == Colour::Black;
+        pos.check = if pos.board.king_is_in_check(enemy) { Some(enemy) } else { None };
+        pos.last_ply = Some(Ply { is_capture, ..ply });
+
+        hash ^= zobrist::castling_key(pos.castling) ^ pos.en_passant_hash() ^ zobrist::side_key();
+        pos.hash = hash;
+        debug_assert_eq!(pos.hash, pos.compute_hash());
+
+        pos
+    }
+
+    /// Passes the turn without moving. Only used by the search
+    pub fn make_null(&self) -> Position {
+        let mut pos = *self;
+        pos.en_passant_targ = None;
+        pos.was_blacks_move = !self.was_blacks_move;
+        pos.ply_clock = self.ply_clock.saturating_add(1);
+        pos.last_ply = None;
+        pos.hash = self.hash ^ self.en_passant_hash() ^ zobrist::side_key();
+
+        pos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+
+    fn perft(pos: &Position, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1
+        }
+        pos.legal_plies().iter().map(|&ply| perft(&pos.make_ply(ply), depth - 1)).sum()
+    }
+
+    fn perft_fen(fen: &str, depth: u8) -> u64 {
+        let game = Game::try_from_fen(fen).unwrap();
+        perft(game.last_position(), depth)
+    }
+
+    #[test]
+    fn perft_initial() {
+        assert_eq!(20,   perft_fen(core::INITIAL_FEN, 1));
+        assert_eq!(400,  perft_fen(core::INITIAL_FEN, 2));
+        assert_eq!(8902, perft_fen(core::INITIAL_FEN, 3));
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        // Castling, en passant, promotions and pins
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(48,   perft_fen(fen, 1));
+        assert_eq!(2039, perft_fen(fen, 2));
+    }
+
+    #[test]
+    fn perft_endgame() {
+        // En passant discovered checks along the rank
+        let fen = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        assert_eq!(14,   perft_fen(fen, 1));
+        assert_eq!(191,  perft_fen(fen, 2));
+        assert_eq!(2812, perft_fen(fen, 3));
+    }
+
+    #[test]
+    fn perft_promotions() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(6,   perft_fen(fen, 1));
+        assert_eq!(264, perft_fen(fen, 2));
+    }
+
+    #[test]
+    fn hash_transposition() {
+        let game = Game::new();
+        let pos = game.last_position();
+        let find = |pos: &Position, from: Square, to: Square| {
+            *pos.legal_plies().iter().find(|p| p.mov.from_sq == from && p.mov.to_sq == to).unwrap()
+        };
+        let a = pos.make_ply(find(pos, Square::G1, Square::F3));
+        let a = a.make_ply(find(&a, Square::G8, Square::F6));
+        let a = a.make_ply(find(&a, Square::B1, Square::C3));
+        let b = pos.make_ply(find(pos, Square::B1, Square::C3));
+        let b = b.make_ply(find(&b, Square::G8, Square::F6));
+        let b = b.make_ply(find(&b, Square::G1, Square::F3));
+        assert_eq!(a.hash, b.hash);
+        assert_ne!(a.hash, pos.hash);
+    }
 }
@@ -0,0 +1,275 @@
+use crate    :: {
+    core     :: parsers::pgn,
+    game     :: Game,
+    ply      :: { Colour, Ply },
+    position :: Position,
+};
+
+/// Index of a node in its [`MoveTree`]
+pub type NodeId = usize;
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// The ply leading here, `None` at the root
+    pub ply: Option<Ply>,
+    pub position: Position,
+    /// Full move number of the position, as in FEN
+    pub mov: u16,
+    pub parent: Option<NodeId>,
+    /// Continuations from here, the main line first
+    pub children: Vec<NodeId>,
+    /// Comment written before the ply, such as at the start of a variation
+    pub before: Option<String>,
+    /// Comment written after the ply, or on the whole game at the root
+    pub comment: Option<String>,
+    /// Numeric annotation glyphs, i.e. 1 for `!` and 2 for `?`
+    pub nags: Vec<u8>,
+}
+
+/// A game with its variations. The cursor marks the current node, which moves are played from.
+/// Deleted nodes stay in the arena, unreachable, so ids remain valid for the rest
+#[derive(Debug, Clone)]
+pub struct MoveTree {
+    nodes: Vec<Node>,
+    cursor: NodeId,
+    pub tags: Vec<(String, String)>,
+    pub result: Option<String>,
+}
+
+impl Default for MoveTree {
+    fn default() -> Self {
+        Self::new(Position::new(), 1)
+    }
+}
+
+impl MoveTree {
+    pub const ROOT: NodeId = 0;
+
+    /// An empty tree starting from `position` on move `mov`
+    pub fn new(position: Position, mov: u16) -> Self {
+        let root = Node { ply: None, position, mov, parent: None, children: Vec::new(), before: None, comment: None, nags: Vec::new() };
+        Self { nodes: vec![root], cursor: Self::ROOT, tags: Vec::new(), result: None }
+    }
+
+    /// The game from the root up to the cursor
+    pub fn to_game(&self) -> Game {
+        let mut game = Game::try_from_fen(&self.fen(Self::ROOT)).expect("FEN is written correctly");
+        for id in self.path(self.cursor) {
+            game.make_ply(self.nodes[id].ply.expect("only the root has no ply"));
+        }
+        game
+    }
+
+    /// FEN of a node's position
+    pub fn fen(&self, id: NodeId) -> String {
+        let node = &self.nodes[id];
+        let game = Game { history: vec![node.position], mov: node.mov, ..Default::default() };
+        game.as_fen()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn cursor(&self) -> NodeId {
+        self.cursor
+    }
+
+    pub fn current(&self) -> &Node {
+        &self.nodes[self.cursor]
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.current().position
+    }
+
+    /// Nodes from the root's first move to `id`
+    pub fn path(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        let mut at = id;
+        while let Some(parent) = self.nodes[at].parent {
+            path.push(at);
+            at = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Nodes of the main continuation after `id`, to the end of its line
+    pub fn mainline(&self, id: NodeId) -> Vec<NodeId> {
+        let mut line = Vec::new();
+        let mut at = id;
+        while let Some(&next) = self.nodes[at].children.first() {
+            line.push(next);
+            at = next;
+        }
+        line
+    }
+
+    /// Plays a legal ply from the cursor and moves onto it. A ply that's already a continuation is
+    /// followed, otherwise it's added as the main line if there is none, else as a variation
+    pub fn play(&mut self, ply: Ply) -> NodeId {
+        let parent = &self.nodes[self.cursor];
+        if let Some(&existing) = parent.children.iter().find(|&&c| self.nodes[c].ply == Some(ply)) {
+            self.cursor = existing;
+            return existing
+        }
+        let mov = match parent.position.side_to_move() {
+            Colour::White => parent.mov,
+            Colour::Black => parent.mov + 1,
+        };
+        let node = Node {
+            ply: Some(ply),
+            position: parent.position.make_ply(ply),
+            mov,
+            parent: Some(self.cursor),
+            children: Vec::new(),
+            before: None,
+            comment: None,
+            nags: Vec::new(),
+        };
+        let id = self.nodes.len();
+        self.nodes.push(node);
+        self.nodes[self.cursor].children.push(id);
+        self.cursor = id;
+        id
+    }
+
+    /// Moves the cursor to its parent. Returns false at the root
+    pub fn back(&mut self) -> bool {
+        match self.current().parent {
+            Some(parent) => {
+                self.cursor = parent;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Moves the cursor along the main continuation. Returns false at the end of a line
+    pub fn forward(&mut self) -> bool {
+        self.enter(0)
+    }
+
+    /// Moves the cursor onto a continuation, 0 being the main line. Returns false if there is no such one
+    pub fn enter(&mut self, variation: usize) -> bool {
+        match self.current().children.get(variation) {
+            Some(&child) => {
+                self.cursor = child;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn go_to_start(&mut self) {
+        self.cursor = Self::ROOT;
+    }
+
+    /// Follows the main continuation to the end of the current line
+    pub fn go_to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    pub fn go_to(&mut self, id: NodeId) {
+        assert!(id < self.nodes.len(), "node {id} is in the tree");
+        self.cursor = id;
+    }
+
+    /// Makes the line through `id` the main line, at every branch back to the root
+    pub fn promote(&mut self, id: NodeId) {
+        let mut at = id;
+        while let Some(parent) = self.nodes[at].parent {
+            let children = &mut self.nodes[parent].children;
+            if let Some(i) = children.iter().position(|&c| c == at) {
+                children[..=i].rotate_right(1);
+            }
+            at = parent;
+        }
+    }
+
+    /// Removes `id` and everything after it, moving the cursor to its parent if it was on the branch.
+    /// Returns false for the root, which can't be deleted
+    pub fn delete(&mut self, id: NodeId) -> bool {
+        let Some(parent) = self.nodes[id].parent else { return false };
+        if self.path(self.cursor).contains(&id) {
+            self.cursor = parent;
+        }
+        self.nodes[parent].children.retain(|&c| c != id);
+        true
+    }
+
+    /// Reads every game of a PGN file, with comments, NAGs and variations
+    pub fn from_pgn(text: &str) -> Result<Vec<Self>, String> {
+        pgn::parse_trees(text)
+    }
+
+    pub fn to_pgn(&self) -> String {
+        pgn::write_tree(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn play(tree: &mut MoveTree, moves: &[&str]) {
+        for mov in moves {
+            let ply = tree.position().parse_uci(mov).unwrap();
+            tree.play(ply);
+        }
+    }
+
+    fn line(tree: &MoveTree) -> Vec<String> {
+        tree.mainline(MoveTree::ROOT).iter().map(|&id| tree.node(id).ply.unwrap().to_uci()).collect()
+    }
+
+    #[test]
+    fn branching() {
+        let mut tree = Game::new().tree;
+        play(&mut tree, &["e2e4", "e7e5", "g1f3"]);
+        tree.back();
+        tree.back();
+        play(&mut tree, &["c7c5", "g1f3"]);
+        assert_eq!(line(&tree), ["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(tree.current().mov, 2);
+
+        // Playing an existing move follows it
+        let nodes = tree.nodes.len();
+        tree.go_to_start();
+        play(&mut tree, &["e2e4", "c7c5"]);
+        assert_eq!(tree.nodes.len(), nodes);
+        let sicilian = tree.cursor();
+
+        tree.promote(sicilian);
+        assert_eq!(line(&tree), ["e2e4", "c7c5", "g1f3"]);
+        tree.go_to_start();
+        tree.go_to_end();
+        assert_eq!(tree.to_game().as_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        // Deleting the branch the cursor is on moves it back
+        assert!(tree.delete(sicilian));
+        assert_eq!(line(&tree), ["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(tree.cursor(), tree.node(sicilian).parent.unwrap());
+        assert!(!tree.delete(MoveTree::ROOT));
+        assert!(tree.enter(0) && !tree.enter(1));
+    }
+
+    #[test]
+    fn from_a_game() {
+        let mut game = Game::try_from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 30").unwrap();
+        for mov in ["e8d7", "e2e4", "d7c6"] {
+            let ply = game.last_position().parse_uci(mov).unwrap();
+            game.make_ply(ply);
+        }
+        let tree = &game.tree;
+        assert_eq!(tree.node(MoveTree::ROOT).mov, 30);
+        assert_eq!(tree.fen(MoveTree::ROOT), "4k3/8/8/8/8/8/4P3/4K3 b - - 0 30");
+        assert_eq!(line(tree), ["e8d7", "e2e4", "d7c6"]);
+        assert_eq!(tree.to_game().as_fen(), game.as_fen());
+    }
+}